@@ -0,0 +1,88 @@
+//! An owned handle to an audio file opened through a backend.
+
+use backend::{AudioBackend, DefaultBackend};
+use channel_layout::ChannelLayout;
+use core_audio;
+use error::{AudioFileError, Error};
+use std::path::Path;
+use std::time::Duration;
+
+/// An open audio file. The backend's handle is closed when this is dropped, so anything that
+/// schedules the file (e.g. a file player unit) borrows it rather than copying the handle.
+pub struct AudioFile<B: AudioBackend = DefaultBackend> {
+    file: B::File,
+}
+
+impl<B: AudioBackend> AudioFile<B> {
+
+    /// opens the file at `path` for reading
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<AudioFile<B>, Error> {
+        let file = try!(B::open_audio_file(path.as_ref()));
+        Ok(AudioFile { file })
+    }
+
+    /// The backend's handle. Only valid for as long as `self` is alive.
+    pub fn raw(&self) -> &B::File {
+        &self.file
+    }
+
+    /// kAudioFilePropertyDataFormat
+    pub fn data_format(&self) -> Result<core_audio::AudioStreamBasicDescription, Error> {
+        B::get_data_format(&self.file)
+    }
+
+    /// kAudioFilePropertyAudioDataPacketCount
+    pub fn audio_data_packet_count(&self) -> Result<u64, Error> {
+        B::audio_file_get_audio_data_packet_count(&self.file)
+    }
+
+    /// The number of frames of audio in the file, not counting any encoder priming or padding.
+    pub fn frame_count(&self) -> Result<u64, Error> {
+        B::audio_file_get_frame_count(&self.file)
+    }
+
+    /// Which speaker each channel is for, if the file says. Where it doesn't, the channels are
+    /// taken to be in the order `ChannelLayout::for_channels` gives.
+    pub fn channel_layout(&self) -> Result<Option<ChannelLayout>, Error> {
+        B::audio_file_get_channel_layout(&self.file)
+    }
+
+    /// How long the file plays for at its own sample rate.
+    pub fn duration(&self) -> Result<Duration, Error> {
+        let frame_count = try!(self.frame_count());
+        let file_format = try!(self.data_format());
+        if file_format.mSampleRate.is_nan() || file_format.mSampleRate <= 0.0 {
+            return Err(Error::AudioFile(AudioFileError::InvalidFile));
+        }
+        Ok(frames_to_duration(frame_count, file_format.mSampleRate))
+    }
+}
+
+/// How long `frames` frames last at `sample_rate`.
+pub fn frames_to_duration(frames: u64, sample_rate: f64) -> Duration {
+    let seconds = (frames as f64 / sample_rate).floor();
+    let nanos = ((frames as f64 - seconds * sample_rate) / sample_rate * 1e9).round();
+    Duration::new(seconds as u64, nanos.min(999_999_999.0) as u32)
+}
+
+/// The frame `duration` into a file at `sample_rate`, rounded to the nearest.
+pub fn duration_to_frames(duration: Duration, sample_rate: f64) -> u64 {
+    let seconds = duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9;
+    (seconds * sample_rate).round() as u64
+}
+
+#[cfg(target_os = "macos")]
+impl AudioFile<::backend::CoreAudioBackend> {
+
+    /// The raw `AudioFileID`. Only valid for as long as `self` is alive.
+    pub fn id(&self) -> core_audio::AudioFileID {
+        self.file
+    }
+}
+
+impl<B: AudioBackend> Drop for AudioFile<B> {
+    fn drop(&mut self) {
+        // nothing sensible to do with a failure here, the handle is gone either way
+        let _ = B::close_audio_file(&mut self.file);
+    }
+}
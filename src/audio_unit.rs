@@ -0,0 +1,103 @@
+//! An audio unit with typed properties, and parameters.
+
+use backend::{AudioBackend, DefaultBackend, ParameterEvent, PortableBackend};
+use channel_matrix::ChannelMatrix;
+use core_audio;
+use error::Error;
+use pcm::Dither;
+use property::Property;
+use std::io::Write;
+use std::marker::PhantomData;
+use stream_format::StreamFormat;
+
+/// One of a graph's audio units, see `Graph::audio_unit`. Like the backend's unit it wraps, it's
+/// only valid for as long as the graph it came from, so it borrows that graph, and the backend's
+/// unit itself is kept inside the crate.
+pub struct AudioUnit<'g, B: AudioBackend = DefaultBackend> {
+    unit: B::Unit,
+    _graph: PhantomData<&'g ()>,
+}
+
+impl<'g, B: AudioBackend> AudioUnit<'g, B> {
+
+    pub(crate) fn new(unit: B::Unit) -> AudioUnit<'g, B> {
+        AudioUnit { unit, _graph: PhantomData }
+    }
+
+    /// Property `P` of `element` in `scope`, e.g.
+    /// `unit.get::<property::StreamFormat>(kAudioUnitScope_Output, 0)`.
+    pub fn get<P: Property>(&self, scope: core_audio::AudioUnitScope,
+                            element: core_audio::AudioUnitElement) -> Result<P::Value, Error> {
+        B::get_property::<P>(&self.unit, scope, element)
+    }
+
+    /// Sets property `P` of `element` in `scope`.
+    pub fn set<P: Property>(&self, scope: core_audio::AudioUnitScope, element: core_audio::AudioUnitElement,
+                            value: &P::Value) -> Result<(), Error> {
+        B::set_property::<P>(&self.unit, scope, element, value)
+    }
+
+    /// Changes the channel count of the stream format of `element` in `scope`, keeping everything
+    /// else.
+    pub fn set_number_of_channels(&self, scope: core_audio::AudioUnitScope, element: core_audio::AudioUnitElement,
+                                  channels: u32) -> Result<(), Error> {
+        B::set_number_of_channels(&self.unit, scope, element, channels)
+    }
+
+    /// Changes the sample rate of the stream format of `element` in `scope`, keeping everything
+    /// else.
+    pub fn set_sample_rate(&self, scope: core_audio::AudioUnitScope, element: core_audio::AudioUnitElement,
+                           sample_rate: f64) -> Result<(), Error> {
+        B::set_sample_rate(&self.unit, scope, element, sample_rate)
+    }
+
+    /// The current value of a parameter.
+    pub fn get_parameter(&self, parameter: core_audio::AudioUnitParameterID, scope: core_audio::AudioUnitScope,
+                         element: core_audio::AudioUnitElement) -> Result<f32, Error> {
+        B::audio_unit_get_parameter(&self.unit, parameter, scope, element)
+    }
+
+    /// Changes a parameter straight away.
+    pub fn set_parameter(&self, parameter: core_audio::AudioUnitParameterID, scope: core_audio::AudioUnitScope,
+                         element: core_audio::AudioUnitElement, value: f32) -> Result<(), Error> {
+        B::audio_unit_set_parameter(&self.unit, parameter, scope, element, value, 0)
+    }
+
+    /// Schedules jumps and ramps for the next render cycle, see `ParameterChange`. Ramps longer
+    /// than a cycle have to be scheduled again for each cycle they carry on into.
+    pub fn schedule_parameters(&self, events: &[ParameterEvent]) -> Result<(), Error> {
+        B::audio_unit_schedule_parameters(&self.unit, events)
+    }
+}
+
+/// The portable backend's extras, see `PortableUnit`.
+impl<'g> AudioUnit<'g, PortableBackend> {
+
+    /// Everything a null output has rendered so far, interleaved.
+    pub fn rendered_samples(&self) -> Result<Vec<f32>, Error> {
+        self.unit.rendered_samples()
+    }
+
+    /// Has an output mix its input into the device's channels with `matrix`, see
+    /// `PortableUnit::set_channel_matrix`.
+    pub fn set_channel_matrix(&self, matrix: Option<ChannelMatrix>) -> Result<(), Error> {
+        self.unit.set_channel_matrix(matrix)
+    }
+
+    /// Has a null output write what it renders to `writer`, see `PortableUnit::set_render_writer`.
+    pub fn set_render_writer<W: Write + Send + 'static>(&self, writer: W) -> Result<(), Error> {
+        self.unit.set_render_writer(writer)
+    }
+
+    /// As `set_render_writer`, in `format`, see `PortableUnit::set_render_writer_format`.
+    pub fn set_render_writer_format<W: Write + Send + 'static>(&self, writer: W, format: &StreamFormat,
+                                                               dither: Dither) -> Result<(), Error> {
+        self.unit.set_render_writer_format(writer, format, dither)
+    }
+}
+
+impl<'g, B: AudioBackend> Clone for AudioUnit<'g, B> {
+    fn clone(&self) -> AudioUnit<'g, B> {
+        AudioUnit { unit: self.unit.clone(), _graph: PhantomData }
+    }
+}
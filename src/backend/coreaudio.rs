@@ -0,0 +1,589 @@
+//! `AudioBackend` on top of AudioToolbox, i.e. the calls the PlayFile sample makes.
+
+use backend::{AudioBackend, CompletionProc, FileRegion, MIXER_MUTE_PARAMETER, ParameterChange, ParameterEvent};
+use channel_layout::ChannelLayout;
+use core_audio;
+use error::{AudioFileError, AudioUnitError, Context, Domain, Error};
+use libc;
+use property::PropertyValue;
+use std::collections::BTreeMap;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Drives the real AUGraph and Audio File Services APIs.
+pub struct CoreAudioBackend;
+
+/// wraps AudioUnitSetProperty
+///
+/// # Safety
+///
+/// `data` has to point to `data_size` bytes of a valid value for the property, see
+/// `AudioBackend::audio_unit_set_property`.
+pub unsafe fn audio_unit_set_property (audio_unit : core_audio::AudioUnit,
+                     property_id : core_audio::AudioUnitPropertyID,
+                     scope : core_audio::AudioUnitScope,
+                     element : core_audio::AudioUnitElement,
+                     data : *const libc::c_void,
+                     data_size : u32) -> Result<(),Error> {
+    try_os_status!(core_audio::AudioUnitSetProperty( audio_unit,
+                                                     property_id,
+                                                     scope,
+                                                     element,
+                                                     data,
+                                                     data_size),
+                   Domain::AudioUnit,
+                   Context::new("AudioUnitSetProperty").property(property_id).scope(scope, element));
+    Ok(())
+}
+
+/// A region's completion, waiting for the file player to finish with the region.
+struct Pending {
+    /// the file player the region is scheduled on, by address so that it can be sent between threads
+    unit: usize,
+    completion: CompletionProc,
+}
+
+/// The completions of every region scheduled but not yet finished with, by the key the file player
+/// is given as the region's user data. The completion itself stays here, so a callback that fires
+/// twice, or after its unit has been reset, finds nothing to call.
+static PENDING: Mutex<BTreeMap<usize, Pending>> = Mutex::new(BTreeMap::new());
+
+/// the next key into `PENDING`, never 0 so that it can't be mistaken for no user data
+static NEXT_KEY: AtomicUsize = AtomicUsize::new(1);
+
+fn pending() -> MutexGuard<'static, BTreeMap<usize, Pending>> {
+    // a completion panicking never happens with the lock held, but if it did the map would still be whole
+    PENDING.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Drops the completions of the regions still scheduled on `unit`, without calling them, like the
+/// portable file player does when it's reset.
+fn drop_completions(unit: usize) {
+    let dropped : Vec<Pending> = {
+        let mut pending = pending();
+        let keys : Vec<usize> = pending.iter().filter(|&(_, p)| p.unit == unit).map(|(key, _)| *key).collect();
+        keys.iter().filter_map(|key| pending.remove(key)).collect()
+    };
+    // dropped here rather than under the lock, in case dropping one schedules another region
+    drop(dropped);
+}
+
+/// The volume each muted mixer input goes back to when it's unmuted, by its unit's address and
+/// its bus. While an input is muted its volume is really 0, and setting or ramping it only changes
+/// what's kept here.
+static MUTED: Mutex<BTreeMap<(usize, u32), f32>> = Mutex::new(BTreeMap::new());
+
+fn muted() -> MutexGuard<'static, BTreeMap<(usize, u32), f32>> {
+    MUTED.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Whether `parameter` in `scope` is a mixer input's volume.
+fn is_input_volume(parameter: core_audio::AudioUnitParameterID, scope: core_audio::AudioUnitScope) -> bool {
+    parameter == core_audio::kMultiChannelMixerParam_Volume && scope == core_audio::kAudioUnitScope_Input
+}
+
+/// Checks `value` is a volume, or a mute switch, the way the AU checks its parameters.
+fn check_level(value: f32) -> Result<(), Error> {
+    if value >= 0.0 && value <= 1.0 {
+        Ok(())
+    }
+    else {
+        Err(Error::AudioUnit(AudioUnitError::InvalidParameterValue))
+    }
+}
+
+/// Forgets everything kept for the units of `graph`, before its units go away: the completions of
+/// regions they never finished, and the volumes of muted inputs.
+unsafe fn forget_graph_units(graph: core_audio::AUGraph) {
+    let mut count : u32 = 0;
+    if core_audio::AUGraphGetNodeCount(graph, &mut count) != 0 {
+        return;
+    }
+    for index in 0..count {
+        let mut node : core_audio::AUNode = mem::zeroed();
+        let mut audio_unit : core_audio::AudioUnit = mem::zeroed();
+        if core_audio::AUGraphGetIndNode(graph, index, &mut node) == 0 &&
+            core_audio::AUGraphNodeInfo(graph, node, ptr::null_mut(), &mut audio_unit) == 0 {
+            drop_completions(audio_unit as usize);
+            muted().retain(|&(unit, _), _| unit != audio_unit as usize);
+        }
+    }
+}
+
+/// The mCompletionProc of every region scheduled with a completion, `user_data` being its key into
+/// `PENDING`. The completion is taken out before it's called, so it's called at most once however
+/// often the file player calls this, and a panic in it stops here rather than unwinding into the
+/// file player.
+extern "C" fn region_completed(user_data: *mut libc::c_void,
+                               _file_region: *mut core_audio::ScheduledAudioFileRegion,
+                               result: core_audio::OSStatus) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        let taken = pending().remove(&(user_data as usize));
+        if let Some(mut taken) = taken {
+            (taken.completion)(Error::from_os_status(result));
+        }
+    }));
+}
+
+/// `event` as an AudioUnitParameterEvent
+fn parameter_event(event: &ParameterEvent) -> core_audio::AudioUnitParameterEvent {
+    let mut raw : core_audio::AudioUnitParameterEvent = unsafe { mem::zeroed() };
+    raw.scope = event.scope;
+    raw.element = event.element;
+    raw.parameter = event.parameter;
+    match event.change {
+        ParameterChange::Immediate { buffer_offset, value } => {
+            raw.eventType = core_audio::kParameterEvent_Immediate;
+            raw.eventValues.immediate.bufferOffset = buffer_offset;
+            raw.eventValues.immediate.value = value;
+        },
+        ParameterChange::Ramp { start_buffer_offset, duration_in_frames, start_value, end_value } => {
+            raw.eventType = core_audio::kParameterEvent_Ramped;
+            raw.eventValues.ramp.startBufferOffset = start_buffer_offset;
+            raw.eventValues.ramp.durationInFrames = duration_in_frames;
+            raw.eventValues.ramp.startValue = start_value;
+            raw.eventValues.ramp.endValue = end_value;
+        },
+    }
+    raw
+}
+
+/// wraps AudioUnitGetParameter
+fn get_parameter(audio_unit: &core_audio::AudioUnit,
+                 parameter: core_audio::AudioUnitParameterID,
+                 scope: core_audio::AudioUnitScope,
+                 element: core_audio::AudioUnitElement) -> Result<f32, Error> {
+    unsafe {
+        let mut value : core_audio::AudioUnitParameterValue = 0.0;
+        try_os_status!(core_audio::AudioUnitGetParameter(*audio_unit, parameter, scope, element, &mut value),
+                       Domain::AudioUnit,
+                       Context::new("AudioUnitGetParameter").parameter(parameter).scope(scope, element));
+        Ok(value)
+    }
+}
+
+/// wraps AudioUnitSetParameter
+fn set_parameter(audio_unit: &core_audio::AudioUnit,
+                 parameter: core_audio::AudioUnitParameterID,
+                 scope: core_audio::AudioUnitScope,
+                 element: core_audio::AudioUnitElement,
+                 value: f32,
+                 buffer_offset: u32) -> Result<(), Error> {
+    unsafe {
+        try_os_status!(core_audio::AudioUnitSetParameter(*audio_unit, parameter, scope, element, value,
+                                                         buffer_offset),
+                       Domain::AudioUnit,
+                       Context::new("AudioUnitSetParameter").parameter(parameter).scope(scope, element));
+        Ok(())
+    }
+}
+
+impl AudioBackend for CoreAudioBackend {
+    type File = core_audio::AudioFileID;
+    type Graph = core_audio::AUGraph;
+    type Unit = core_audio::AudioUnit;
+
+    fn open_audio_file(path: &Path) -> Result<core_audio::AudioFileID, Error> {
+        let bytes = path.as_os_str().as_bytes();
+        unsafe {
+            let url_ref = core_audio::CFURLCreateFromFileSystemRepresentation(core_audio::kCFAllocatorDefault,
+                                                                              bytes.as_ptr(),
+                                                                              bytes.len() as i64,
+                                                                              0 as core_audio::Boolean);
+            if url_ref.is_null() {
+                return Err(Error::Unspecified.with_context(Context::new("CFURLCreateFromFileSystemRepresentation")
+                                                           .path(path)));
+            }
+
+            let mut audio_file_id: core_audio::AudioFileID = mem::zeroed();
+            let status = core_audio::AudioFileOpenURL(url_ref,
+                                                      core_audio::kAudioFileReadPermission as i8,
+                                                      0,
+                                                      &mut audio_file_id as *mut core_audio::AudioFileID);
+            // release the url whether or not the open succeeded
+            core_audio::CFRelease(url_ref as core_audio::CFTypeRef);
+            try_os_status!(status, Domain::AudioFile, Context::new("AudioFileOpenURL").path(path));
+
+            Ok(audio_file_id)
+        }
+    }
+
+    fn close_audio_file(file: &mut core_audio::AudioFileID) -> Result<(), Error> {
+        unsafe {
+            try_os_status!(core_audio::AudioFileClose(*file), Domain::AudioFile, Context::new("AudioFileClose"));
+            Ok(())
+        }
+    }
+
+    fn get_data_format(file: &core_audio::AudioFileID) -> Result<core_audio::AudioStreamBasicDescription, Error> {
+        unsafe {
+            let mut file_format : core_audio::AudioStreamBasicDescription = mem::zeroed();
+            let mut property_size = mem::size_of::<core_audio::AudioStreamBasicDescription>() as u32;
+            try_os_status!(core_audio::AudioFileGetProperty(*file,
+                                                            core_audio::kAudioFilePropertyDataFormat,
+                                                            &mut property_size as *mut core_audio::UInt32,
+                                                            &mut file_format as *mut _ as *mut libc::c_void),
+                           Domain::AudioFile,
+                           Context::new("AudioFileGetProperty").property(core_audio::kAudioFilePropertyDataFormat));
+            Ok(file_format)
+        }
+    }
+
+    fn audio_file_get_audio_data_packet_count(file: &core_audio::AudioFileID) -> Result<u64, Error> {
+        unsafe {
+            let mut packet_count : u64 = 0;
+            let mut property_size = mem::size_of::<u64>() as u32;
+            try_os_status!(core_audio::AudioFileGetProperty(*file,
+                                                            core_audio::kAudioFilePropertyAudioDataPacketCount,
+                                                            &mut property_size as *mut core_audio::UInt32,
+                                                            &mut packet_count as *mut _ as *mut libc::c_void),
+                           Domain::AudioFile,
+                           Context::new("AudioFileGetProperty").property(core_audio::kAudioFilePropertyAudioDataPacketCount));
+            Ok(packet_count)
+        }
+    }
+
+    fn audio_file_get_frame_count(file: &core_audio::AudioFileID) -> Result<u64, Error> {
+        unsafe {
+            let mut packet_table : core_audio::AudioFilePacketTableInfo = mem::zeroed();
+            let mut property_size = mem::size_of::<core_audio::AudioFilePacketTableInfo>() as u32;
+            // files without a packet table fail this, which just means falling back on the format
+            let status = core_audio::AudioFileGetProperty(*file,
+                                                          core_audio::kAudioFilePropertyPacketTableInfo,
+                                                          &mut property_size as *mut core_audio::UInt32,
+                                                          &mut packet_table as *mut _ as *mut libc::c_void);
+            if status == 0 && packet_table.mNumberValidFrames > 0 {
+                return Ok(packet_table.mNumberValidFrames as u64);
+            }
+        }
+
+        let file_format = try!(Self::get_data_format(file));
+        if file_format.mFramesPerPacket != 0 {
+            let packet_count = try!(Self::audio_file_get_audio_data_packet_count(file));
+            return Ok(packet_count * file_format.mFramesPerPacket as u64);
+        }
+
+        unsafe {
+            let mut duration : core_audio::Float64 = 0.0;
+            let mut property_size = mem::size_of::<core_audio::Float64>() as u32;
+            try_os_status!(core_audio::AudioFileGetProperty(*file,
+                                                            core_audio::kAudioFilePropertyEstimatedDuration,
+                                                            &mut property_size as *mut core_audio::UInt32,
+                                                            &mut duration as *mut _ as *mut libc::c_void),
+                           Domain::AudioFile,
+                           Context::new("AudioFileGetProperty").property(core_audio::kAudioFilePropertyEstimatedDuration));
+            Ok((duration * file_format.mSampleRate).round() as u64)
+        }
+    }
+
+    fn audio_file_get_channel_layout(file: &core_audio::AudioFileID) -> Result<Option<ChannelLayout>, Error> {
+        let context = Context::new("AudioFileGetProperty").property(core_audio::kAudioFilePropertyChannelLayout);
+        unsafe {
+            let mut property_size : u32 = 0;
+            let mut writable : u32 = 0;
+            let status = core_audio::AudioFileGetPropertyInfo(*file,
+                                                              core_audio::kAudioFilePropertyChannelLayout,
+                                                              &mut property_size as *mut core_audio::UInt32,
+                                                              &mut writable as *mut core_audio::UInt32);
+            // formats that can't record a layout don't have the property at all
+            match Error::from_domain_os_status(Domain::AudioFile, status) {
+                Err(Error::AudioFile(AudioFileError::UnsupportedProperty)) => return Ok(None),
+                Err(err) => return Err(err.with_context(context)),
+                Ok(()) if property_size == 0 => return Ok(None),
+                Ok(()) => (),
+            }
+            let mut data = vec![0u8; property_size as usize];
+            try_os_status!(core_audio::AudioFileGetProperty(*file,
+                                                            core_audio::kAudioFilePropertyChannelLayout,
+                                                            &mut property_size as *mut core_audio::UInt32,
+                                                            data.as_mut_ptr() as *mut libc::c_void),
+                           Domain::AudioFile,
+                           context.clone());
+            data.truncate(property_size as usize);
+            ChannelLayout::from_bytes(&data).map(Some).map_err(|err| err.with_context(context))
+        }
+    }
+
+    fn new_au_graph() -> Result<core_audio::AUGraph, Error> {
+        unsafe {
+            let mut graph: core_audio::AUGraph = mem::zeroed();
+            try_os_status!(core_audio::NewAUGraph(&mut graph as *mut core_audio::AUGraph), Domain::Graph, Context::new("NewAUGraph"));
+            Ok(graph)
+        }
+    }
+
+    fn dispose_au_graph(graph: &mut core_audio::AUGraph) -> Result<(), Error> {
+        unsafe {
+            // disposing of an open graph closes it
+            forget_graph_units(*graph);
+            try_os_status!(core_audio::DisposeAUGraph(*graph), Domain::Graph, Context::new("DisposeAUGraph"));
+            Ok(())
+        }
+    }
+
+    fn graph_add_node(graph: &mut core_audio::AUGraph,
+                      description: &core_audio::AudioComponentDescription) -> Result<core_audio::AUNode, Error> {
+        unsafe {
+            let mut node: core_audio::AUNode = mem::zeroed();
+            try_os_status!(core_audio::AUGraphAddNode(*graph,
+                                                      description as *const core_audio::AudioComponentDescription,
+                                                      &mut node as *mut core_audio::AUNode),
+                           Domain::Graph,
+                           Context::new("AUGraphAddNode"));
+            Ok(node)
+        }
+    }
+
+    fn graph_open(graph: &mut core_audio::AUGraph) -> Result<(), Error> {
+        unsafe {
+            try_os_status!(core_audio::AUGraphOpen(*graph), Domain::Graph, Context::new("AUGraphOpen"));
+            Ok(())
+        }
+    }
+
+    fn graph_close(graph: &mut core_audio::AUGraph) -> Result<(), Error> {
+        unsafe {
+            // closing the graph disposes of its units, and with them any regions they hadn't finished
+            forget_graph_units(*graph);
+            try_os_status!(core_audio::AUGraphClose(*graph), Domain::Graph, Context::new("AUGraphClose"));
+            Ok(())
+        }
+    }
+
+    fn graph_node_info(graph: &core_audio::AUGraph, node: core_audio::AUNode) -> Result<core_audio::AudioUnit, Error> {
+        unsafe {
+            let description: *mut core_audio::AudioComponentDescription = ptr::null_mut();
+            let mut audio_unit : core_audio::AudioUnit = mem::zeroed();
+            try_os_status!(core_audio::AUGraphNodeInfo(*graph, node, description, &mut audio_unit), Domain::Graph, Context::new("AUGraphNodeInfo"));
+            Ok(audio_unit)
+        }
+    }
+
+    fn graph_connect_node_input(graph: &mut core_audio::AUGraph, source_node: core_audio::AUNode, source_output: u32,
+                                dest_node: core_audio::AUNode, dest_input: u32) -> Result<(), Error> {
+        unsafe {
+            try_os_status!(core_audio::AUGraphConnectNodeInput(*graph, source_node, source_output,
+                                                               dest_node, dest_input),
+                           Domain::Graph,
+                           Context::new("AUGraphConnectNodeInput"));
+            Ok(())
+        }
+    }
+
+    fn graph_initialize(graph: &mut core_audio::AUGraph) -> Result<(), Error> {
+        unsafe {
+            try_os_status!(core_audio::AUGraphInitialize(*graph), Domain::Graph, Context::new("AUGraphInitialize"));
+            Ok(())
+        }
+    }
+
+    fn graph_uninitialize(graph: &mut core_audio::AUGraph) -> Result<(), Error> {
+        unsafe {
+            try_os_status!(core_audio::AUGraphUninitialize(*graph), Domain::Graph, Context::new("AUGraphUninitialize"));
+            Ok(())
+        }
+    }
+
+    fn graph_start(graph: &mut core_audio::AUGraph) -> Result<(), Error> {
+        unsafe {
+            try_os_status!(core_audio::AUGraphStart(*graph), Domain::Graph, Context::new("AUGraphStart"));
+            Ok(())
+        }
+    }
+
+    fn graph_stop(graph: &mut core_audio::AUGraph) -> Result<(), Error> {
+        unsafe {
+            try_os_status!(core_audio::AUGraphStop(*graph), Domain::Graph, Context::new("AUGraphStop"));
+            Ok(())
+        }
+    }
+
+    fn audio_unit_get_property(audio_unit: &core_audio::AudioUnit,
+                               property_id: core_audio::AudioUnitPropertyID,
+                               scope: core_audio::AudioUnitScope,
+                               element: core_audio::AudioUnitElement) -> Result<Vec<u8>, Error> {
+        let context = Context::new("AudioUnitGetProperty").property(property_id).scope(scope, element);
+        unsafe {
+            let mut property_size : u32 = 0;
+            let mut writable : core_audio::Boolean = 0;
+            try_os_status!(core_audio::AudioUnitGetPropertyInfo(*audio_unit, property_id, scope, element,
+                                                                &mut property_size, &mut writable),
+                           Domain::AudioUnit,
+                           context.clone());
+            let mut data = vec![0u8; property_size as usize];
+            try_os_status!(core_audio::AudioUnitGetProperty(*audio_unit, property_id, scope, element,
+                                                            data.as_mut_ptr() as *mut libc::c_void,
+                                                            &mut property_size),
+                           Domain::AudioUnit,
+                           context);
+            // variable size properties can shrink in between
+            data.truncate(property_size as usize);
+            Ok(data)
+        }
+    }
+
+    unsafe fn audio_unit_set_property(audio_unit: &core_audio::AudioUnit,
+                                      property_id: core_audio::AudioUnitPropertyID,
+                                      scope: core_audio::AudioUnitScope,
+                                      element: core_audio::AudioUnitElement,
+                                      data: &[u8]) -> Result<(), Error> {
+        audio_unit_set_property(*audio_unit, property_id, scope, element,
+                                data.as_ptr() as *const libc::c_void, data.len() as u32)
+    }
+
+    fn audio_unit_get_parameter(audio_unit: &core_audio::AudioUnit,
+                                parameter: core_audio::AudioUnitParameterID,
+                                scope: core_audio::AudioUnitScope,
+                                element: core_audio::AudioUnitElement) -> Result<f32, Error> {
+        if parameter != MIXER_MUTE_PARAMETER && !is_input_volume(parameter, scope) {
+            return get_parameter(audio_unit, parameter, scope, element);
+        }
+        if scope != core_audio::kAudioUnitScope_Input {
+            return Err(Error::AudioUnit(AudioUnitError::InvalidScope));
+        }
+        // the volume's asked for either way, so that a bus the mixer hasn't got is refused
+        let volume = try!(get_parameter(audio_unit, core_audio::kMultiChannelMixerParam_Volume, scope, element));
+        let kept = muted().get(&(*audio_unit as usize, element)).cloned();
+        Ok(match kept {
+            Some(_) if parameter == MIXER_MUTE_PARAMETER => 1.0,
+            None if parameter == MIXER_MUTE_PARAMETER => 0.0,
+            Some(kept) => kept,
+            None => volume,
+        })
+    }
+
+    fn audio_unit_set_parameter(audio_unit: &core_audio::AudioUnit,
+                                parameter: core_audio::AudioUnitParameterID,
+                                scope: core_audio::AudioUnitScope,
+                                element: core_audio::AudioUnitElement,
+                                value: f32,
+                                buffer_offset: u32) -> Result<(), Error> {
+        let key = (*audio_unit as usize, element);
+        if parameter == MIXER_MUTE_PARAMETER {
+            if scope != core_audio::kAudioUnitScope_Input {
+                return Err(Error::AudioUnit(AudioUnitError::InvalidScope));
+            }
+            try!(check_level(value));
+            let volume = core_audio::kMultiChannelMixerParam_Volume;
+            let kept = muted().get(&key).cloned();
+            match (kept, value != 0.0) {
+                (None, true) => {
+                    let level = try!(get_parameter(audio_unit, volume, scope, element));
+                    try!(set_parameter(audio_unit, volume, scope, element, 0.0, buffer_offset));
+                    muted().insert(key, level);
+                },
+                (Some(level), false) => {
+                    try!(set_parameter(audio_unit, volume, scope, element, level, buffer_offset));
+                    muted().remove(&key);
+                },
+                _ => (),
+            }
+            return Ok(());
+        }
+        if is_input_volume(parameter, scope) {
+            if let Some(kept) = muted().get_mut(&key) {
+                try!(check_level(value));
+                *kept = value;
+                return Ok(());
+            }
+        }
+        set_parameter(audio_unit, parameter, scope, element, value, buffer_offset)
+    }
+
+    fn audio_unit_schedule_parameters(audio_unit: &core_audio::AudioUnit, events: &[ParameterEvent]) -> Result<(), Error> {
+        // changes to a muted input's volume only change the volume it will be unmuted at
+        let mut muted = muted();
+        let mut kept = Vec::new();
+        let mut raw_events = Vec::with_capacity(events.len());
+        for event in events {
+            let key = (*audio_unit as usize, event.element);
+            if is_input_volume(event.parameter, event.scope) && muted.contains_key(&key) {
+                let level = match event.change {
+                    ParameterChange::Immediate { value, .. } => value,
+                    ParameterChange::Ramp { start_value, end_value, .. } => {
+                        try!(check_level(start_value));
+                        end_value
+                    },
+                };
+                try!(check_level(level));
+                kept.push((key, level));
+            }
+            else {
+                raw_events.push(parameter_event(event));
+            }
+        }
+        if !raw_events.is_empty() {
+            unsafe {
+                try_os_status!(core_audio::AudioUnitScheduleParameters(*audio_unit, raw_events.as_ptr(),
+                                                                       raw_events.len() as u32),
+                               Domain::AudioUnit,
+                               Context::new("AudioUnitScheduleParameters"));
+            }
+        }
+        muted.extend(kept);
+        Ok(())
+    }
+
+    unsafe fn set_scheduled_file_ids(audio_unit: &core_audio::AudioUnit,
+                                     scope: core_audio::AudioUnitScope,
+                                     element: core_audio::AudioUnitElement,
+                                     files: &[&core_audio::AudioFileID]) -> Result<(), Error> {
+        let file_ids : Vec<core_audio::AudioFileID> = files.iter().map(|file| **file).collect();
+        // the caller keeps the files open, so the file IDs are fine to hand over
+        Self::audio_unit_set_property(audio_unit, core_audio::kAudioUnitProperty_ScheduledFileIDs, scope, element,
+                                      &file_ids.to_bytes())
+    }
+
+    unsafe fn audio_unit_set_scheduled_file_region(audio_unit: &core_audio::AudioUnit,
+                                                   file: &core_audio::AudioFileID,
+                                                   file_region: &FileRegion,
+                                                   completion: Option<CompletionProc>) -> Result<(), Error> {
+        let mut region : core_audio::ScheduledAudioFileRegion = core_audio::ScheduledAudioFileRegion::default();
+        // should be memset to 0 by default (see what I did there?)
+        region.mTimeStamp = core_audio::AudioTimeStamp::default();
+        region.mTimeStamp.mFlags = core_audio::kAudioTimeStampSampleTimeValid;
+        region.mTimeStamp.mSampleTime = file_region.sample_time;
+        // the completion waits in PENDING for region_completed, or for the unit to be reset
+        let key = match completion {
+            Some(completion) => {
+                let key = NEXT_KEY.fetch_add(1, Ordering::Relaxed);
+                pending().insert(key, Pending { unit: *audio_unit as usize, completion: completion });
+                key
+            },
+            None => 0,
+        };
+        region.mCompletionProc = if key == 0 { Option::None } else { Some(region_completed) };
+        region.mCompletionProcUserData = key as *mut libc::c_void;
+        region.mAudioFile = *file;
+        region.mLoopCount = file_region.loop_count;
+        region.mStartFrame = file_region.start_frame;
+        region.mFramesToPlay = file_region.frames_to_play;
+
+        let result = Self::audio_unit_set_property(audio_unit, core_audio::kAudioUnitProperty_ScheduledFileRegion,
+                                                   core_audio::kAudioUnitScope_Global, 0, &region.to_bytes());
+        if result.is_err() && key != 0 {
+            // the file player never saw the region, so nothing will ever call the completion
+            let dropped = pending().remove(&key);
+            drop(dropped);
+        }
+        result
+    }
+
+    fn audio_unit_reset(audio_unit: &core_audio::AudioUnit,
+                        scope: core_audio::AudioUnitScope,
+                        element: core_audio::AudioUnitElement) -> Result<(), Error> {
+        unsafe {
+            try_os_status!(core_audio::AudioUnitReset(*audio_unit, scope, element),
+                           Domain::AudioUnit,
+                           Context::new("AudioUnitReset").scope(scope, element));
+        }
+        // the file player has forgotten its regions, so their completions will never be called
+        drop_completions(*audio_unit as usize);
+        Ok(())
+    }
+}
@@ -0,0 +1,336 @@
+//! The operations the rest of the crate needs from an audio system.
+//!
+//! `AudioFile` and `Graph` are written against the `AudioBackend` trait rather than CoreAudio
+//! directly. `CoreAudioBackend` implements it with AudioToolbox on macOS, and `PortableBackend`
+//! implements it in plain Rust so the crate builds, and its tests run, everywhere.
+
+use channel_layout::ChannelLayout;
+use core_audio;
+use error::{Context, Error};
+use property::{self, Property, PropertyValue};
+use std::path::Path;
+use stream_format::StreamFormat;
+
+#[cfg(target_os = "macos")]
+pub mod coreaudio;
+pub mod portable;
+
+#[cfg(target_os = "macos")]
+pub use self::coreaudio::CoreAudioBackend;
+pub use self::portable::PortableBackend;
+
+/// The backend `AudioFile` and `Graph` use when none is given.
+#[cfg(target_os = "macos")]
+pub type DefaultBackend = CoreAudioBackend;
+/// The backend `AudioFile` and `Graph` use when none is given.
+#[cfg(not(target_os = "macos"))]
+pub type DefaultBackend = PortableBackend;
+
+/// A region of a file scheduled on a file player, mirroring `ScheduledAudioFileRegion`. `Region`
+/// works one out from times in the file.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FileRegion {
+    /// when to start playing the region, in the file player's sample time
+    pub sample_time: f64,
+    /// the first frame of the file to play
+    pub start_frame: i64,
+    /// how many frames of the file to play
+    pub frames_to_play: u32,
+    /// how many more times to play the region once it has played through (0 plays it once)
+    pub loop_count: u32,
+}
+
+/// A parameter of each of a multichannel mixer's inputs, 1 if the input is muted and 0 if not.
+/// Muting silences an input without touching its volume, so a muted input can have its volume
+/// changed, or ramped, and stays silent until it's unmuted. The AU has no such parameter, so the
+/// Core Audio backend turns a muted input's volume down and keeps the real one itself.
+pub const MIXER_MUTE_PARAMETER: core_audio::AudioUnitParameterID = 0x6d757465; // 'mute'
+
+/// A change to a parameter in the next render cycle, mirroring `AudioUnitParameterEvent`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParameterEvent {
+    pub parameter: core_audio::AudioUnitParameterID,
+    pub scope: core_audio::AudioUnitScope,
+    pub element: core_audio::AudioUnitElement,
+    pub change: ParameterChange,
+}
+
+/// What a `ParameterEvent` does. Offsets count frames from the start of the next render cycle.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ParameterChange {
+    /// jumps to `value` at `buffer_offset`
+    Immediate { buffer_offset: u32, value: f32 },
+    /// goes in a straight line from `start_value` to `end_value` over `duration_in_frames` frames
+    /// starting at `start_buffer_offset`, then stays at `end_value`. A negative offset means the
+    /// ramp started that many frames before this cycle, which is how a ramp longer than a cycle
+    /// is carried on in the cycles after its first.
+    Ramp { start_buffer_offset: i32, duration_in_frames: u32, start_value: f32, end_value: f32 },
+}
+
+impl ParameterEvent {
+
+    pub fn immediate(parameter: core_audio::AudioUnitParameterID, scope: core_audio::AudioUnitScope,
+                     element: core_audio::AudioUnitElement, buffer_offset: u32, value: f32) -> ParameterEvent {
+        ParameterEvent {
+            parameter,
+            scope,
+            element,
+            change: ParameterChange::Immediate { buffer_offset, value },
+        }
+    }
+
+    pub fn ramp(parameter: core_audio::AudioUnitParameterID, scope: core_audio::AudioUnitScope,
+                element: core_audio::AudioUnitElement, start_buffer_offset: i32, duration_in_frames: u32,
+                start_value: f32, end_value: f32) -> ParameterEvent {
+        ParameterEvent {
+            parameter,
+            scope,
+            element,
+            change: ParameterChange::Ramp {
+                start_buffer_offset,
+                duration_in_frames,
+                start_value,
+                end_value,
+            },
+        }
+    }
+}
+
+/// Called once a scheduled region is done with, with the error if it couldn't be played. It runs
+/// on the audio system's own thread, so it mustn't block or call back into the graph. A region
+/// thrown away by resetting its unit, or closing the graph, drops its completion without calling it.
+pub type CompletionProc = Box<dyn FnMut(Result<(), Error>) + Send>;
+
+/// The file, graph and unit operations an audio system has to provide.
+///
+/// These mirror the AudioToolbox calls they are named after. Handles are plain associated types
+/// with no ownership semantics of their own; `AudioFile` and `Graph` take care of releasing them.
+pub trait AudioBackend: Sized {
+    /// an open audio file
+    type File;
+    /// an audio processing graph
+    type Graph;
+    /// an audio unit belonging to a node of an open graph
+    type Unit: Clone;
+
+    /// wraps AudioFileOpenURL
+    fn open_audio_file(path: &Path) -> Result<Self::File, Error>;
+
+    /// wraps AudioFileClose
+    fn close_audio_file(file: &mut Self::File) -> Result<(), Error>;
+
+    /// wraps AudioFileGetProperty with kAudioFilePropertyDataFormat
+    fn get_data_format(file: &Self::File) -> Result<core_audio::AudioStreamBasicDescription, Error>;
+
+    /// wraps AudioFileGetProperty with kAudioFilePropertyAudioDataPacketCount
+    fn audio_file_get_audio_data_packet_count(file: &Self::File) -> Result<u64, Error>;
+
+    /// The number of frames of audio in the file, leaving out any priming and remainder frames.
+    /// Taken from kAudioFilePropertyPacketTableInfo where the file has a packet table, otherwise
+    /// worked out from the packet count or, for formats whose packets vary in length,
+    /// kAudioFilePropertyEstimatedDuration.
+    fn audio_file_get_frame_count(file: &Self::File) -> Result<u64, Error>;
+
+    /// wraps AudioFileGetPropertyInfo and AudioFileGetProperty with
+    /// kAudioFilePropertyChannelLayout, `None` for files that don't say which speaker each
+    /// channel is for
+    fn audio_file_get_channel_layout(file: &Self::File) -> Result<Option<ChannelLayout>, Error>;
+
+    /// wraps NewAUGraph
+    fn new_au_graph() -> Result<Self::Graph, Error>;
+
+    /// wraps DisposeAUGraph
+    fn dispose_au_graph(graph: &mut Self::Graph) -> Result<(), Error>;
+
+    /// wraps AUGraphAddNode
+    fn graph_add_node(graph: &mut Self::Graph,
+                      description: &core_audio::AudioComponentDescription) -> Result<core_audio::AUNode, Error>;
+
+    /// wraps AUGraphOpen
+    fn graph_open(graph: &mut Self::Graph) -> Result<(), Error>;
+
+    /// wraps AUGraphClose
+    fn graph_close(graph: &mut Self::Graph) -> Result<(), Error>;
+
+    /// wraps AUGraphNodeInfo
+    fn graph_node_info(graph: &Self::Graph, node: core_audio::AUNode) -> Result<Self::Unit, Error>;
+
+    /// wraps AUGraphConnectNodeInput
+    fn graph_connect_node_input(graph: &mut Self::Graph, source_node: core_audio::AUNode, source_output: u32,
+                                dest_node: core_audio::AUNode, dest_input: u32) -> Result<(), Error>;
+
+    /// wraps AUGraphInitialize
+    fn graph_initialize(graph: &mut Self::Graph) -> Result<(), Error>;
+
+    /// wraps AUGraphUninitialize
+    fn graph_uninitialize(graph: &mut Self::Graph) -> Result<(), Error>;
+
+    /// wraps AUGraphStart
+    fn graph_start(graph: &mut Self::Graph) -> Result<(), Error>;
+
+    /// wraps AUGraphStop
+    fn graph_stop(graph: &mut Self::Graph) -> Result<(), Error>;
+
+    /// wraps AudioUnitGetPropertyInfo and AudioUnitGetProperty, returning the buffer the unit
+    /// filled in
+    fn audio_unit_get_property(audio_unit: &Self::Unit,
+                               property_id: core_audio::AudioUnitPropertyID,
+                               scope: core_audio::AudioUnitScope,
+                               element: core_audio::AudioUnitElement) -> Result<Vec<u8>, Error>;
+
+    /// wraps AudioUnitSetProperty
+    ///
+    /// # Safety
+    ///
+    /// The unit takes `data` as the property's value as it is, so it has to be a valid one for
+    /// `property_id`. Values holding pointers or handles, e.g. file IDs and completion procs, have
+    /// to stay valid for as long as the unit can use them. `set_property` makes sure of it for
+    /// `Property` types.
+    unsafe fn audio_unit_set_property(audio_unit: &Self::Unit,
+                                      property_id: core_audio::AudioUnitPropertyID,
+                                      scope: core_audio::AudioUnitScope,
+                                      element: core_audio::AudioUnitElement,
+                                      data: &[u8]) -> Result<(), Error>;
+
+    /// wraps AudioUnitSetProperty with kAudioUnitProperty_ScheduledFileIDs, replacing the files
+    /// the unit can schedule regions of with `files`
+    ///
+    /// # Safety
+    ///
+    /// The files have to stay open for as long as the unit can play them, i.e. until they're
+    /// replaced or the unit is disposed of. `Graph::set_scheduled_file_ids` makes sure of that by
+    /// borrowing them for the graph's lifetime.
+    unsafe fn set_scheduled_file_ids(audio_unit: &Self::Unit,
+                              scope: core_audio::AudioUnitScope,
+                              element: core_audio::AudioUnitElement,
+                              files: &[&Self::File]) -> Result<(), Error>;
+
+    /// wraps AudioUnitSetProperty with kAudioUnitProperty_ScheduledFileRegion, `completion` being
+    /// the region's mCompletionProc
+    ///
+    /// # Safety
+    ///
+    /// As for `set_scheduled_file_ids`, `file` has to stay open for as long as the unit can play
+    /// the region. `Graph::set_scheduled_file_region` makes sure of that.
+    unsafe fn audio_unit_set_scheduled_file_region(audio_unit: &Self::Unit,
+                                            file: &Self::File,
+                                            region: &FileRegion,
+                                            completion: Option<CompletionProc>) -> Result<(), Error>;
+
+    /// wraps AudioUnitReset, which for a file player throws away everything scheduled on it
+    fn audio_unit_reset(audio_unit: &Self::Unit,
+                        scope: core_audio::AudioUnitScope,
+                        element: core_audio::AudioUnitElement) -> Result<(), Error>;
+
+    /// wraps AudioUnitGetParameter
+    fn audio_unit_get_parameter(audio_unit: &Self::Unit,
+                                parameter: core_audio::AudioUnitParameterID,
+                                scope: core_audio::AudioUnitScope,
+                                element: core_audio::AudioUnitElement) -> Result<f32, Error>;
+
+    /// wraps AudioUnitSetParameter, the new value taking effect `buffer_offset` frames into the
+    /// next render cycle
+    fn audio_unit_set_parameter(audio_unit: &Self::Unit,
+                                parameter: core_audio::AudioUnitParameterID,
+                                scope: core_audio::AudioUnitScope,
+                                element: core_audio::AudioUnitElement,
+                                value: f32,
+                                buffer_offset: u32) -> Result<(), Error>;
+
+    /// wraps AudioUnitScheduleParameters. The events apply to the next render cycle only, so a
+    /// ramp running past its end has to be scheduled again, with a negative start offset, for the
+    /// cycles after; where it isn't the parameter stays at the value the ramp had got to.
+    fn audio_unit_schedule_parameters(audio_unit: &Self::Unit, events: &[ParameterEvent]) -> Result<(), Error>;
+
+    /// property `P` of the unit
+    fn get_property<P: Property>(audio_unit: &Self::Unit,
+                                 scope: core_audio::AudioUnitScope,
+                                 element: core_audio::AudioUnitElement) -> Result<P::Value, Error> {
+        let data = try!(Self::audio_unit_get_property(audio_unit, P::ID, scope, element));
+        P::Value::from_bytes(&data).map_err(|err| {
+            err.with_context(Context::new("AudioUnitGetProperty").property(P::ID).scope(scope, element))
+        })
+    }
+
+    /// sets property `P` of the unit
+    fn set_property<P: Property>(audio_unit: &Self::Unit,
+                                 scope: core_audio::AudioUnitScope,
+                                 element: core_audio::AudioUnitElement,
+                                 value: &P::Value) -> Result<(), Error> {
+        // a `Property` promises that any value of its type is fine to set
+        unsafe { Self::audio_unit_set_property(audio_unit, P::ID, scope, element, &value.to_bytes()) }
+    }
+
+    /// the unit's kAudioUnitProperty_StreamFormat
+    fn get_format(audio_unit: &Self::Unit,
+                  scope: core_audio::AudioUnitScope,
+                  element: core_audio::AudioUnitElement) -> Result<core_audio::AudioStreamBasicDescription, Error> {
+        Self::get_property::<property::StreamFormat>(audio_unit, scope, element)
+    }
+
+    /// sets the unit's kAudioUnitProperty_StreamFormat
+    fn set_format(audio_unit: &Self::Unit,
+                  scope: core_audio::AudioUnitScope,
+                  element: core_audio::AudioUnitElement,
+                  description: &core_audio::AudioStreamBasicDescription) -> Result<(), Error> {
+        Self::set_property::<property::StreamFormat>(audio_unit, scope, element, description)
+    }
+
+    /// sets kAudioUnitProperty_ScheduledFilePrime on a file player
+    fn audio_unit_set_scheduled_file_prime(audio_unit: &Self::Unit, prime_value: u32) -> Result<(), Error> {
+        Self::set_property::<property::ScheduledFilePrime>(audio_unit, core_audio::kAudioUnitScope_Global, 0,
+                                                           &prime_value)
+    }
+
+    /// sets kAudioUnitProperty_ScheduleStartTimeStamp on a file player, -1 means the next render cycle
+    fn audio_unit_set_schedule_start_time_stamp(audio_unit: &Self::Unit, sample_time: f64) -> Result<(), Error> {
+        let start_time = core_audio::AudioTimeStamp {
+            mFlags: core_audio::kAudioTimeStampSampleTimeValid,
+            mSampleTime: sample_time,
+            ..Default::default()
+        };
+        Self::set_property::<property::ScheduleStartTimeStamp>(audio_unit, core_audio::kAudioUnitScope_Global, 0,
+                                                               &start_time)
+    }
+
+    /// a file player's kAudioUnitProperty_CurrentPlayTime: how far it has got in sample frames,
+    /// counting from its start time stamp, or -1 before it has started
+    fn audio_unit_get_current_play_time(audio_unit: &Self::Unit) -> Result<f64, Error> {
+        let play_time = try!(Self::get_property::<property::CurrentPlayTime>(audio_unit,
+                                                                             core_audio::kAudioUnitScope_Global, 0));
+        Ok(play_time.mSampleTime)
+    }
+
+    /// the unit's stream format, checked, see `StreamFormat::from_description`
+    fn get_stream_format(audio_unit: &Self::Unit,
+                         scope: core_audio::AudioUnitScope,
+                         element: core_audio::AudioUnitElement) -> Result<StreamFormat, Error> {
+        StreamFormat::from_description(&try!(Self::get_format(audio_unit, scope, element)))
+    }
+
+    /// sets the unit's stream format
+    fn set_stream_format(audio_unit: &Self::Unit,
+                         scope: core_audio::AudioUnitScope,
+                         element: core_audio::AudioUnitElement,
+                         format: &StreamFormat) -> Result<(), Error> {
+        Self::set_format(audio_unit, scope, element, &format.to_description())
+    }
+
+    /// changes the channel count of the unit's stream format, keeping everything else
+    fn set_number_of_channels(audio_unit: &Self::Unit,
+                              scope: core_audio::AudioUnitScope,
+                              element: core_audio::AudioUnitElement,
+                              number_of_channels: u32) -> Result<(), Error> {
+        let format = try!(Self::get_stream_format(audio_unit, scope, element));
+        Self::set_stream_format(audio_unit, scope, element, &try!(format.with_channels(number_of_channels)))
+    }
+
+    /// changes the sample rate of the unit's stream format, keeping everything else
+    fn set_sample_rate(audio_unit: &Self::Unit,
+                       scope: core_audio::AudioUnitScope,
+                       element: core_audio::AudioUnitElement,
+                       sample_rate: f64) -> Result<(), Error> {
+        let format = try!(Self::get_stream_format(audio_unit, scope, element));
+        Self::set_stream_format(audio_unit, scope, element, &try!(format.with_sample_rate(sample_rate)))
+    }
+}
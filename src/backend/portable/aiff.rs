@@ -0,0 +1,275 @@
+//! AIFF and AIFF-C files: big endian PCM, plus the `sowt`, `fl32` and `fl64` compression types.
+
+use backend::portable::bytes::ByteReader;
+use backend::portable::file::FileData;
+use core_audio;
+use error::{AudioFileError, Error};
+
+/// The parts of a `COMM` chunk we care about.
+struct Comm {
+    channels: u16,
+    sample_frames: u32,
+    sample_size: u16,
+    sample_rate: f64,
+    compression_type: [u8; 4],
+}
+
+/// Whether `bytes` starts like an AIFF or AIFF-C file.
+pub fn sniff(bytes: &[u8]) -> bool {
+    bytes.len() >= 12 && &bytes[0..4] == b"FORM" && (&bytes[8..12] == b"AIFF" || &bytes[8..12] == b"AIFC")
+}
+
+pub fn parse(bytes: &[u8]) -> Result<FileData, Error> {
+    let compressed = &bytes[8..12] == b"AIFC";
+    let mut reader = ByteReader::new(bytes);
+    // FORM, its size and the form type
+    try!(reader.skip(12));
+
+    let mut comm = None;
+    let mut sound_data = None;
+    while reader.remaining() >= 8 {
+        let id = try!(reader.fourcc());
+        let size = try!(reader.u32_be()) as usize;
+        match &id {
+            b"COMM" => comm = Some(try!(parse_comm(try!(reader.take(size)), compressed))),
+            b"SSND" => {
+                // like a WAVE data chunk, play what's there if it runs off the end
+                let available = size.min(reader.remaining());
+                let mut chunk = ByteReader::new(try!(reader.take(available)));
+                let offset = try!(chunk.u32_be()) as usize;
+                try!(chunk.skip(4)); // block size
+                try!(chunk.skip(offset));
+                let remaining = chunk.remaining();
+                sound_data = Some(try!(chunk.take(remaining)));
+            },
+            _ => try!(reader.skip(size)),
+        }
+        // chunks are padded to an even length
+        if size % 2 == 1 && reader.remaining() > 0 {
+            try!(reader.skip(1));
+        }
+    }
+
+    let comm = try!(comm.ok_or(Error::AudioFile(AudioFileError::InvalidFile)));
+    let format = try!(stream_format(&comm));
+    // the sound data chunk can only be left out when there's no sound
+    let sound_data = match sound_data {
+        Some(sound_data) => sound_data,
+        None if comm.sample_frames == 0 => &[],
+        None => return Err(Error::AudioFile(AudioFileError::InvalidFile)),
+    };
+    let frame_bytes = format.mBytesPerFrame as usize;
+    let packet_count = (comm.sample_frames as usize).min(sound_data.len() / frame_bytes);
+    Ok(FileData::new(format, packet_count as u64, sound_data[..packet_count * frame_bytes].to_vec()))
+}
+
+fn parse_comm(body: &[u8], compressed: bool) -> Result<Comm, Error> {
+    let mut reader = ByteReader::new(body);
+    let channels = try!(reader.u16_be());
+    let sample_frames = try!(reader.u32_be());
+    let sample_size = try!(reader.u16_be());
+    let sample_rate = try!(extended_to_f64(try!(reader.take(10))));
+    // plain AIFF is always uncompressed, the compression name that follows in AIFF-C is only
+    // for showing to people
+    let compression_type = if compressed { try!(reader.fourcc()) } else { *b"NONE" };
+    Ok(Comm {
+        channels,
+        sample_frames,
+        sample_size,
+        sample_rate,
+        compression_type,
+    })
+}
+
+/// Decodes an IEEE 754 80 bit extended precision number, the format of a `COMM` chunk's sample
+/// rate: a sign bit, 15 bit exponent and 64 bit mantissa with an explicit integer bit.
+fn extended_to_f64(bytes: &[u8]) -> Result<f64, Error> {
+    let mut reader = ByteReader::new(bytes);
+    let sign_and_exponent = try!(reader.u16_be());
+    let mantissa = try!(reader.u64_be());
+    let exponent = (sign_and_exponent & 0x7fff) as i32;
+    if exponent == 0x7fff {
+        // infinity or NaN, neither of which is a sample rate
+        return Err(Error::AudioFile(AudioFileError::InvalidChunk));
+    }
+    if exponent == 0 && mantissa == 0 {
+        return Ok(0.0);
+    }
+    let magnitude = mantissa as f64 * 2f64.powi(exponent - 16383 - 63);
+    Ok(if sign_and_exponent & 0x8000 != 0 { -magnitude } else { magnitude })
+}
+
+/// The description AudioFileGetProperty gives for an AIFF file's data format. Integer samples
+/// that don't fill their bytes are left justified, i.e. aligned high.
+fn stream_format(comm: &Comm) -> Result<core_audio::AudioStreamBasicDescription, Error> {
+    if comm.channels == 0 || comm.sample_rate.is_nan() || comm.sample_rate <= 0.0 {
+        return Err(Error::AudioFile(AudioFileError::InvalidFile));
+    }
+    let bits = comm.sample_size as u32;
+    let (mut flags, bits) = match &comm.compression_type {
+        b"NONE" | b"twos" => (core_audio::kAudioFormatFlagIsSignedInteger | core_audio::kAudioFormatFlagIsBigEndian, bits),
+        b"sowt" => (core_audio::kAudioFormatFlagIsSignedInteger, bits),
+        b"fl32" | b"FL32" => (core_audio::kAudioFormatFlagIsFloat | core_audio::kAudioFormatFlagIsBigEndian, 32),
+        b"fl64" | b"FL64" => (core_audio::kAudioFormatFlagIsFloat | core_audio::kAudioFormatFlagIsBigEndian, 64),
+        _ => return Err(Error::AudioFile(AudioFileError::UnsupportedDataFormat)),
+    };
+    if bits == 0 || bits > 32 && flags & core_audio::kAudioFormatFlagIsFloat == 0 {
+        return Err(Error::AudioFile(AudioFileError::UnsupportedDataFormat));
+    }
+    let container_bytes = bits.div_ceil(8);
+    flags |= if bits == container_bytes * 8 {
+        core_audio::kAudioFormatFlagIsPacked
+    }
+    else {
+        core_audio::kAudioFormatFlagIsAlignedHigh
+    };
+
+    let frame_bytes = container_bytes * comm.channels as u32;
+    Ok(core_audio::AudioStreamBasicDescription {
+        mSampleRate: comm.sample_rate,
+        mFormatID: core_audio::kAudioFormatLinearPCM,
+        mFormatFlags: flags as u32,
+        mBytesPerPacket: frame_bytes,
+        mFramesPerPacket: 1,
+        mBytesPerFrame: frame_bytes,
+        mChannelsPerFrame: comm.channels as u32,
+        mBitsPerChannel: bits,
+        mReserved: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{extended_to_f64, parse, sniff};
+    use backend::portable::test_files::{assert_error, first_sample, iff_chunk};
+    use core_audio;
+    use error::AudioFileError;
+
+    fn be32(value: u32) -> [u8; 4] {
+        [(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]
+    }
+
+    /// 80 bit extended encodings of common sample rates
+    const RATE_44100: [u8; 10] = [0x40, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0];
+    const RATE_48000: [u8; 10] = [0x40, 0x0e, 0xbb, 0x80, 0, 0, 0, 0, 0, 0];
+
+    fn comm(channels: u16, frames: u32, bits: u16, rate: [u8; 10], compression: Option<&[u8]>) -> Vec<u8> {
+        let mut body = vec![(channels >> 8) as u8, channels as u8];
+        body.extend_from_slice(&be32(frames));
+        body.extend_from_slice(&[(bits >> 8) as u8, bits as u8]);
+        body.extend_from_slice(&rate);
+        if let Some(compression) = compression {
+            body.extend_from_slice(compression);
+            // the pascal string naming it
+            body.extend_from_slice(&[3, b'a', b'b', b'c']);
+        }
+        iff_chunk(b"COMM", &body)
+    }
+
+    fn ssnd(offset: u32, data: &[u8]) -> Vec<u8> {
+        let mut body = be32(offset).to_vec();
+        body.extend_from_slice(&be32(0));
+        body.extend_from_slice(&vec![0xee; offset as usize]);
+        body.extend_from_slice(data);
+        iff_chunk(b"SSND", &body)
+    }
+
+    fn form(form_type: &[u8], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = chunks.iter().flat_map(|chunk| chunk.iter().cloned()).collect();
+        let mut bytes = b"FORM".to_vec();
+        bytes.extend_from_slice(&be32(body.len() as u32 + 4));
+        bytes.extend_from_slice(form_type);
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    #[test]
+    fn decodes_extended_sample_rates() {
+        assert_eq!(extended_to_f64(&RATE_44100).unwrap(), 44100.0);
+        assert_eq!(extended_to_f64(&RATE_48000).unwrap(), 48000.0);
+        assert_eq!(extended_to_f64(&[0x40, 0x0f, 0xfa, 0, 0, 0, 0, 0, 0, 0]).unwrap(), 128000.0);
+        assert_eq!(extended_to_f64(&[0x3f, 0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0]).unwrap(), 0.5);
+        assert_eq!(extended_to_f64(&[0; 10]).unwrap(), 0.0);
+        assert!(extended_to_f64(&[0x7f, 0xff, 0x80, 0, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn reads_big_endian_pcm() {
+        let bytes = form(b"AIFF", &[comm(2, 2, 16, RATE_44100, None),
+                                    ssnd(4, &[0x40, 0, 0xc0, 0, 0, 1, 0, 2])]);
+        assert!(sniff(&bytes));
+        let file = parse(&bytes).unwrap();
+        assert_eq!(file.format.mSampleRate, 44100.0);
+        assert_eq!(file.format.mFormatFlags,
+                   (core_audio::kAudioFormatFlagIsSignedInteger | core_audio::kAudioFormatFlagIsBigEndian |
+                    core_audio::kAudioFormatFlagIsPacked) as u32);
+        assert_eq!(file.format.mBytesPerFrame, 4);
+        assert_eq!(file.format.mChannelsPerFrame, 2);
+        assert_eq!(file.format.mBitsPerChannel, 16);
+        assert_eq!(file.packet_count, 2);
+        assert_eq!(file.audio_data, vec![0x40, 0, 0xc0, 0, 0, 1, 0, 2]);
+        assert_eq!(first_sample(&parse(&bytes).unwrap()), 0.5);
+    }
+
+    #[test]
+    fn reads_odd_sample_sizes_aligned_high() {
+        // 12 bit samples take two bytes
+        let bytes = form(b"AIFF", &[comm(1, 1, 12, RATE_48000, None), ssnd(0, &[0x40, 0x00])]);
+        let file = parse(&bytes).unwrap();
+        assert_eq!(file.format.mBitsPerChannel, 12);
+        assert_eq!(file.format.mBytesPerFrame, 2);
+        assert_eq!(file.format.mFormatFlags,
+                   (core_audio::kAudioFormatFlagIsSignedInteger | core_audio::kAudioFormatFlagIsBigEndian |
+                    core_audio::kAudioFormatFlagIsAlignedHigh) as u32);
+    }
+
+    #[test]
+    fn reads_aiff_c_compression_types() {
+        let bytes = form(b"AIFC", &[iff_chunk(b"FVER", &be32(0xa2805140)),
+                                    comm(1, 1, 16, RATE_44100, Some(b"sowt")),
+                                    ssnd(0, &[0x00, 0xc0])]);
+        let file = parse(&bytes).unwrap();
+        assert_eq!(file.format.mFormatFlags,
+                   (core_audio::kAudioFormatFlagIsSignedInteger | core_audio::kAudioFormatFlagIsPacked) as u32);
+        assert_eq!(first_sample(&parse(&bytes).unwrap()), -0.5);
+
+        let bytes = form(b"AIFC", &[comm(1, 1, 32, RATE_44100, Some(b"fl32")), ssnd(0, &be32(0.25f32.to_bits()))]);
+        let file = parse(&bytes).unwrap();
+        assert_eq!(file.format.mFormatFlags,
+                   (core_audio::kAudioFormatFlagIsFloat | core_audio::kAudioFormatFlagIsBigEndian |
+                    core_audio::kAudioFormatFlagIsPacked) as u32);
+        assert_eq!(file.format.mBitsPerChannel, 32);
+        assert_eq!(first_sample(&parse(&bytes).unwrap()), 0.25);
+
+        let bits = (-0.75f64).to_bits();
+        let data: Vec<u8> = (0..8).rev().map(|i| (bits >> (i * 8)) as u8).collect();
+        let bytes = form(b"AIFC", &[comm(1, 1, 64, RATE_44100, Some(b"fl64")), ssnd(0, &data)]);
+        let file = parse(&bytes).unwrap();
+        assert_eq!(file.format.mBitsPerChannel, 64);
+        assert_eq!(file.format.mBytesPerFrame, 8);
+        assert_eq!(first_sample(&parse(&bytes).unwrap()), -0.75);
+
+        let bytes = form(b"AIFC", &[comm(1, 1, 16, RATE_44100, Some(b"NONE")), ssnd(0, &[0x40, 0])]);
+        assert_eq!(first_sample(&parse(&bytes).unwrap()), 0.5);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        // no COMM chunk
+        assert_error(parse(&form(b"AIFF", &[ssnd(0, &[0, 0])])), AudioFileError::InvalidFile);
+        // frames but no SSND chunk
+        assert_error(parse(&form(b"AIFF", &[comm(1, 10, 16, RATE_44100, None)])), AudioFileError::InvalidFile);
+        // a COMM chunk too short for its fields
+        assert_error(parse(&form(b"AIFF", &[iff_chunk(b"COMM", &[0, 1, 0, 0]), ssnd(0, &[0, 0])])),
+                     AudioFileError::InvalidChunk);
+        // an SSND offset past the end of the chunk
+        assert_error(parse(&form(b"AIFF", &[comm(1, 1, 16, RATE_44100, None), iff_chunk(b"SSND", &[0, 0, 1, 0, 0, 0, 0, 0])])),
+                     AudioFileError::InvalidChunk);
+        // a compression type we can't decode
+        assert_error(parse(&form(b"AIFC", &[comm(1, 1, 16, RATE_44100, Some(b"ima4")), ssnd(0, &[0; 34])])),
+                     AudioFileError::UnsupportedDataFormat);
+        // no channels
+        assert_error(parse(&form(b"AIFF", &[comm(0, 0, 16, RATE_44100, None)])), AudioFileError::InvalidFile);
+    }
+}
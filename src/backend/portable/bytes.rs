@@ -0,0 +1,68 @@
+//! Reading the fixed size fields container formats are made of.
+
+use error::{AudioFileError, Error};
+
+/// Reads fields off the front of a byte slice. Running out of bytes means whatever is being read
+/// is cut short, which is reported as `InvalidChunk`.
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+
+    pub fn new(bytes: &'a [u8]) -> ByteReader<'a> {
+        ByteReader { bytes }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn take(&mut self, count: usize) -> Result<&'a [u8], Error> {
+        if count > self.bytes.len() {
+            return Err(Error::AudioFile(AudioFileError::InvalidChunk));
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub fn skip(&mut self, count: usize) -> Result<(), Error> {
+        self.take(count).map(|_| ())
+    }
+
+    pub fn fourcc(&mut self) -> Result<[u8; 4], Error> {
+        let bytes = try!(self.take(4));
+        Ok([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    pub fn u16_le(&mut self) -> Result<u16, Error> {
+        Ok(try!(self.uint(2, false)) as u16)
+    }
+
+    pub fn u32_le(&mut self) -> Result<u32, Error> {
+        Ok(try!(self.uint(4, false)) as u32)
+    }
+
+    pub fn u16_be(&mut self) -> Result<u16, Error> {
+        Ok(try!(self.uint(2, true)) as u16)
+    }
+
+    pub fn u32_be(&mut self) -> Result<u32, Error> {
+        Ok(try!(self.uint(4, true)) as u32)
+    }
+
+    pub fn u64_be(&mut self) -> Result<u64, Error> {
+        self.uint(8, true)
+    }
+
+    fn uint(&mut self, size: usize, big_endian: bool) -> Result<u64, Error> {
+        let bytes = try!(self.take(size));
+        let mut value: u64 = 0;
+        for i in 0..size {
+            let byte = if big_endian { bytes[i] } else { bytes[size - 1 - i] };
+            value = (value << 8) | byte as u64;
+        }
+        Ok(value)
+    }
+}
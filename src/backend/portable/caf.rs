@@ -0,0 +1,441 @@
+//! Core Audio Format files. Any format can be described and its packets located, but only linear
+//! PCM can be decoded.
+
+use backend::portable::bytes::ByteReader;
+use backend::portable::file::{FileData, Marker, PacketDescription, PacketTable};
+use channel_layout::{ChannelDescription, ChannelLabel, ChannelLayout, ChannelLayoutTag};
+use core_audio;
+use error::{AudioFileError, Error};
+
+/// kCAFLinearPCMFormatFlagIsFloat
+const CAF_FLAG_IS_FLOAT: u32 = 1 << 0;
+/// kCAFLinearPCMFormatFlagIsLittleEndian
+const CAF_FLAG_IS_LITTLE_ENDIAN: u32 = 1 << 1;
+
+/// The size of a `data` chunk whose size was never filled in, which runs to the end of the file.
+const UNKNOWN_SIZE: i64 = -1;
+
+/// Whether `bytes` starts like a CAF file.
+pub fn sniff(bytes: &[u8]) -> bool {
+    bytes.len() >= 8 && &bytes[0..4] == b"caff"
+}
+
+pub fn parse(bytes: &[u8]) -> Result<FileData, Error> {
+    let mut reader = ByteReader::new(bytes);
+    try!(reader.skip(4)); // caff
+    if try!(reader.u16_be()) != 1 {
+        return Err(Error::AudioFile(AudioFileError::UnsupportedFileType));
+    }
+    try!(reader.skip(2)); // flags
+
+    let mut format = None;
+    let mut audio_data = None;
+    let mut pakt = None;
+    let mut channel_layout = None;
+    let mut magic_cookie = Vec::new();
+    let mut info = Vec::new();
+    let mut markers = Vec::new();
+    while reader.remaining() >= 12 {
+        let id = try!(reader.fourcc());
+        let size = try!(reader.u64_be()) as i64;
+        let size = if id == *b"data" && size == UNKNOWN_SIZE {
+            reader.remaining()
+        }
+        else if size < 0 {
+            return Err(Error::AudioFile(AudioFileError::InvalidChunk));
+        }
+        else {
+            size as usize
+        };
+        let body = try!(reader.take(size));
+        match &id {
+            b"desc" => format = Some(try!(parse_desc(body))),
+            // the data starts with an edit count we've no use for
+            b"data" => {
+                let mut data = ByteReader::new(body);
+                try!(data.skip(4));
+                audio_data = Some(&body[4..]);
+            },
+            b"pakt" => pakt = Some(body),
+            b"chan" => channel_layout = Some(try!(parse_chan(body))),
+            b"kuki" => magic_cookie = body.to_vec(),
+            b"info" => info = try!(parse_info(body)),
+            b"mark" => markers = try!(parse_mark(body)),
+            _ => (),
+        }
+    }
+
+    // the description has to come first, but be lenient about where it is as long as it's there
+    let format = try!(format.ok_or(Error::AudioFile(AudioFileError::InvalidFile)));
+    let audio_data = try!(audio_data.ok_or(Error::AudioFile(AudioFileError::InvalidFile)));
+    let mut file = FileData::new(format, 0, Vec::new());
+    file.channel_layout = channel_layout;
+    file.magic_cookie = magic_cookie;
+    file.info = info;
+    file.markers = markers;
+    let bytes_per_packet = file.format.mBytesPerPacket as usize;
+    match pakt {
+        Some(pakt) => {
+            let (packet_count, table, descriptions) = try!(parse_pakt(pakt, &file.format));
+            file.packet_count = packet_count;
+            file.packet_table = Some(table);
+            file.packet_descriptions = descriptions;
+        },
+        // a variable bit rate file can't be read without its packet table
+        None if bytes_per_packet == 0 || file.format.mFramesPerPacket == 0 =>
+            return Err(Error::AudioFile(AudioFileError::InvalidFile)),
+        None => file.packet_count = (audio_data.len() / bytes_per_packet) as u64,
+    }
+
+    let data_bytes = match file.packet_descriptions.last() {
+        Some(last) => (last.start_offset + last.data_byte_size as i64) as u64,
+        // a packet count no data could hold is as wrong as one past the end of the data
+        None => try!(file.packet_count.checked_mul(bytes_per_packet as u64)
+                                      .ok_or(Error::AudioFile(AudioFileError::InvalidChunk))),
+    };
+    if data_bytes > audio_data.len() as u64 {
+        return Err(Error::AudioFile(AudioFileError::InvalidPacketOffset));
+    }
+    file.audio_data = audio_data[..data_bytes as usize].to_vec();
+    Ok(file)
+}
+
+/// Reads a CAFAudioDescription, turning CAF's own linear PCM flags into Core Audio's.
+fn parse_desc(body: &[u8]) -> Result<core_audio::AudioStreamBasicDescription, Error> {
+    let mut reader = ByteReader::new(body);
+    let sample_rate = f64::from_bits(try!(reader.u64_be()));
+    let format_id = try!(reader.u32_be());
+    let caf_flags = try!(reader.u32_be());
+    let bytes_per_packet = try!(reader.u32_be());
+    let frames_per_packet = try!(reader.u32_be());
+    let channels = try!(reader.u32_be());
+    let bits = try!(reader.u32_be());
+    if sample_rate.is_nan() || sample_rate <= 0.0 || channels == 0 {
+        return Err(Error::AudioFile(AudioFileError::InvalidFile));
+    }
+
+    let (flags, bytes_per_frame) = if format_id == core_audio::kAudioFormatLinearPCM {
+        if frames_per_packet != 1 || bytes_per_packet == 0 || bits == 0 {
+            return Err(Error::AudioFile(AudioFileError::InvalidFile));
+        }
+        let mut flags = if caf_flags & CAF_FLAG_IS_FLOAT != 0 {
+            core_audio::kAudioFormatFlagIsFloat
+        }
+        else {
+            core_audio::kAudioFormatFlagIsSignedInteger
+        };
+        if caf_flags & CAF_FLAG_IS_LITTLE_ENDIAN == 0 {
+            flags |= core_audio::kAudioFormatFlagIsBigEndian;
+        }
+        if bits == bytes_per_packet / channels * 8 {
+            flags |= core_audio::kAudioFormatFlagIsPacked;
+        }
+        else {
+            flags |= core_audio::kAudioFormatFlagIsAlignedHigh;
+        }
+        (flags as u32, bytes_per_packet)
+    }
+    else {
+        // compressed formats keep their own flags and have no frames of fixed size
+        (caf_flags, 0)
+    };
+
+    Ok(core_audio::AudioStreamBasicDescription {
+        mSampleRate: sample_rate,
+        mFormatID: format_id,
+        mFormatFlags: flags,
+        mBytesPerPacket: bytes_per_packet,
+        mFramesPerPacket: frames_per_packet,
+        mBytesPerFrame: bytes_per_frame,
+        mChannelsPerFrame: channels,
+        mBitsPerChannel: bits,
+        mReserved: 0,
+    })
+}
+
+/// Reads a CAFPacketTableHeader and, for formats whose packets vary in size or length, the
+/// packet sizes that follow it.
+fn parse_pakt(body: &[u8], format: &core_audio::AudioStreamBasicDescription)
+              -> Result<(u64, PacketTable, Vec<PacketDescription>), Error> {
+    let mut reader = ByteReader::new(body);
+    let packet_count = try!(reader.u64_be()) as i64;
+    let table = PacketTable {
+        valid_frames: try!(reader.u64_be()) as i64,
+        priming_frames: try!(reader.u32_be()) as i32,
+        remainder_frames: try!(reader.u32_be()) as i32,
+    };
+    if packet_count < 0 || table.valid_frames < 0 || table.priming_frames < 0 || table.remainder_frames < 0 {
+        return Err(Error::AudioFile(AudioFileError::InvalidChunk));
+    }
+
+    let mut descriptions = Vec::new();
+    if format.mBytesPerPacket == 0 || format.mFramesPerPacket == 0 {
+        // every packet's size or length takes at least a byte, so the rest of the chunk says how
+        // many packets there can be
+        if packet_count as u64 > reader.remaining() as u64 {
+            return Err(Error::AudioFile(AudioFileError::InvalidChunk));
+        }
+        let mut start_offset = 0;
+        for _ in 0..packet_count {
+            let data_byte_size = if format.mBytesPerPacket == 0 {
+                try!(variable_length_int(&mut reader))
+            }
+            else {
+                format.mBytesPerPacket
+            };
+            let variable_frames = if format.mFramesPerPacket == 0 {
+                try!(variable_length_int(&mut reader))
+            }
+            else {
+                0
+            };
+            descriptions.push(PacketDescription {
+                start_offset,
+                variable_frames,
+                data_byte_size,
+            });
+            start_offset += data_byte_size as i64;
+        }
+    }
+    Ok((packet_count as u64, table, descriptions))
+}
+
+/// Reads one of the packet table's integers, stored seven bits to a byte, most significant first,
+/// with the top bit set on every byte but the last.
+fn variable_length_int(reader: &mut ByteReader) -> Result<u32, Error> {
+    let mut value: u64 = 0;
+    loop {
+        let byte = try!(reader.take(1))[0];
+        value = (value << 7) | (byte & 0x7f) as u64;
+        if value > u32::MAX as u64 {
+            return Err(Error::AudioFile(AudioFileError::InvalidChunk));
+        }
+        if byte & 0x80 == 0 {
+            return Ok(value as u32);
+        }
+    }
+}
+
+/// Reads a CAFChannelLayout.
+fn parse_chan(body: &[u8]) -> Result<ChannelLayout, Error> {
+    let mut reader = ByteReader::new(body);
+    let tag = try!(reader.u32_be());
+    let bitmap = try!(reader.u32_be());
+    let count = try!(reader.u32_be());
+    let mut descriptions = Vec::new();
+    for _ in 0..count {
+        let label = ChannelLabel(try!(reader.u32_be()));
+        let flags = try!(reader.u32_be());
+        let mut coordinates = [0.0; 3];
+        for coordinate in coordinates.iter_mut() {
+            *coordinate = f32::from_bits(try!(reader.u32_be()));
+        }
+        descriptions.push(ChannelDescription { label, flags, coordinates });
+    }
+    Ok(ChannelLayout { tag: ChannelLayoutTag(tag), bitmap, descriptions })
+}
+
+/// Reads a CAFStringsChunk: a count followed by that many pairs of null terminated UTF-8 strings.
+fn parse_info(body: &[u8]) -> Result<Vec<(String, String)>, Error> {
+    let mut reader = ByteReader::new(body);
+    let count = try!(reader.u32_be());
+    let mut strings = body[4..].split(|&byte| byte == 0).map(|string| String::from_utf8_lossy(string).into_owned());
+    let mut info = Vec::new();
+    for _ in 0..count {
+        match (strings.next(), strings.next()) {
+            (Some(key), Some(value)) => info.push((key, value)),
+            _ => return Err(Error::AudioFile(AudioFileError::InvalidChunk)),
+        }
+    }
+    Ok(info)
+}
+
+/// Reads a CAFMarkerChunk.
+fn parse_mark(body: &[u8]) -> Result<Vec<Marker>, Error> {
+    let mut reader = ByteReader::new(body);
+    try!(reader.skip(4)); // SMPTE time type
+    let count = try!(reader.u32_be());
+    let mut markers = Vec::new();
+    for _ in 0..count {
+        let kind = try!(reader.u32_be());
+        let frame_position = f64::from_bits(try!(reader.u64_be()));
+        let id = try!(reader.u32_be()) as i32;
+        try!(reader.skip(8)); // SMPTE time
+        let channel = try!(reader.u32_be());
+        markers.push(Marker { kind, frame_position, id, channel });
+    }
+    Ok(markers)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{parse, sniff};
+    use backend::portable::file::{Marker, PacketDescription, PacketTable};
+    use backend::portable::pcm;
+    use channel_layout::{ChannelDescription, ChannelLabel, ChannelLayoutTag};
+    use backend::portable::test_files::{assert_error, caf_chunk};
+    use core_audio;
+    use error::AudioFileError;
+
+    fn be32(value: u32) -> Vec<u8> {
+        (0..4).rev().map(|i| (value >> (i * 8)) as u8).collect()
+    }
+
+    fn be64(value: u64) -> Vec<u8> {
+        (0..8).rev().map(|i| (value >> (i * 8)) as u8).collect()
+    }
+
+    fn desc(rate: f64, format_id: &[u8], flags: u32, bytes_per_packet: u32, frames_per_packet: u32,
+            channels: u32, bits: u32) -> Vec<u8> {
+        let mut body = be64(rate.to_bits());
+        body.extend_from_slice(format_id);
+        for &field in &[flags, bytes_per_packet, frames_per_packet, channels, bits] {
+            body.extend(be32(field));
+        }
+        caf_chunk(b"desc", &body)
+    }
+
+    fn data(audio: &[u8]) -> Vec<u8> {
+        let mut body = be32(0);
+        body.extend_from_slice(audio);
+        caf_chunk(b"data", &body)
+    }
+
+    fn caff(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = b"caff\x00\x01\x00\x00".to_vec();
+        for chunk in chunks {
+            bytes.extend_from_slice(chunk);
+        }
+        bytes
+    }
+
+    #[test]
+    fn reads_linear_pcm() {
+        // big endian 16 bit stereo
+        let bytes = caff(&[desc(48000.0, b"lpcm", 0, 4, 1, 2, 16), data(&[0x40, 0, 0xc0, 0, 0, 1, 0, 2])]);
+        assert!(sniff(&bytes));
+        let file = parse(&bytes).unwrap();
+        assert_eq!(file.format.mSampleRate, 48000.0);
+        assert_eq!(file.format.mFormatFlags,
+                   (core_audio::kAudioFormatFlagIsSignedInteger | core_audio::kAudioFormatFlagIsBigEndian |
+                    core_audio::kAudioFormatFlagIsPacked) as u32);
+        assert_eq!(file.format.mBytesPerFrame, 4);
+        assert_eq!(file.packet_count, 2);
+        let mut buffer = vec![vec![0.0; 2]; 2];
+        pcm::decode(&file.format, &file.audio_data, &mut buffer, 0);
+        assert_eq!(buffer[0][0], 0.5);
+        assert_eq!(buffer[1][0], -0.5);
+
+        // little endian float, with a data chunk whose size was never filled in
+        let mut bytes = caff(&[desc(44100.0, b"lpcm", 3, 4, 1, 1, 32)]);
+        bytes.extend_from_slice(b"data\xff\xff\xff\xff\xff\xff\xff\xff\x00\x00\x00\x00");
+        bytes.extend((0..4).map(|i| (0.25f32.to_bits() >> (i * 8)) as u8));
+        let file = parse(&bytes).unwrap();
+        assert_eq!(file.format.mFormatFlags,
+                   (core_audio::kAudioFormatFlagIsFloat | core_audio::kAudioFormatFlagIsPacked) as u32);
+        assert_eq!(file.packet_count, 1);
+        let mut buffer = vec![vec![0.0; 1]];
+        pcm::decode(&file.format, &file.audio_data, &mut buffer, 0);
+        assert_eq!(buffer[0][0], 0.25);
+    }
+
+    #[test]
+    fn reads_variable_packet_sizes_from_the_packet_table() {
+        // three AAC packets of 1024 frames, 2112 frames of priming and 448 of remainder
+        let mut pakt = be64(3);
+        pakt.extend(be64(3 * 1024 - 2112 - 448));
+        pakt.extend(be32(2112));
+        pakt.extend(be32(448));
+        // 6, 300 and 200 bytes
+        pakt.extend_from_slice(&[0x06, 0x82, 0x2c, 0x81, 0x48]);
+        let bytes = caff(&[desc(44100.0, b"aac ", 0, 0, 1024, 2, 0),
+                           caf_chunk(b"kuki", &[1, 2, 3]),
+                           caf_chunk(b"pakt", &pakt),
+                           data(&vec![0; 506])]);
+        let file = parse(&bytes).unwrap();
+        assert_eq!(file.format.mFormatID, u32::from_be_bytes(*b"aac "));
+        assert_eq!(file.format.mFramesPerPacket, 1024);
+        assert_eq!(file.packet_count, 3);
+        assert_eq!(file.packet_table, Some(PacketTable { valid_frames: 512, priming_frames: 2112, remainder_frames: 448 }));
+        assert_eq!(file.packet_descriptions,
+                   vec![PacketDescription { start_offset: 0, variable_frames: 0, data_byte_size: 6 },
+                        PacketDescription { start_offset: 6, variable_frames: 0, data_byte_size: 300 },
+                        PacketDescription { start_offset: 306, variable_frames: 0, data_byte_size: 200 }]);
+        assert_eq!(file.magic_cookie, vec![1, 2, 3]);
+        assert_eq!(file.audio_data.len(), 506);
+
+        // packets that claim more bytes than there are
+        let bytes = caff(&[desc(44100.0, b"aac ", 0, 0, 1024, 2, 0), caf_chunk(b"pakt", &pakt), data(&[0; 400])]);
+        assert_error(parse(&bytes), AudioFileError::InvalidPacketOffset);
+    }
+
+    #[test]
+    fn reads_channel_layout_info_and_markers() {
+        let mut chan = be32(0); // kAudioChannelLayoutTag_UseChannelDescriptions
+        chan.extend(be32(0));
+        chan.extend(be32(1));
+        chan.extend(be32(3)); // kAudioChannelLabel_Center
+        chan.extend(be32(0));
+        for coordinate in &[0.0f32, 1.0, -1.0] {
+            chan.extend(be32(coordinate.to_bits()));
+        }
+
+        let mut info = be32(2);
+        info.extend_from_slice(b"title\0Tone\0artist\0Nobody\0");
+
+        let mut mark = be32(0);
+        mark.extend(be32(1));
+        mark.extend(be32(u32::from_be_bytes(*b"rgn ")));
+        mark.extend(be64(100.0f64.to_bits()));
+        mark.extend(be32(7));
+        mark.extend(vec![0; 8]);
+        mark.extend(be32(0));
+
+        let bytes = caff(&[desc(44100.0, b"lpcm", 0, 2, 1, 1, 16),
+                           caf_chunk(b"chan", &chan),
+                           caf_chunk(b"info", &info),
+                           caf_chunk(b"mark", &mark),
+                           data(&[0, 0])]);
+        let file = parse(&bytes).unwrap();
+        let layout = file.channel_layout.unwrap();
+        assert_eq!(layout.tag, ChannelLayoutTag::USE_CHANNEL_DESCRIPTIONS);
+        assert_eq!(layout.descriptions, vec![ChannelDescription { label: ChannelLabel::CENTER, flags: 0, coordinates: [0.0, 1.0, -1.0] }]);
+        assert_eq!(file.info, vec![("title".to_string(), "Tone".to_string()),
+                                   ("artist".to_string(), "Nobody".to_string())]);
+        assert_eq!(file.markers, vec![Marker { kind: u32::from_be_bytes(*b"rgn "), frame_position: 100.0, id: 7, channel: 0 }]);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        // no description
+        assert_error(parse(&caff(&[data(&[0, 0])])), AudioFileError::InvalidFile);
+        // no data
+        assert_error(parse(&caff(&[desc(44100.0, b"lpcm", 0, 2, 1, 1, 16)])), AudioFileError::InvalidFile);
+        // variable bit rate without a packet table
+        assert_error(parse(&caff(&[desc(44100.0, b"aac ", 0, 0, 1024, 2, 0), data(&[0; 8])])),
+                     AudioFileError::InvalidFile);
+        // a chunk running off the end
+        let mut bytes = caff(&[desc(44100.0, b"lpcm", 0, 2, 1, 1, 16)]);
+        bytes.extend(caf_chunk(b"data", &[0; 8])[..18].to_vec());
+        assert_error(parse(&bytes), AudioFileError::InvalidChunk);
+        // a version we don't know
+        assert_error(parse(b"caff\x00\x02\x00\x00"), AudioFileError::UnsupportedFileType);
+
+        // packet tables counting more packets than could ever be there
+        let pakt = |packet_count: u64| {
+            let mut pakt = be64(packet_count);
+            pakt.extend(be64(0));
+            pakt.extend(be32(0));
+            pakt.extend(be32(0));
+            pakt.extend_from_slice(&[0x06, 0x06]);
+            caf_chunk(b"pakt", &pakt)
+        };
+        let bytes = caff(&[desc(44100.0, b"lpcm", 0, 4, 1, 2, 16), pakt(1 << 62), data(&[0; 8])]);
+        assert_error(parse(&bytes), AudioFileError::InvalidChunk);
+        let bytes = caff(&[desc(44100.0, b"aac ", 0, 0, 1024, 2, 0), pakt(1 << 62), data(&[0; 12])]);
+        assert_error(parse(&bytes), AudioFileError::InvalidChunk);
+        let bytes = caff(&[desc(44100.0, b"ac-3", 0, 6, 0, 2, 0), pakt(3), data(&[0; 18])]);
+        assert_error(parse(&bytes), AudioFileError::InvalidChunk);
+    }
+}
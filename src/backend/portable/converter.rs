@@ -0,0 +1,226 @@
+//! Sample rate conversion in the portable backend: kAudioUnitSubType_AUConverter, and the
+//! resampling the outputs do when the device runs at a different rate to what's fed in.
+//!
+//! A unit that resamples pulls its input at the input's rate, so it asks the engine for however
+//! many frames the resampler needs to render the cycle, with sample times counted on the input's
+//! side. How well it resamples is kAudioUnitProperty_RenderQuality.
+
+use backend::portable::unit::{Buffer, Formats, RenderContext, Unit};
+use core_audio;
+use error::{AudioUnitError, Error};
+use property::{self, Property, PropertyValue};
+use resampler::{Quality, Resampler};
+use std::any::Any;
+use std::borrow::Cow;
+use stream_format::StreamFormat;
+
+/// Resamples a unit's input to its output's rate as the unit is pulled.
+pub struct RateConverter {
+    quality: Quality,
+    /// made when the rates first differ, and again whenever the formats change
+    resampler: Option<Resampler>,
+    /// where rendering started, on the output's timeline and the input's
+    start: Option<(f64, f64)>,
+    /// the sample time of the next output cycle, if rendering carries on from the last one
+    next_time: f64,
+    /// the sample time of the next input frame to pull
+    input_time: f64,
+}
+
+impl RateConverter {
+
+    pub fn new() -> RateConverter {
+        RateConverter { quality: Quality::High, resampler: None, start: None, next_time: 0.0, input_time: 0.0 }
+    }
+
+    /// Throws away the input waiting to be resampled.
+    pub fn reset(&mut self) {
+        self.resampler = None;
+        self.start = None;
+    }
+
+    /// What to pull from the input to render `context`, making the resampler if the formats need
+    /// one, or the error making it gave. Rendering from anywhere other than where the last cycle
+    /// left off starts afresh.
+    pub fn input_context(&mut self, input: &core_audio::AudioStreamBasicDescription,
+                         output: &core_audio::AudioStreamBasicDescription,
+                         context: &RenderContext) -> Result<RenderContext, Error> {
+        if self.start.is_none() || context.sample_time != self.next_time {
+            let input_start = context.sample_time * input.mSampleRate / output.mSampleRate;
+            self.start = Some((context.sample_time, input_start));
+            self.input_time = input_start;
+            self.resampler = None;
+        }
+        self.next_time = context.sample_time + context.frames as f64;
+
+        let frames = if input.mSampleRate == output.mSampleRate {
+            self.resampler = None;
+            context.frames
+        }
+        else {
+            let fits = self.resampler.as_ref().is_some_and(|resampler| {
+                resampler.from_rate() == input.mSampleRate && resampler.to_rate() == output.mSampleRate &&
+                    resampler.channels() == input.mChannelsPerFrame as usize && resampler.quality() == self.quality
+            });
+            if !fits {
+                self.resampler = Some(try!(Resampler::new(input.mChannelsPerFrame as usize, input.mSampleRate,
+                                                          output.mSampleRate, self.quality)));
+            }
+            self.resampler.as_ref().map_or(0, |resampler| resampler.input_frames_needed(context.frames))
+        };
+        let input_context = RenderContext { sample_time: self.input_time, frames };
+        self.input_time += frames as f64;
+        Ok(input_context)
+    }
+
+    /// Checks the formats can be resampled between, so that an output rate nothing can convert
+    /// to fails when the unit's initialized rather than silently rendering nothing.
+    pub fn check(&self, input: &core_audio::AudioStreamBasicDescription,
+                 output: &core_audio::AudioStreamBasicDescription) -> Result<(), Error> {
+        if input.mSampleRate != output.mSampleRate {
+            try!(Resampler::new(input.mChannelsPerFrame as usize, input.mSampleRate, output.mSampleRate,
+                                self.quality));
+        }
+        Ok(())
+    }
+
+    /// The input's sample time for `sample_time` on the output.
+    pub fn input_sample_time(&self, sample_time: f64, input: &core_audio::AudioStreamBasicDescription,
+                             output: &core_audio::AudioStreamBasicDescription) -> f64 {
+        match self.start {
+            Some((output_start, input_start)) =>
+                input_start + (sample_time - output_start) * input.mSampleRate / output.mSampleRate,
+            None => sample_time * input.mSampleRate / output.mSampleRate,
+        }
+    }
+
+    /// `input`, pulled for the context `input_context` gave, as `frames` frames at the output's
+    /// rate.
+    pub fn convert<'b>(&mut self, input: Option<&'b Buffer>, frames: usize) -> Result<Option<Cow<'b, Buffer>>, Error> {
+        let input = match input {
+            Some(input) => input,
+            None => return Ok(None),
+        };
+        match self.resampler {
+            Some(ref mut resampler) => {
+                try!(resampler.write(input));
+                let mut output = vec![vec![0.0; frames]; resampler.channels()];
+                resampler.read(&mut output, 0);
+                Ok(Some(Cow::Owned(output)))
+            },
+            // the rates match
+            None if input.iter().all(|channel| channel.len() >= frames) => Ok(Some(Cow::Borrowed(input))),
+            None => Ok(None),
+        }
+    }
+
+    /// kAudioUnitProperty_RenderQuality, in the global scope
+    pub fn get_property(&self, scope: core_audio::AudioUnitScope) -> Result<Vec<u8>, Error> {
+        if scope != core_audio::kAudioUnitScope_Global {
+            return Err(Error::AudioUnit(AudioUnitError::InvalidScope));
+        }
+        Ok(self.quality.render_quality().to_bytes())
+    }
+
+    pub fn set_property(&mut self, scope: core_audio::AudioUnitScope, data: &[u8]) -> Result<(), Error> {
+        if scope != core_audio::kAudioUnitScope_Global {
+            return Err(Error::AudioUnit(AudioUnitError::InvalidScope));
+        }
+        self.quality = Quality::from_render_quality(try!(u32::from_bytes(data)));
+        Ok(())
+    }
+}
+
+/// kAudioUnitSubType_AUConverter: resamples its input to the rate of its output format. Only the
+/// rate is taken from a format set on the output, which otherwise follows the input's, channels and
+/// all, as soon as the input's is set so that it flows on down the graph.
+pub struct Converter {
+    formats: Formats,
+    /// the output's rate, once it's been set
+    sample_rate: Option<f64>,
+    rate: RateConverter,
+}
+
+impl Converter {
+
+    pub fn new() -> Converter {
+        Converter { formats: Formats::new(1, 1), sample_rate: None, rate: RateConverter::new() }
+    }
+
+    fn output_format(&self) -> core_audio::AudioStreamBasicDescription {
+        self.formats.get(core_audio::kAudioUnitScope_Output, 0).unwrap_or(*self.formats.input(0))
+    }
+}
+
+impl Unit for Converter {
+
+    fn input_count(&self) -> u32 {
+        1
+    }
+
+    fn get_format(&self, scope: core_audio::AudioUnitScope,
+                  element: core_audio::AudioUnitElement) -> Result<core_audio::AudioStreamBasicDescription, Error> {
+        self.formats.get(scope, element)
+    }
+
+    fn set_format(&mut self, scope: core_audio::AudioUnitScope, element: core_audio::AudioUnitElement,
+                  format: &core_audio::AudioStreamBasicDescription) -> Result<(), Error> {
+        try!(self.formats.set(scope, element, format));
+        if scope == core_audio::kAudioUnitScope_Output {
+            self.sample_rate = Some(format.mSampleRate);
+        }
+        let input = *self.formats.input(0);
+        let output = match self.sample_rate {
+            Some(sample_rate) =>
+                try!(try!(StreamFormat::from_description(&input)).with_sample_rate(sample_rate)).to_description(),
+            None => input,
+        };
+        self.formats.set(core_audio::kAudioUnitScope_Output, 0, &output)
+    }
+
+    fn get_unit_property(&mut self, property_id: core_audio::AudioUnitPropertyID, scope: core_audio::AudioUnitScope,
+                         _element: core_audio::AudioUnitElement) -> Result<Vec<u8>, Error> {
+        match property_id {
+            property::RenderQuality::ID => self.rate.get_property(scope),
+            _ => Err(Error::AudioUnit(AudioUnitError::InvalidProperty)),
+        }
+    }
+
+    fn set_unit_property(&mut self, property_id: core_audio::AudioUnitPropertyID, scope: core_audio::AudioUnitScope,
+                         _element: core_audio::AudioUnitElement, data: &[u8]) -> Result<(), Error> {
+        match property_id {
+            property::RenderQuality::ID => self.rate.set_property(scope, data),
+            _ => Err(Error::AudioUnit(AudioUnitError::InvalidProperty)),
+        }
+    }
+
+    fn initialize(&mut self) -> Result<(), Error> {
+        self.rate.check(self.formats.input(0), &self.output_format())
+    }
+
+    fn reset(&mut self) {
+        self.rate.reset();
+    }
+
+    fn input_context(&mut self, context: &RenderContext) -> Result<RenderContext, Error> {
+        let output = self.output_format();
+        self.rate.input_context(self.formats.input(0), &output, context)
+    }
+
+    fn input_sample_time(&self, sample_time: f64) -> f64 {
+        self.rate.input_sample_time(sample_time, self.formats.input(0), &self.output_format())
+    }
+
+    fn render(&mut self, context: &RenderContext, inputs: &[Option<Buffer>], output: &mut Buffer) -> Result<(), Error> {
+        if let Some(input) = try!(self.rate.convert(inputs[0].as_ref(), context.frames)) {
+            for (out, channel) in output.iter_mut().zip(input.iter()) {
+                out.copy_from_slice(&channel[..context.frames]);
+            }
+        }
+        Ok(())
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
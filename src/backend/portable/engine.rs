@@ -0,0 +1,176 @@
+//! The portable backend's graph: nodes, their connections, and pulling audio through them.
+
+use backend::portable::converter::Converter;
+use backend::portable::file_player::FilePlayer;
+use backend::portable::mixer::MultiChannelMixer;
+use backend::portable::NULL_OUTPUT_SUB_TYPE;
+use backend::portable::output::Output;
+use backend::portable::unit::{Buffer, RenderContext, Unit};
+use core_audio;
+use error::{Error, GraphError};
+
+/// AUGraphConnectNodeInput's arguments.
+#[derive(Copy, Clone, Debug)]
+struct Connection {
+    source: core_audio::AUNode,
+    source_output: u32,
+    dest: core_audio::AUNode,
+    dest_input: u32,
+}
+
+struct Node {
+    description: core_audio::AudioComponentDescription,
+    unit: Box<dyn Unit>,
+}
+
+/// Everything the graph owns. It lives behind a mutex so the output's render thread can pull on
+/// it while the graph's units are being configured.
+pub struct Engine {
+    nodes: Vec<Node>,
+    connections: Vec<Connection>,
+    /// the sample time of the next render cycle
+    pub sample_time: f64,
+}
+
+/// Creates the unit for a component, if the portable backend has one.
+fn instantiate(description: &core_audio::AudioComponentDescription) -> Option<Box<dyn Unit>> {
+    match (description.componentType, description.componentSubType, description.componentManufacturer) {
+        (core_audio::kAudioUnitType_Output, core_audio::kAudioUnitSubType_DefaultOutput,
+         core_audio::kAudioUnitManufacturer_Apple) => Some(Box::new(Output::default_output())),
+        (core_audio::kAudioUnitType_Output, NULL_OUTPUT_SUB_TYPE,
+         core_audio::kAudioUnitManufacturer_Apple) => Some(Box::new(Output::null())),
+        (core_audio::kAudioUnitType_Generator, core_audio::kAudioUnitSubType_AudioFilePlayer,
+         core_audio::kAudioUnitManufacturer_Apple) => Some(Box::new(FilePlayer::new())),
+        (core_audio::kAudioUnitType_Mixer, core_audio::kAudioUnitSubType_MultiChannelMixer,
+         core_audio::kAudioUnitManufacturer_Apple) => Some(Box::new(MultiChannelMixer::new())),
+        (core_audio::kAudioUnitType_FormatConverter, core_audio::kAudioUnitSubType_AUConverter,
+         core_audio::kAudioUnitManufacturer_Apple) => Some(Box::new(Converter::new())),
+        _ => None,
+    }
+}
+
+impl Engine {
+
+    pub fn new() -> Engine {
+        Engine { nodes: Vec::new(), connections: Vec::new(), sample_time: 0.0 }
+    }
+
+    pub fn add_node(&mut self, description: &core_audio::AudioComponentDescription) -> Result<core_audio::AUNode, Error> {
+        let unit = try!(instantiate(description).ok_or(Error::NoMatchingDefaultAudioUnitFound));
+        self.nodes.push(Node { description: *description, unit });
+        Ok((self.nodes.len() - 1) as core_audio::AUNode)
+    }
+
+    pub fn unit(&mut self, node: core_audio::AUNode) -> Result<&mut dyn Unit, Error> {
+        if node < 0 {
+            return Err(Error::Graph(GraphError::NodeNotFound));
+        }
+        match self.nodes.get_mut(node as usize) {
+            Some(node) => Ok(&mut *node.unit),
+            None => Err(Error::Graph(GraphError::NodeNotFound)),
+        }
+    }
+
+    pub fn connect(&mut self, source: core_audio::AUNode, source_output: u32,
+                   dest: core_audio::AUNode, dest_input: u32) -> Result<(), Error> {
+        try!(self.unit(source));
+        let inputs = try!(self.unit(dest)).input_count();
+        // every unit here has a single output bus, an input can only be fed by one output, and
+        // nothing can feed itself, however far round
+        if source_output != 0 || dest_input >= inputs || self.feeds(dest, source) ||
+           self.connections.iter().any(|c| (c.dest == dest && c.dest_input == dest_input) ||
+                                           (c.source == source && c.source_output == source_output)) {
+            return Err(Error::Graph(GraphError::InvalidConnection));
+        }
+        self.connections.push(Connection { source, source_output,
+                                           dest, dest_input });
+        Ok(())
+    }
+
+    /// Whether `from` is `to`, or feeds it through the connections made so far.
+    fn feeds(&self, from: core_audio::AUNode, to: core_audio::AUNode) -> bool {
+        from == to || self.connections.iter().any(|c| c.source == from && self.feeds(c.dest, to))
+    }
+
+    /// The graph's output node, which is what gets driven when the graph starts.
+    pub fn output_node(&self) -> Result<core_audio::AUNode, Error> {
+        let mut outputs = self.nodes.iter().enumerate()
+                              .filter(|&(_, node)| node.description.componentType == core_audio::kAudioUnitType_Output);
+        match (outputs.next(), outputs.next()) {
+            (Some((index, _)), None) => Ok(index as core_audio::AUNode),
+            _ => Err(Error::Graph(GraphError::OutputNodeErr)),
+        }
+    }
+
+    /// Like AUGraphInitialize, each connected input takes on the format of the output feeding it
+    /// before the units are initialized.
+    pub fn initialize(&mut self) -> Result<(), Error> {
+        // a pass per node is enough for formats to flow down the longest possible chain
+        for _ in 0..self.nodes.len() {
+            for connection in self.connections.clone() {
+                let format = try!(try!(self.unit(connection.source))
+                                  .get_format(core_audio::kAudioUnitScope_Output, connection.source_output));
+                try!(try!(self.unit(connection.dest))
+                     .set_format(core_audio::kAudioUnitScope_Input, connection.dest_input, &format));
+            }
+        }
+        for node in &mut self.nodes {
+            try!(node.unit.initialize());
+        }
+        Ok(())
+    }
+
+    /// Whether `node` and everything feeding it have finished playing as of the graph's current
+    /// sample time.
+    pub fn finished(&mut self, node: core_audio::AUNode) -> Result<bool, Error> {
+        let sample_time = self.sample_time;
+        self.finished_at(node, sample_time)
+    }
+
+    /// Whether `node` and everything feeding it have finished as of `sample_time` on `node`'s
+    /// timeline, which its sources see through `input_sample_time`.
+    fn finished_at(&mut self, node: core_audio::AUNode, sample_time: f64) -> Result<bool, Error> {
+        let unit = try!(self.unit(node));
+        if !unit.finished(sample_time) {
+            return Ok(false);
+        }
+        let input_time = unit.input_sample_time(sample_time);
+        let sources: Vec<core_audio::AUNode> = self.connections.iter().filter(|c| c.dest == node)
+                                                   .map(|c| c.source).collect();
+        for source in sources {
+            if !try!(self.finished_at(source, input_time)) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Renders a cycle of `frames` frames from `node`'s output, pulling its inputs first, and
+    /// advances the graph's sample time.
+    pub fn render(&mut self, node: core_audio::AUNode, frames: usize) -> Result<Buffer, Error> {
+        let context = RenderContext { sample_time: self.sample_time, frames };
+        let buffer = try!(self.pull(node, &context));
+        self.sample_time += frames as f64;
+        Ok(buffer)
+    }
+
+    fn pull(&mut self, node: core_audio::AUNode, context: &RenderContext) -> Result<Buffer, Error> {
+        let input_count = try!(self.unit(node)).input_count() as usize;
+        let mut inputs: Vec<Option<Buffer>> = (0..input_count).map(|_| None).collect();
+        let input_context = try!(try!(self.unit(node)).input_context(context));
+        let feeds: Vec<Connection> = self.connections.iter().filter(|c| c.dest == node).cloned().collect();
+        for connection in feeds {
+            let buffer = try!(self.pull(connection.source, &input_context));
+            // a mixer can lose inputs after they've been connected, leaving the connection hanging
+            if let Some(input) = inputs.get_mut(connection.dest_input as usize) {
+                *input = Some(buffer);
+            }
+        }
+
+        let unit = try!(self.unit(node));
+        let channels = try!(unit.get_format(core_audio::kAudioUnitScope_Output, 0)).mChannelsPerFrame;
+        let mut output = vec![vec![0.0; context.frames]; channels as usize];
+        try!(unit.render(context, &inputs, &mut output));
+        Ok(output)
+    }
+}
@@ -0,0 +1,195 @@
+//! Audio files for the portable backend.
+//!
+//! A file is read into memory and handed to the reader for its container, which pulls out the
+//! format and the audio data. The file player decodes the audio data as it renders.
+
+use backend::portable::{aiff, caf, wav};
+use channel_layout::ChannelLayout;
+use core_audio;
+use error::{AudioFileError, Error};
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+/// What a container reader found in a file.
+pub struct FileData {
+    /// kAudioFilePropertyDataFormat
+    pub format: core_audio::AudioStreamBasicDescription,
+    /// kAudioFilePropertyAudioDataPacketCount
+    pub packet_count: u64,
+    /// the packets themselves
+    pub audio_data: Vec<u8>,
+    /// which speaker each channel is for, as a WAVE_FORMAT_EXTENSIBLE dwChannelMask
+    pub channel_mask: Option<u32>,
+    /// kAudioFilePropertyPacketTableInfo
+    pub packet_table: Option<PacketTable>,
+    /// where each packet is in `audio_data`, for formats whose packets vary in size or length
+    pub packet_descriptions: Vec<PacketDescription>,
+    /// kAudioFilePropertyChannelLayout
+    pub channel_layout: Option<ChannelLayout>,
+    /// kAudioFilePropertyMagicCookieData
+    pub magic_cookie: Vec<u8>,
+    /// kAudioFilePropertyInfoDictionary, as key/value pairs in the order they appear
+    pub info: Vec<(String, String)>,
+    /// kAudioFilePropertyMarkerList
+    pub markers: Vec<Marker>,
+}
+
+/// Mirrors AudioFilePacketTableInfo: how many of the frames in the packets are real audio, as
+/// opposed to encoder priming at the start or padding at the end.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PacketTable {
+    pub valid_frames: i64,
+    pub priming_frames: i32,
+    pub remainder_frames: i32,
+}
+
+/// Mirrors AudioStreamPacketDescription.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PacketDescription {
+    /// byte offset of the packet in the audio data
+    pub start_offset: i64,
+    /// frames in the packet, 0 if the format's mFramesPerPacket says how many
+    pub variable_frames: u32,
+    pub data_byte_size: u32,
+}
+
+/// Mirrors AudioFileMarker, without the SMPTE time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Marker {
+    /// kAudioFileMarkerType_*
+    pub kind: u32,
+    pub frame_position: f64,
+    pub id: i32,
+    /// 0 for all channels, otherwise the channel number counting from 1
+    pub channel: u32,
+}
+
+impl FileData {
+
+    /// A file with nothing known about it beyond its format and audio data.
+    pub fn new(format: core_audio::AudioStreamBasicDescription, packet_count: u64, audio_data: Vec<u8>) -> FileData {
+        FileData {
+            format,
+            packet_count,
+            audio_data,
+            channel_mask: None,
+            packet_table: None,
+            packet_descriptions: Vec::new(),
+            channel_layout: None,
+            magic_cookie: Vec::new(),
+            info: Vec::new(),
+            markers: Vec::new(),
+        }
+    }
+
+    /// The number of frames of audio, leaving out priming and remainder frames.
+    pub fn frame_count(&self) -> u64 {
+        if let Some(packet_table) = self.packet_table {
+            return packet_table.valid_frames as u64;
+        }
+        let frames_per_packet = self.format.mFramesPerPacket as u64;
+        if frames_per_packet != 0 {
+            self.packet_count * frames_per_packet
+        }
+        else {
+            self.packet_descriptions.iter().map(|packet| packet.variable_frames as u64).sum()
+        }
+    }
+
+    /// How many frames at the start are encoder priming rather than audio.
+    pub fn priming_frames(&self) -> u64 {
+        self.packet_table.map_or(0, |packet_table| packet_table.priming_frames as u64)
+    }
+}
+
+/// An open file. Clones share the same data.
+#[derive(Clone)]
+pub struct PortableFile {
+    data: Arc<FileData>,
+}
+
+impl PortableFile {
+
+    /// Reads and parses the file at `path`.
+    pub fn open(path: &Path) -> Result<PortableFile, Error> {
+        let mut bytes = Vec::new();
+        try!(fs::File::open(path).and_then(|mut file| file.read_to_end(&mut bytes)).map_err(io_error));
+        let data = try!(parse(&bytes));
+        Ok(PortableFile { data: Arc::new(data) })
+    }
+
+    /// A file that only exists in memory.
+    pub fn from_data(data: FileData) -> PortableFile {
+        PortableFile { data: Arc::new(data) }
+    }
+
+    pub fn data(&self) -> &Arc<FileData> {
+        &self.data
+    }
+
+    /// The number of frames of audio, leaving out priming and remainder frames.
+    pub fn frame_count(&self) -> u64 {
+        self.data.frame_count()
+    }
+
+    /// The speaker assignment of a WAVE_FORMAT_EXTENSIBLE file's channels.
+    pub fn channel_mask(&self) -> Option<u32> {
+        self.data.channel_mask
+    }
+
+    /// The priming and remainder frames of a file that records them.
+    pub fn packet_table(&self) -> Option<PacketTable> {
+        self.data.packet_table
+    }
+
+    /// kAudioFilePropertyChannelLayout: the layout a file records, or the one its WAVE channel
+    /// mask stands for.
+    pub fn channel_layout(&self) -> Option<ChannelLayout> {
+        match (&self.data.channel_layout, self.data.channel_mask) {
+            (Some(layout), _) => Some(layout.clone()),
+            (None, Some(mask)) => Some(ChannelLayout::from_wave_channel_mask(mask, self.data.format.mChannelsPerFrame)),
+            (None, None) => None,
+        }
+    }
+
+    /// The codec's magic cookie, empty if there isn't one.
+    pub fn magic_cookie(&self) -> &[u8] {
+        &self.data.magic_cookie
+    }
+
+    /// Information strings such as the title and artist.
+    pub fn info(&self) -> &[(String, String)] {
+        &self.data.info
+    }
+
+    pub fn markers(&self) -> &[Marker] {
+        &self.data.markers
+    }
+}
+
+/// Picks a reader by looking at the start of the file.
+fn parse(bytes: &[u8]) -> Result<FileData, Error> {
+    if wav::sniff(bytes) {
+        wav::parse(bytes)
+    }
+    else if aiff::sniff(bytes) {
+        aiff::parse(bytes)
+    }
+    else if caf::sniff(bytes) {
+        caf::parse(bytes)
+    }
+    else {
+        Err(Error::AudioFile(AudioFileError::UnsupportedFileType))
+    }
+}
+
+/// Maps a failure to read the file onto what AudioFileOpenURL would have said.
+fn io_error(err: io::Error) -> Error {
+    match err.kind() {
+        io::ErrorKind::NotFound         => Error::AudioFile(AudioFileError::FileNotFound),
+        io::ErrorKind::PermissionDenied => Error::AudioFile(AudioFileError::Permissions),
+        _                               => Error::AudioFile(AudioFileError::Unspecified),
+    }
+}
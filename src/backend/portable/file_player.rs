@@ -0,0 +1,248 @@
+//! The portable kAudioUnitSubType_AudioFilePlayer.
+
+use backend::{CompletionProc, FileRegion};
+use backend::portable::file::FileData;
+use backend::portable::pcm;
+use backend::portable::unit::{Buffer, Formats, RenderContext, Unit};
+use core_audio;
+use error::{AudioUnitError, Error};
+use property::{self, Property, PropertyValue};
+use std::any::Any;
+use std::sync::Arc;
+
+/// A region together with the file it plays.
+struct Scheduled {
+    file: Arc<FileData>,
+    region: FileRegion,
+    /// taken when it's called
+    completion: Option<CompletionProc>,
+}
+
+/// Plays scheduled regions of files. Like the AU, it stays silent until it is given a start
+/// time stamp, and region time stamps count from that start time.
+pub struct FilePlayer {
+    formats: Formats,
+    files: Vec<Arc<FileData>>,
+    regions: Vec<Scheduled>,
+    start_time: Option<f64>,
+    started_at: Option<f64>,
+    /// the graph sample time the last render cycle rendered up to
+    rendered_to: f64,
+}
+
+impl FilePlayer {
+
+    pub fn new() -> FilePlayer {
+        FilePlayer {
+            formats: Formats::new(0, 1),
+            files: Vec::new(),
+            regions: Vec::new(),
+            start_time: None,
+            started_at: None,
+            rendered_to: 0.0,
+        }
+    }
+
+    /// kAudioUnitProperty_ScheduledFileIDs
+    pub fn set_files(&mut self, files: Vec<Arc<FileData>>) -> Result<(), Error> {
+        if files.iter().any(|file| !pcm::can_decode(&file.format)) {
+            return Err(Error::AudioUnit(AudioUnitError::FormatNotSupported));
+        }
+        self.files = files;
+        Ok(())
+    }
+
+    /// kAudioUnitProperty_ScheduledFileRegion, the file has to be one of the scheduled files.
+    /// `completion` is called from the render cycle in which the region plays its last frame.
+    pub fn schedule_region(&mut self, file: Arc<FileData>, region: FileRegion,
+                           completion: Option<CompletionProc>) -> Result<(), Error> {
+        if !self.files.iter().any(|scheduled| Arc::ptr_eq(scheduled, &file)) {
+            return Err(Error::AudioUnit(AudioUnitError::InvalidPropertyValue));
+        }
+        self.regions.push(Scheduled { file, region, completion });
+        Ok(())
+    }
+
+    /// kAudioUnitProperty_ScheduleStartTimeStamp
+    pub fn set_start_time(&mut self, sample_time: f64) {
+        self.start_time = Some(sample_time);
+        self.started_at = None;
+    }
+
+    /// kAudioUnitProperty_CurrentPlayTime
+    pub fn current_play_time(&self) -> f64 {
+        match self.started_at {
+            Some(started_at) => (self.rendered_to - started_at).max(0.0),
+            None => -1.0,
+        }
+    }
+
+    /// the graph sample time at which `scheduled` stops playing, if it ever does
+    fn region_end(&self, scheduled: &Scheduled) -> Option<f64> {
+        let started_at = self.started_at?;
+        let region = &scheduled.region;
+        if region.loop_count == u32::MAX && region.frames_to_play > 0 {
+            return None;
+        }
+        let total = region.frames_to_play as f64 * (region.loop_count as f64 + 1.0);
+        Some(started_at + region.sample_time + total)
+    }
+
+    fn render_region(scheduled: &Scheduled, player_time: i64, output: &mut Buffer) {
+        let region = &scheduled.region;
+        let length = region.frames_to_play as i64;
+        if length == 0 {
+            return;
+        }
+        let total = if region.loop_count == u32::MAX {
+            i64::MAX
+        }
+        else {
+            length * (region.loop_count as i64 + 1)
+        };
+
+        let format = &scheduled.file.format;
+        let frame_bytes = format.mBytesPerFrame as usize;
+        // region frames count from the first frame after any priming, and stop before any
+        // remainder frames
+        let priming = scheduled.file.priming_frames() as i64;
+        let file_frames = ((scheduled.file.audio_data.len() / frame_bytes) as i64 - priming)
+            .min(scheduled.file.frame_count() as i64);
+        let frames = output.first().map_or(0, |channel| channel.len()) as i64;
+
+        let mut i: i64 = 0;
+        while i < frames {
+            let offset = player_time + i - region.sample_time as i64;
+            if offset < 0 {
+                i -= offset;
+                continue;
+            }
+            if offset >= total {
+                break;
+            }
+            let position = offset % length;
+            let chunk = (frames - i).min(length - position);
+            let first = priming + (region.start_frame + position).max(0).min(file_frames);
+            let last = priming + (region.start_frame + position + chunk).max(0).min(file_frames);
+            let data = &scheduled.file.audio_data[first as usize * frame_bytes..last as usize * frame_bytes];
+            pcm::decode(format, data, output, i as usize);
+            i += chunk;
+        }
+    }
+}
+
+impl Unit for FilePlayer {
+
+    fn get_format(&self, scope: core_audio::AudioUnitScope,
+                  element: core_audio::AudioUnitElement) -> Result<core_audio::AudioStreamBasicDescription, Error> {
+        self.formats.get(scope, element)
+    }
+
+    fn set_format(&mut self, scope: core_audio::AudioUnitScope, element: core_audio::AudioUnitElement,
+                  format: &core_audio::AudioStreamBasicDescription) -> Result<(), Error> {
+        self.formats.set(scope, element, format)
+    }
+
+    fn get_unit_property(&mut self, property_id: core_audio::AudioUnitPropertyID, scope: core_audio::AudioUnitScope,
+                         element: core_audio::AudioUnitElement) -> Result<Vec<u8>, Error> {
+        try!(check_global(scope, element));
+        match property_id {
+            property::CurrentPlayTime::ID => {
+                let play_time = core_audio::AudioTimeStamp {
+                    mFlags: core_audio::kAudioTimeStampSampleTimeValid,
+                    mSampleTime: self.current_play_time(),
+                    ..Default::default()
+                };
+                Ok(play_time.to_bytes())
+            },
+            property::ScheduleStartTimeStamp::ID => {
+                let mut start_time = core_audio::AudioTimeStamp::default();
+                if let Some(sample_time) = self.start_time {
+                    start_time.mFlags = core_audio::kAudioTimeStampSampleTimeValid;
+                    start_time.mSampleTime = sample_time;
+                }
+                Ok(start_time.to_bytes())
+            },
+            _ => Err(Error::AudioUnit(AudioUnitError::InvalidProperty)),
+        }
+    }
+
+    fn set_unit_property(&mut self, property_id: core_audio::AudioUnitPropertyID, scope: core_audio::AudioUnitScope,
+                         element: core_audio::AudioUnitElement, data: &[u8]) -> Result<(), Error> {
+        try!(check_global(scope, element));
+        match property_id {
+            property::ScheduleStartTimeStamp::ID => {
+                let start_time: core_audio::AudioTimeStamp = try!(PropertyValue::from_bytes(data));
+                if start_time.mFlags & core_audio::kAudioTimeStampSampleTimeValid == 0 {
+                    return Err(Error::AudioUnit(AudioUnitError::InvalidPropertyValue));
+                }
+                self.set_start_time(start_time.mSampleTime);
+                Ok(())
+            },
+            // everything is in memory already, so there's nothing to prime
+            property::ScheduledFilePrime::ID => u32::from_bytes(data).map(|_| ()),
+            property::CurrentPlayTime::ID => Err(Error::AudioUnit(AudioUnitError::PropertyNotWritable)),
+            _ => Err(Error::AudioUnit(AudioUnitError::InvalidProperty)),
+        }
+    }
+
+    fn render(&mut self, context: &RenderContext, _inputs: &[Option<Buffer>], output: &mut Buffer) -> Result<(), Error> {
+        let start_time = match self.start_time {
+            Some(start_time) => start_time,
+            None => return Ok(()),
+        };
+        // -1 means start on the next render cycle, i.e. this one
+        let started_at = *self.started_at.get_or_insert(if start_time < 0.0 { context.sample_time } else { start_time });
+        let player_time = (context.sample_time - started_at) as i64;
+        for scheduled in &self.regions {
+            FilePlayer::render_region(scheduled, player_time, output);
+        }
+        self.rendered_to = context.sample_time + context.frames as f64;
+
+        for i in 0..self.regions.len() {
+            let ended = match self.region_end(&self.regions[i]) {
+                Some(end) => end <= self.rendered_to,
+                None => false,
+            };
+            if ended {
+                if let Some(mut completion) = self.regions[i].completion.take() {
+                    completion(Ok(()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        // the scheduled files stay, but their regions and the start time go, without calling any
+        // completions
+        self.regions.clear();
+        self.start_time = None;
+        self.started_at = None;
+    }
+
+    fn finished(&self, sample_time: f64) -> bool {
+        // without a start time nothing is ever going to play
+        self.start_time.is_none() || self.regions.iter().all(|scheduled| {
+            match self.region_end(scheduled) {
+                Some(end) => end <= sample_time,
+                None => false,
+            }
+        })
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// The file player's properties all live in the global scope.
+fn check_global(scope: core_audio::AudioUnitScope, element: core_audio::AudioUnitElement) -> Result<(), Error> {
+    if scope != core_audio::kAudioUnitScope_Global {
+        return Err(Error::AudioUnit(AudioUnitError::InvalidScope));
+    }
+    if element != 0 {
+        return Err(Error::AudioUnit(AudioUnitError::InvalidElement));
+    }
+    Ok(())
+}
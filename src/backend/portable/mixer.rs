@@ -0,0 +1,245 @@
+//! The portable kAudioUnitSubType_MultiChannelMixer.
+
+use backend::MIXER_MUTE_PARAMETER;
+use backend::portable::parameters::Parameters;
+use backend::portable::unit::{Buffer, Formats, RenderContext, Unit};
+use core_audio;
+use error::Error;
+use std::any::Any;
+use std::f32::consts::FRAC_PI_4;
+
+/// How many inputs a mixer starts out with, the same as the AU.
+const DEFAULT_INPUT_COUNT: u32 = 8;
+
+const VOLUME: core_audio::AudioUnitParameterID = core_audio::kMultiChannelMixerParam_Volume;
+const ENABLE: core_audio::AudioUnitParameterID = core_audio::kMultiChannelMixerParam_Enable;
+const PAN: core_audio::AudioUnitParameterID = core_audio::kMultiChannelMixerParam_Pan;
+const MUTE: core_audio::AudioUnitParameterID = MIXER_MUTE_PARAMETER;
+
+/// Sums its inputs into its output. Each input has a volume (0 to 1), an enable switch (0 or 1),
+/// a pan (-1 for left to 1 for right) and a mute switch (0 or 1), and the output a master volume,
+/// all of them parameters that can be ramped.
+pub struct MultiChannelMixer {
+    formats: Formats,
+    parameters: Parameters,
+}
+
+/// Volume, enable, pan and mute for `inputs` inputs, and the output's volume.
+fn mixer_parameters(inputs: u32) -> Parameters {
+    let mut parameters = Parameters::new().with(VOLUME, core_audio::kAudioUnitScope_Output, 0, 0.0, 1.0, 1.0);
+    for bus in 0..inputs {
+        parameters = parameters.with(VOLUME, core_audio::kAudioUnitScope_Input, bus, 0.0, 1.0, 1.0)
+                               .with(ENABLE, core_audio::kAudioUnitScope_Input, bus, 0.0, 1.0, 1.0)
+                               .with(PAN, core_audio::kAudioUnitScope_Input, bus, -1.0, 1.0, 0.0)
+                               .with(MUTE, core_audio::kAudioUnitScope_Input, bus, 0.0, 1.0, 0.0);
+    }
+    parameters
+}
+
+/// How much of each input channel goes to each output channel, `gains[input][output]`.
+///
+/// A mono input panned between two outputs keeps the same power wherever it is, so it's 3dB
+/// down on each side in the middle. A stereo input's pan is a balance control, turning one side
+/// down without touching the other. Otherwise channels go straight across, a mono input feeding
+/// every output channel, and a single output getting the average of the input channels.
+pub fn channel_gains(inputs: usize, outputs: usize, pan: f32) -> Vec<Vec<f32>> {
+    let mut gains = vec![vec![0.0; outputs]; inputs];
+    match (inputs, outputs) {
+        (1, 2) => {
+            let angle = (pan + 1.0) * FRAC_PI_4;
+            gains[0][0] = angle.cos();
+            gains[0][1] = angle.sin();
+        },
+        (2, 2) => {
+            gains[0][0] = (1.0 - pan).min(1.0);
+            gains[1][1] = (1.0 + pan).min(1.0);
+        },
+        (1, _) => {
+            for gain in gains[0].iter_mut() {
+                *gain = 1.0;
+            }
+        },
+        (_, 1) => {
+            for input in gains.iter_mut() {
+                input[0] = 1.0 / inputs as f32;
+            }
+        },
+        _ => {
+            for (channel, input) in (0..outputs).zip(gains.iter_mut()) {
+                input[channel] = 1.0;
+            }
+        },
+    }
+    gains
+}
+
+impl MultiChannelMixer {
+
+    pub fn new() -> MultiChannelMixer {
+        MultiChannelMixer {
+            formats: Formats::new(DEFAULT_INPUT_COUNT, 1),
+            parameters: mixer_parameters(DEFAULT_INPUT_COUNT),
+        }
+    }
+
+    /// Adds `input` at `bus`'s volume, enable, pan and mute for each frame to `output`.
+    fn mix(&self, bus: u32, input: &Buffer, output: &mut Buffer) {
+        let scope = core_audio::kAudioUnitScope_Input;
+        let volume = self.parameters.frames(VOLUME, scope, bus);
+        let enable = self.parameters.frames(ENABLE, scope, bus);
+        let pan = self.parameters.frames(PAN, scope, bus);
+        let mute = self.parameters.frames(MUTE, scope, bus);
+        let mut gains = channel_gains(input.len(), output.len(), pan.first().cloned().unwrap_or(0.0));
+        for frame in 0..volume.len() {
+            if frame > 0 && pan[frame] != pan[frame - 1] {
+                gains = channel_gains(input.len(), output.len(), pan[frame]);
+            }
+            if enable[frame] == 0.0 || mute[frame] != 0.0 {
+                continue;
+            }
+            for (samples, gains) in input.iter().zip(gains.iter()) {
+                for (out, &gain) in output.iter_mut().zip(gains.iter()) {
+                    out[frame] += samples[frame] * gain * volume[frame];
+                }
+            }
+        }
+    }
+}
+
+impl Unit for MultiChannelMixer {
+
+    fn input_count(&self) -> u32 {
+        self.formats.input_count()
+    }
+
+    fn get_format(&self, scope: core_audio::AudioUnitScope,
+                  element: core_audio::AudioUnitElement) -> Result<core_audio::AudioStreamBasicDescription, Error> {
+        self.formats.get(scope, element)
+    }
+
+    fn set_format(&mut self, scope: core_audio::AudioUnitScope, element: core_audio::AudioUnitElement,
+                  format: &core_audio::AudioStreamBasicDescription) -> Result<(), Error> {
+        self.formats.set(scope, element, format)
+    }
+
+    fn set_input_count(&mut self, count: u32) -> Result<(), Error> {
+        // the busses that are kept keep their settings
+        let mut parameters = mixer_parameters(count);
+        for bus in 0..count.min(self.formats.input_count()) {
+            for &id in &[VOLUME, ENABLE, PAN, MUTE] {
+                let value = try!(self.parameters.get(id, core_audio::kAudioUnitScope_Input, bus));
+                try!(parameters.set(id, core_audio::kAudioUnitScope_Input, bus, value, 0));
+            }
+        }
+        let master = try!(self.parameters.get(VOLUME, core_audio::kAudioUnitScope_Output, 0));
+        try!(parameters.set(VOLUME, core_audio::kAudioUnitScope_Output, 0, master, 0));
+        self.parameters = parameters;
+        self.formats.set_input_count(count);
+        Ok(())
+    }
+
+    fn parameters(&mut self) -> Option<&mut Parameters> {
+        Some(&mut self.parameters)
+    }
+
+    fn reset(&mut self) {
+        self.parameters.reset();
+    }
+
+    fn render(&mut self, context: &RenderContext, inputs: &[Option<Buffer>], output: &mut Buffer) -> Result<(), Error> {
+        self.parameters.render(context.frames);
+        for (bus, input) in inputs.iter().enumerate() {
+            if let Some(ref input) = *input {
+                self.mix(bus as u32, input, output);
+            }
+        }
+        let master = self.parameters.frames(VOLUME, core_audio::kAudioUnitScope_Output, 0);
+        for channel in output.iter_mut() {
+            for (sample, &gain) in channel.iter_mut().zip(master.iter()) {
+                *sample *= gain;
+            }
+        }
+        Ok(())
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{channel_gains, MultiChannelMixer};
+    use backend::{MIXER_MUTE_PARAMETER, ParameterEvent};
+    use backend::portable::unit::{RenderContext, Unit};
+    use core_audio;
+
+    fn assert_close(actual: &[Vec<f32>], expected: &[Vec<f32>]) {
+        for (actual, expected) in actual.iter().zip(expected.iter()) {
+            for (a, e) in actual.iter().zip(expected.iter()) {
+                assert!((a - e).abs() < 1e-6, "{:?} != {:?}", actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn pans_mono_at_constant_power_and_balances_stereo() {
+        let half = 0.5f32.sqrt();
+        assert_close(&channel_gains(1, 2, 0.0), &[vec![half, half]]);
+        assert_close(&channel_gains(1, 2, -1.0), &[vec![1.0, 0.0]]);
+        assert_close(&channel_gains(1, 2, 1.0), &[vec![0.0, 1.0]]);
+        assert_close(&channel_gains(2, 2, 0.0), &[vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert_close(&channel_gains(2, 2, 0.5), &[vec![0.5, 0.0], vec![0.0, 1.0]]);
+        assert_close(&channel_gains(1, 3, 0.5), &[vec![1.0, 1.0, 1.0]]);
+        assert_close(&channel_gains(2, 1, 0.0), &[vec![0.5], vec![0.5]]);
+        assert_close(&channel_gains(3, 2, 0.0), &[vec![1.0, 0.0], vec![0.0, 1.0], vec![0.0, 0.0]]);
+    }
+
+    #[test]
+    fn mixes_inputs_at_their_own_levels() {
+        let mut mixer = MultiChannelMixer::new();
+        let input = core_audio::kAudioUnitScope_Input;
+        {
+            let parameters = mixer.parameters().unwrap();
+            parameters.set(core_audio::kMultiChannelMixerParam_Volume, input, 0, 0.5, 0).unwrap();
+            parameters.set(core_audio::kMultiChannelMixerParam_Pan, input, 1, 1.0, 0).unwrap();
+            parameters.set(core_audio::kMultiChannelMixerParam_Enable, input, 2, 0.0, 0).unwrap();
+            // muted, so turning its volume up leaves it silent
+            parameters.set(MIXER_MUTE_PARAMETER, input, 3, 1.0, 0).unwrap();
+            parameters.schedule(&[ParameterEvent::ramp(core_audio::kMultiChannelMixerParam_Volume, input, 3, 0, 2,
+                                                       0.0, 1.0)]).unwrap();
+            parameters.schedule(&[ParameterEvent::ramp(core_audio::kMultiChannelMixerParam_Volume,
+                                                       core_audio::kAudioUnitScope_Output, 0, 0, 2, 1.0, 0.5)])
+                      .unwrap();
+        }
+        let stereo = Some(vec![vec![1.0; 3], vec![-1.0; 3]]);
+        let mono = Some(vec![vec![0.25; 3]]);
+        let mut output = vec![vec![0.0; 3]; 2];
+        let context = RenderContext { sample_time: 0.0, frames: 3 };
+        mixer.render(&context, &[stereo.clone(), mono.clone(), stereo, mono], &mut output).unwrap();
+
+        // bus 0 at half volume, bus 1 all the way right, bus 2 switched off, bus 3 muted, all of it
+        // faded
+        let frame = [0.5 + 0.0, -0.5 + 0.25];
+        let master = [1.0, 0.75, 0.5];
+        let expected: Vec<Vec<f32>> = (0..2).map(|channel| master.iter().map(|m| frame[channel] * m).collect())
+                                            .collect();
+        assert_close(&output, &expected);
+    }
+
+    #[test]
+    fn input_count_can_change() {
+        let mut mixer = MultiChannelMixer::new();
+        assert_eq!(mixer.input_count(), 8);
+        let input = core_audio::kAudioUnitScope_Input;
+        mixer.parameters().unwrap().set(core_audio::kMultiChannelMixerParam_Pan, input, 1, -0.5, 0).unwrap();
+        mixer.set_input_count(2).unwrap();
+        assert_eq!(mixer.input_count(), 2);
+        assert!(mixer.get_format(input, 2).is_err());
+        assert!(mixer.parameters().unwrap().get(core_audio::kMultiChannelMixerParam_Pan, input, 2).is_err());
+        assert_eq!(mixer.parameters().unwrap().get(core_audio::kMultiChannelMixerParam_Pan, input, 1).unwrap(), -0.5);
+        mixer.set_input_count(16).unwrap();
+        assert_eq!(mixer.parameters().unwrap().get(core_audio::kMultiChannelMixerParam_Volume, input, 15).unwrap(), 1.0);
+    }
+}
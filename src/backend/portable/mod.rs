@@ -0,0 +1,731 @@
+//! `AudioBackend` in plain Rust.
+//!
+//! Graphs are pulled from their output node the way an AUGraph is. There's no audio device behind
+//! the default output, so once started it renders on a thread of its own at the real time rate
+//! and throws the audio away. A null output (`NULL_OUTPUT_SUB_TYPE`) can stand in for it to render
+//! offline instead: it is pulled as fast as possible until everything feeding it has finished,
+//! and keeps what it rendered so it can be checked sample by sample.
+
+mod aiff;
+mod bytes;
+mod caf;
+mod converter;
+mod engine;
+mod file;
+mod file_player;
+mod mixer;
+mod output;
+mod parameters;
+mod pcm;
+#[cfg(test)]
+pub mod test_files;
+mod unit;
+mod wav;
+
+pub use self::file::{Marker, PacketDescription, PacketTable, PortableFile};
+
+use self::engine::Engine;
+use self::file_player::FilePlayer;
+use self::output::Output;
+use self::parameters::Parameters;
+use self::unit::Unit;
+use backend::{AudioBackend, CompletionProc, FileRegion, ParameterEvent};
+use channel_layout::ChannelLayout;
+use channel_matrix::ChannelMatrix;
+use core_audio;
+use error::{AudioUnitError, Context, Error};
+use pcm::Dither;
+use std::io::Write;
+use std::path::Path;
+use stream_format::{Endianness, SampleFormat, StreamFormat};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How many frames the output pulls per render cycle.
+const FRAMES_PER_CYCLE: usize = 512;
+
+/// Component sub type of the null output, an output unit (kAudioUnitType_Output, made by
+/// kAudioUnitManufacturer_Apple) that renders offline rather than to a device.
+pub const NULL_OUTPUT_SUB_TYPE: u32 = 0x6e756c6c; // 'null'
+
+/// Runs graphs in-process without any platform audio APIs.
+pub struct PortableBackend;
+
+/// Set by a driver when its thread is done.
+type Finished = Arc<(Mutex<bool>, Condvar)>;
+
+/// The thread pulling on the output node while the graph is running.
+struct Driver {
+    stop: Arc<AtomicBool>,
+    finished: Finished,
+    thread: thread::JoinHandle<()>,
+}
+
+/// A portable graph.
+pub struct PortableGraph {
+    engine: Arc<Mutex<Engine>>,
+    initialized: bool,
+    driver: Option<Driver>,
+}
+
+impl PortableGraph {
+
+    fn stop_driver(&mut self) {
+        if let Some(driver) = self.driver.take() {
+            driver.stop.store(true, Ordering::SeqCst);
+            let _ = driver.thread.join();
+        }
+    }
+
+    /// Blocks until the graph's output stops pulling on its own, i.e. until a null output has
+    /// rendered everything feeding it. Returns straight away if the graph isn't running, and
+    /// never returns for a default output, which carries on until it's stopped.
+    pub fn wait_until_finished(&self) -> Result<(), Error> {
+        if let Some(ref driver) = self.driver {
+            let (lock, condvar) = &*driver.finished;
+            let mut finished = try!(lock.lock().map_err(|_| Error::Unspecified));
+            while !*finished {
+                finished = try!(condvar.wait(finished).map_err(|_| Error::Unspecified));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PortableGraph {
+    fn drop(&mut self) {
+        self.stop_driver();
+    }
+}
+
+/// A node's unit in a portable graph.
+#[derive(Clone)]
+pub struct PortableUnit {
+    engine: Arc<Mutex<Engine>>,
+    node: core_audio::AUNode,
+}
+
+fn lock(engine: &Mutex<Engine>) -> Result<MutexGuard<'_, Engine>, Error> {
+    // a poisoned engine means a render panicked part way through a cycle
+    engine.lock().map_err(|_| Error::Unspecified)
+}
+
+impl PortableUnit {
+
+    /// Runs `f` on the concrete unit, or fails the way setting a property the unit doesn't have
+    /// would.
+    fn with_unit<U, T, F>(&self, f: F) -> Result<T, Error>
+        where U: Unit + 'static, F: FnOnce(&mut U) -> Result<T, Error> {
+        let mut engine = try!(lock(&self.engine));
+        let unit = try!(engine.unit(self.node));
+        match unit.as_any().downcast_mut::<U>() {
+            Some(unit) => f(unit),
+            None => Err(Error::AudioUnit(AudioUnitError::InvalidProperty)),
+        }
+    }
+
+    fn with_file_player<T, F>(&self, f: F) -> Result<T, Error>
+        where F: FnOnce(&mut FilePlayer) -> Result<T, Error> {
+        self.with_unit(f)
+    }
+
+    /// Runs `f` on the unit's parameters, or fails the way getting one the unit doesn't have would.
+    fn with_parameters<T, F>(&self, f: F) -> Result<T, Error>
+        where F: FnOnce(&mut Parameters) -> Result<T, Error> {
+        let mut engine = try!(lock(&self.engine));
+        match try!(engine.unit(self.node)).parameters() {
+            Some(parameters) => f(parameters),
+            None => Err(Error::AudioUnit(AudioUnitError::InvalidParameter)),
+        }
+    }
+
+    /// Everything a null output has rendered so far, interleaved.
+    pub fn rendered_samples(&self) -> Result<Vec<f32>, Error> {
+        self.with_unit(|output: &mut Output| output.samples().map(|samples| samples.to_vec()))
+    }
+
+    /// Has an output unit mix its input into the device's channels with `matrix` rather than the
+    /// matrix between their channel layouts, or go back to that with `None`.
+    pub fn set_channel_matrix(&self, matrix: Option<ChannelMatrix>) -> Result<(), Error> {
+        let mut engine = try!(lock(&self.engine));
+        try!(engine.unit(self.node)).set_channel_matrix(matrix)
+    }
+
+    /// Has a null output write what it renders from now on to `writer`, interleaved little endian
+    /// 32 bit floats, rather than keeping it in memory.
+    pub fn set_render_writer<W: Write + Send + 'static>(&self, writer: W) -> Result<(), Error> {
+        let format = try!(StreamFormat::new(SampleFormat::Float, 32, 1, 44100.0)).with_endianness(Endianness::Little);
+        self.set_render_writer_format(writer, &format, Dither::None)
+    }
+
+    /// As `set_render_writer`, writing samples in `format` instead, e.g. 16 bit integers
+    /// requantized with `dither`. The format has to be interleaved, and its channel count and
+    /// sample rate are ignored.
+    pub fn set_render_writer_format<W: Write + Send + 'static>(&self, writer: W, format: &StreamFormat,
+                                                               dither: Dither) -> Result<(), Error> {
+        self.with_unit(|output: &mut Output| output.set_writer(Box::new(writer), format, dither))
+    }
+}
+
+/// Pulls `output` every cycle. Paced, it sleeps so that it goes at `sample_rate`; otherwise it
+/// goes as fast as it can and stops once everything feeding `output` has finished.
+fn drive(engine: Arc<Mutex<Engine>>, output: core_audio::AUNode, sample_rate: f64, paced: bool,
+         stop: Arc<AtomicBool>) {
+    let nanos = (FRAMES_PER_CYCLE as f64 / sample_rate * 1e9) as u64;
+    let period = Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32);
+    let mut deadline = Instant::now();
+    while !stop.load(Ordering::SeqCst) {
+        match lock(&engine) {
+            Ok(mut engine) => {
+                if !paced && engine.finished(output).unwrap_or(true) {
+                    return;
+                }
+                if engine.render(output, FRAMES_PER_CYCLE).is_err() {
+                    return;
+                }
+            },
+            Err(_) => return,
+        }
+        if paced {
+            deadline += period;
+            let now = Instant::now();
+            if deadline > now {
+                thread::sleep(deadline - now);
+            }
+        }
+    }
+}
+
+impl AudioBackend for PortableBackend {
+    type File = PortableFile;
+    type Graph = PortableGraph;
+    type Unit = PortableUnit;
+
+    fn open_audio_file(path: &Path) -> Result<PortableFile, Error> {
+        PortableFile::open(path).map_err(|err| err.with_context(Context::new("AudioFileOpenURL").path(path)))
+    }
+
+    fn close_audio_file(_file: &mut PortableFile) -> Result<(), Error> {
+        // the data goes when the last player scheduling it lets go
+        Ok(())
+    }
+
+    fn get_data_format(file: &PortableFile) -> Result<core_audio::AudioStreamBasicDescription, Error> {
+        Ok(file.data().format)
+    }
+
+    fn audio_file_get_audio_data_packet_count(file: &PortableFile) -> Result<u64, Error> {
+        Ok(file.data().packet_count)
+    }
+
+    fn audio_file_get_frame_count(file: &PortableFile) -> Result<u64, Error> {
+        Ok(file.frame_count())
+    }
+
+    fn audio_file_get_channel_layout(file: &PortableFile) -> Result<Option<ChannelLayout>, Error> {
+        Ok(file.channel_layout())
+    }
+
+    fn new_au_graph() -> Result<PortableGraph, Error> {
+        Ok(PortableGraph { engine: Arc::new(Mutex::new(Engine::new())), initialized: false, driver: None })
+    }
+
+    fn dispose_au_graph(graph: &mut PortableGraph) -> Result<(), Error> {
+        graph.stop_driver();
+        *try!(lock(&graph.engine)) = Engine::new();
+        Ok(())
+    }
+
+    fn graph_add_node(graph: &mut PortableGraph,
+                      description: &core_audio::AudioComponentDescription) -> Result<core_audio::AUNode, Error> {
+        try!(lock(&graph.engine)).add_node(description)
+            .map_err(|err| err.with_context(Context::new("AUGraphAddNode")))
+    }
+
+    fn graph_open(_graph: &mut PortableGraph) -> Result<(), Error> {
+        // units are created as their nodes are added
+        Ok(())
+    }
+
+    fn graph_close(_graph: &mut PortableGraph) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn graph_node_info(graph: &PortableGraph, node: core_audio::AUNode) -> Result<PortableUnit, Error> {
+        try!(try!(lock(&graph.engine)).unit(node).map_err(|err| err.with_context(Context::new("AUGraphNodeInfo"))));
+        Ok(PortableUnit { engine: graph.engine.clone(), node })
+    }
+
+    fn graph_connect_node_input(graph: &mut PortableGraph, source_node: core_audio::AUNode, source_output: u32,
+                                dest_node: core_audio::AUNode, dest_input: u32) -> Result<(), Error> {
+        try!(lock(&graph.engine)).connect(source_node, source_output, dest_node, dest_input)
+            .map_err(|err| err.with_context(Context::new("AUGraphConnectNodeInput")))
+    }
+
+    fn graph_initialize(graph: &mut PortableGraph) -> Result<(), Error> {
+        try!(try!(lock(&graph.engine)).initialize().map_err(|err| err.with_context(Context::new("AUGraphInitialize"))));
+        graph.initialized = true;
+        Ok(())
+    }
+
+    fn graph_uninitialize(graph: &mut PortableGraph) -> Result<(), Error> {
+        graph.stop_driver();
+        graph.initialized = false;
+        Ok(())
+    }
+
+    fn graph_start(graph: &mut PortableGraph) -> Result<(), Error> {
+        if !graph.initialized {
+            return Err(Error::AudioUnit(AudioUnitError::Uninitialized).with_context(Context::new("AUGraphStart")));
+        }
+        if graph.driver.is_some() {
+            return Ok(());
+        }
+        let (output, sample_rate, paced) = {
+            let mut engine = try!(lock(&graph.engine));
+            let output = try!(engine.output_node());
+            let unit = try!(engine.unit(output));
+            // the device's rate, which the output resamples to if it's fed at another
+            let format = try!(unit.get_format(core_audio::kAudioUnitScope_Output, 0));
+            let paced = unit.as_any().downcast_ref::<Output>().is_none_or(|output| output.is_paced());
+            engine.sample_time = 0.0;
+            (output, format.mSampleRate, paced)
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let finished: Finished = Arc::new((Mutex::new(false), Condvar::new()));
+        let engine = graph.engine.clone();
+        let thread_stop = stop.clone();
+        let thread_finished = finished.clone();
+        let thread = thread::spawn(move || {
+            drive(engine, output, sample_rate, paced, thread_stop);
+            let (lock, condvar) = &*thread_finished;
+            if let Ok(mut finished) = lock.lock() {
+                *finished = true;
+            }
+            condvar.notify_all();
+        });
+        graph.driver = Some(Driver { stop, finished, thread });
+        Ok(())
+    }
+
+    fn graph_stop(graph: &mut PortableGraph) -> Result<(), Error> {
+        graph.stop_driver();
+        Ok(())
+    }
+
+    fn audio_unit_get_property(audio_unit: &PortableUnit,
+                               property_id: core_audio::AudioUnitPropertyID,
+                               scope: core_audio::AudioUnitScope,
+                               element: core_audio::AudioUnitElement) -> Result<Vec<u8>, Error> {
+        let mut engine = try!(lock(&audio_unit.engine));
+        unit::get_property(try!(engine.unit(audio_unit.node)), property_id, scope, element)
+            .map_err(|err| err.with_context(Context::new("AudioUnitGetProperty").property(property_id)
+                                                                                .scope(scope, element)))
+    }
+
+    unsafe fn audio_unit_set_property(audio_unit: &PortableUnit,
+                                      property_id: core_audio::AudioUnitPropertyID,
+                                      scope: core_audio::AudioUnitScope,
+                                      element: core_audio::AudioUnitElement,
+                                      data: &[u8]) -> Result<(), Error> {
+        let mut engine = try!(lock(&audio_unit.engine));
+        unit::set_property(try!(engine.unit(audio_unit.node)), property_id, scope, element, data)
+            .map_err(|err| err.with_context(Context::new("AudioUnitSetProperty").property(property_id)
+                                                                                .scope(scope, element)))
+    }
+
+    fn audio_unit_get_parameter(audio_unit: &PortableUnit,
+                                parameter: core_audio::AudioUnitParameterID,
+                                scope: core_audio::AudioUnitScope,
+                                element: core_audio::AudioUnitElement) -> Result<f32, Error> {
+        audio_unit.with_parameters(|parameters| parameters.get(parameter, scope, element))
+            .map_err(|err| err.with_context(Context::new("AudioUnitGetParameter").parameter(parameter)
+                                                                                 .scope(scope, element)))
+    }
+
+    fn audio_unit_set_parameter(audio_unit: &PortableUnit,
+                                parameter: core_audio::AudioUnitParameterID,
+                                scope: core_audio::AudioUnitScope,
+                                element: core_audio::AudioUnitElement,
+                                value: f32,
+                                buffer_offset: u32) -> Result<(), Error> {
+        audio_unit.with_parameters(|parameters| parameters.set(parameter, scope, element, value, buffer_offset))
+            .map_err(|err| err.with_context(Context::new("AudioUnitSetParameter").parameter(parameter)
+                                                                                 .scope(scope, element)))
+    }
+
+    fn audio_unit_schedule_parameters(audio_unit: &PortableUnit, events: &[ParameterEvent]) -> Result<(), Error> {
+        audio_unit.with_parameters(|parameters| parameters.schedule(events))
+            .map_err(|err| err.with_context(Context::new("AudioUnitScheduleParameters")))
+    }
+
+    // the portable file player shares the files' data, so they can be closed whenever
+    unsafe fn set_scheduled_file_ids(audio_unit: &PortableUnit,
+                                     scope: core_audio::AudioUnitScope,
+                                     element: core_audio::AudioUnitElement,
+                                     files: &[&PortableFile]) -> Result<(), Error> {
+        let context = Context::new("AudioUnitSetProperty").property(core_audio::kAudioUnitProperty_ScheduledFileIDs)
+                                                          .scope(scope, element);
+        if scope != core_audio::kAudioUnitScope_Global {
+            return Err(Error::AudioUnit(AudioUnitError::InvalidScope).with_context(context));
+        }
+        if element != 0 {
+            return Err(Error::AudioUnit(AudioUnitError::InvalidElement).with_context(context));
+        }
+        audio_unit.with_file_player(|player| player.set_files(files.iter().map(|file| file.data().clone()).collect()))
+            .map_err(|err| err.with_context(context))
+    }
+
+    unsafe fn audio_unit_set_scheduled_file_region(audio_unit: &PortableUnit,
+                                                   file: &PortableFile,
+                                                   region: &FileRegion,
+                                                   completion: Option<CompletionProc>) -> Result<(), Error> {
+        audio_unit.with_file_player(|player| player.schedule_region(file.data().clone(), *region, completion))
+            .map_err(|err| err.with_context(Context::new("AudioUnitSetProperty")
+                                            .property(core_audio::kAudioUnitProperty_ScheduledFileRegion)
+                                            .scope(core_audio::kAudioUnitScope_Global, 0)))
+    }
+
+    fn audio_unit_reset(audio_unit: &PortableUnit,
+                        _scope: core_audio::AudioUnitScope,
+                        _element: core_audio::AudioUnitElement) -> Result<(), Error> {
+        let mut engine = try!(lock(&audio_unit.engine));
+        try!(engine.unit(audio_unit.node)).reset();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{NULL_OUTPUT_SUB_TYPE, PortableBackend, PortableFile, PortableUnit};
+    use super::file::{FileData, PacketDescription, PacketTable};
+    use backend::{AudioBackend, FileRegion, ParameterEvent};
+    use core_audio;
+    use error::{AudioUnitError, Error, GraphError};
+    use graph::{Created, Graph};
+    use pcm::Dither;
+    use property;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use stream_format::{Endianness, SampleFormat, StreamFormat};
+
+    /// a mono 16 bit file whose frame `n` holds the sample value `n`
+    fn ramp_file(frames: u32) -> PortableFile {
+        let mut audio_data = Vec::new();
+        for n in 0..frames {
+            audio_data.push(n as u8);
+            audio_data.push((n >> 8) as u8);
+        }
+        let format = core_audio::AudioStreamBasicDescription {
+            mSampleRate: 44100.0,
+            mFormatID: core_audio::kAudioFormatLinearPCM,
+            mFormatFlags: (core_audio::kAudioFormatFlagIsSignedInteger | core_audio::kAudioFormatFlagIsPacked) as u32,
+            mBytesPerPacket: 2,
+            mFramesPerPacket: 1,
+            mBytesPerFrame: 2,
+            mChannelsPerFrame: 1,
+            mBitsPerChannel: 16,
+            mReserved: 0,
+        };
+        PortableFile::from_data(FileData::new(format, frames as u64, audio_data))
+    }
+
+    #[test]
+    fn frame_count_leaves_out_priming_and_remainder() {
+        let file = ramp_file(100);
+        assert_eq!(PortableBackend::audio_file_get_frame_count(&file).unwrap(), 100);
+
+        let mut data = FileData::new(file.data().format, 100, file.data().audio_data.clone());
+        data.packet_table = Some(PacketTable { valid_frames: 60, priming_frames: 30, remainder_frames: 10 });
+        let file = PortableFile::from_data(data);
+        assert_eq!(PortableBackend::audio_file_get_frame_count(&file).unwrap(), 60);
+
+        // packets that each say how long they are
+        let mut format = file.data().format;
+        format.mBytesPerPacket = 0;
+        format.mFramesPerPacket = 0;
+        let mut data = FileData::new(format, 2, vec![0; 8]);
+        data.packet_descriptions = vec![PacketDescription { start_offset: 0, variable_frames: 3, data_byte_size: 4 },
+                                        PacketDescription { start_offset: 4, variable_frames: 5, data_byte_size: 4 }];
+        let file = PortableFile::from_data(data);
+        assert_eq!(PortableBackend::audio_file_get_frame_count(&file).unwrap(), 8);
+    }
+
+    fn ramp_value(n: i64) -> f32 {
+        n as f32 / 32768.0
+    }
+
+    /// schedules `region` of `file` on a file player, to start playing straight away
+    fn schedule(player: &PortableUnit, file: &PortableFile, region: &FileRegion) {
+        // the portable file player shares the file's data, so the file needn't stay open
+        unsafe {
+            PortableBackend::set_scheduled_file_ids(player, core_audio::kAudioUnitScope_Global, 0, &[file]).unwrap();
+            PortableBackend::audio_unit_set_scheduled_file_region(player, file, region, None).unwrap();
+        }
+        PortableBackend::audio_unit_set_schedule_start_time_stamp(player, -1.0).unwrap();
+    }
+
+    /// file player into a mono null output, rendering `region` of `file`
+    fn render<F>(file: &PortableFile, region: &FileRegion, configure: F) -> Vec<f32>
+        where F: FnOnce(&PortableUnit) {
+        let mut graph = Graph::<Created, PortableBackend>::new().unwrap();
+        let output_node = graph.add_node(core_audio::kAudioUnitType_Output, NULL_OUTPUT_SUB_TYPE,
+                                         core_audio::kAudioUnitManufacturer_Apple).unwrap();
+        let file_node = graph.add_node(core_audio::kAudioUnitType_Generator,
+                                       core_audio::kAudioUnitSubType_AudioFilePlayer,
+                                       core_audio::kAudioUnitManufacturer_Apple).unwrap();
+        let mut graph = graph.open().unwrap();
+        let player = graph.unit(file_node).unwrap();
+        let output = graph.unit(output_node).unwrap();
+        configure(&output);
+        PortableBackend::set_number_of_channels(&player, core_audio::kAudioUnitScope_Output, 0, 1).unwrap();
+        schedule(&player, file, region);
+        graph.connect_node_input(file_node, 0, output_node, 0).unwrap();
+
+        let graph = graph.initialize().unwrap().start().unwrap();
+        graph.wait_until_finished().unwrap();
+        graph.node_info(output_node).unwrap().rendered_samples().unwrap_or_default()
+    }
+
+    #[test]
+    fn null_output_renders_the_whole_file() {
+        let file = ramp_file(1000);
+        let region = FileRegion { sample_time: 0.0, start_frame: 0, frames_to_play: 1000, loop_count: 0 };
+        let samples = render(&file, &region, |_| ());
+
+        // whole render cycles, so the tail is padded with silence
+        assert_eq!(samples.len(), 1024);
+        for (n, &sample) in samples[..1000].iter().enumerate() {
+            assert_eq!(sample, ramp_value(n as i64));
+        }
+        assert!(samples[1000..].iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn null_output_honours_start_frame_length_and_loops() {
+        let file = ramp_file(1000);
+        let region = FileRegion { sample_time: 50.0, start_frame: 100, frames_to_play: 200, loop_count: 2 };
+        let samples = render(&file, &region, |_| ());
+
+        // 50 frames of silence, then frames 100..300 three times over
+        assert_eq!(samples.len(), 1024);
+        assert!(samples[..50].iter().all(|&sample| sample == 0.0));
+        for n in 0..600 {
+            assert_eq!(samples[50 + n], ramp_value(100 + (n as i64 % 200)));
+        }
+        assert!(samples[650..].iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn null_output_fades_with_a_scheduled_ramp() {
+        let file = ramp_file(1000);
+        let region = FileRegion { sample_time: 0.0, start_frame: 0, frames_to_play: 1000, loop_count: 0 };
+        let volume = core_audio::kHALOutputParam_Volume;
+        let global = core_audio::kAudioUnitScope_Global;
+        let samples = render(&file, &region, |output| {
+            PortableBackend::audio_unit_set_parameter(output, volume, global, 0, 0.5, 0).unwrap();
+            assert_eq!(PortableBackend::audio_unit_get_parameter(output, volume, global, 0).unwrap(), 0.5);
+            // fade out over the first 256 frames
+            PortableBackend::audio_unit_schedule_parameters(output, &[
+                ParameterEvent::ramp(volume, global, 0, 0, 256, 0.5, 0.0),
+            ]).unwrap();
+        });
+
+        for (n, &sample) in samples[..256].iter().enumerate() {
+            assert_eq!(sample, ramp_value(n as i64) * (0.5 - 0.5 * n as f32 / 256.0));
+        }
+        // the ramp isn't carried on into the second cycle, so the volume stays where it ended up
+        assert!(samples[256..].iter().all(|&sample| sample == 0.0));
+    }
+
+    /// shares what's written with the test after the graph has taken the writer
+    #[derive(Clone)]
+    struct SharedWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> ::std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn null_output_writes_to_a_writer() {
+        let file = ramp_file(600);
+        let region = FileRegion { sample_time: 0.0, start_frame: 0, frames_to_play: 600, loop_count: 0 };
+        let writer = SharedWriter(Arc::new(Mutex::new(Vec::new())));
+        let shared = writer.clone();
+        let samples = render(&file, &region, move |output| output.set_render_writer(writer).unwrap());
+        assert!(samples.is_empty());
+
+        let bytes = shared.0.lock().unwrap();
+        assert_eq!(bytes.len(), 1024 * 4);
+        let sample = |n: usize| f32::from_bits(bytes[n * 4] as u32 | (bytes[n * 4 + 1] as u32) << 8 |
+                                               (bytes[n * 4 + 2] as u32) << 16 | (bytes[n * 4 + 3] as u32) << 24);
+        assert_eq!(sample(1), ramp_value(1));
+        assert_eq!(sample(599), ramp_value(599));
+        assert_eq!(sample(600), 0.0);
+    }
+
+    #[test]
+    fn null_output_writes_dithered_integers_reproducibly() {
+        let file = ramp_file(600);
+        let region = FileRegion { sample_time: 0.0, start_frame: 0, frames_to_play: 600, loop_count: 0 };
+        let format = StreamFormat::new(SampleFormat::SignedInteger, 16, 1, 44100.0).unwrap()
+            .with_endianness(Endianness::Little);
+        let dither = Dither::Triangular { seed: 3 };
+        let render_bytes = || {
+            let writer = SharedWriter(Arc::new(Mutex::new(Vec::new())));
+            let shared = writer.clone();
+            render(&file, &region, move |output| output.set_render_writer_format(writer, &format, dither).unwrap());
+            let bytes = shared.0.lock().unwrap().clone();
+            bytes
+        };
+        let bytes = render_bytes();
+        assert_eq!(bytes.len(), 1024 * 2);
+        assert_eq!(render_bytes(), bytes);
+        // the ramp is already on whole steps, so the dither moves it by a step at most
+        for (n, word) in bytes[..600 * 2].chunks(2).enumerate() {
+            let sample = i16::from_le_bytes([word[0], word[1]]) as i64;
+            assert!((sample - n as i64).abs() <= 1, "{} at {}", sample, n);
+        }
+        assert!(bytes[..600 * 2] != (0..600i16).flat_map(|n| n.to_le_bytes().to_vec()).collect::<Vec<u8>>()[..]);
+
+        let deinterleaved = format.with_channels(2).unwrap().interleaved(false);
+        let mut graph = Graph::<Created, PortableBackend>::new().unwrap();
+        let node = graph.add_node(core_audio::kAudioUnitType_Output, NULL_OUTPUT_SUB_TYPE,
+                                  core_audio::kAudioUnitManufacturer_Apple).unwrap();
+        let graph = graph.open().unwrap();
+        let output = graph.node_info(node).unwrap();
+        assert!(output.set_render_writer_format(Vec::new(), &deinterleaved, dither).is_err());
+    }
+
+    /// a mono 16 bit 44.1kHz file of a half scale sine at `frequency`
+    fn sine_file(frames: u32, frequency: f64) -> PortableFile {
+        let mut audio_data = Vec::new();
+        for n in 0..frames {
+            let value = (0.5 * 32768.0 * (2.0 * ::std::f64::consts::PI * frequency * n as f64 / 44100.0).sin()) as i16;
+            audio_data.extend_from_slice(&value.to_le_bytes());
+        }
+        let format = ramp_file(0).data().format;
+        PortableFile::from_data(FileData::new(format, frames as u64, audio_data))
+    }
+
+    #[test]
+    fn converter_resamples_to_its_output_rate() {
+        let file = sine_file(4410, 1000.0);
+        let mut graph = Graph::<Created, PortableBackend>::new().unwrap();
+        let output_node = graph.add_node(core_audio::kAudioUnitType_Output, NULL_OUTPUT_SUB_TYPE,
+                                         core_audio::kAudioUnitManufacturer_Apple).unwrap();
+        let converter_node = graph.add_converter().unwrap();
+        let file_node = graph.add_node(core_audio::kAudioUnitType_Generator,
+                                       core_audio::kAudioUnitSubType_AudioFilePlayer,
+                                       core_audio::kAudioUnitManufacturer_Apple).unwrap();
+        let mut graph = graph.open().unwrap();
+        let player = graph.unit(file_node).unwrap();
+        let converter = graph.unit(converter_node).unwrap();
+        PortableBackend::set_number_of_channels(&player, core_audio::kAudioUnitScope_Output, 0, 1).unwrap();
+        PortableBackend::set_sample_rate(&converter, core_audio::kAudioUnitScope_Output, 0, 48000.0).unwrap();
+        let region = FileRegion { sample_time: 0.0, start_frame: 0, frames_to_play: 4410, loop_count: 0 };
+        schedule(&player, &file, &region);
+        graph.connect_node_input(file_node, 0, converter_node, 0).unwrap();
+        graph.connect_node_input(converter_node, 0, output_node, 0).unwrap();
+
+        let graph = graph.initialize().unwrap();
+        let output = graph.unit(output_node).unwrap();
+        assert_eq!(PortableBackend::get_format(&output, core_audio::kAudioUnitScope_Input, 0).unwrap().mSampleRate,
+                   48000.0);
+        let graph = graph.start().unwrap();
+        graph.wait_until_finished().unwrap();
+        let samples = graph.node_info(output_node).unwrap().rendered_samples().unwrap();
+
+        // a tenth of a second at 48kHz, in whole render cycles
+        assert_eq!(samples.len(), 5120);
+        for (n, &sample) in samples.iter().enumerate().take(4700).skip(100) {
+            let expected = 0.5 * (2.0 * ::std::f64::consts::PI * 1000.0 * n as f64 / 48000.0).sin();
+            assert!((sample as f64 - expected).abs() < 1e-3, "{} at {}, not {}", sample, n, expected);
+        }
+        assert!(samples[4900..].iter().all(|&sample| sample.abs() < 1e-3));
+    }
+
+    #[test]
+    fn resampling_units_take_a_render_quality() {
+        let mut graph = Graph::<Created, PortableBackend>::new().unwrap();
+        let output_node = graph.add_node(core_audio::kAudioUnitType_Output, NULL_OUTPUT_SUB_TYPE,
+                                         core_audio::kAudioUnitManufacturer_Apple).unwrap();
+        let converter_node = graph.add_converter().unwrap();
+        let graph = graph.open().unwrap();
+        let global = core_audio::kAudioUnitScope_Global;
+        for &node in &[output_node, converter_node] {
+            let unit = graph.audio_unit(node).unwrap();
+            assert_eq!(unit.get::<property::RenderQuality>(global, 0).unwrap(), 0x60);
+            unit.set::<property::RenderQuality>(global, 0, &0x50).unwrap();
+            assert_eq!(unit.get::<property::RenderQuality>(global, 0).unwrap(), 0x40);
+            let err = unit.set::<property::RenderQuality>(core_audio::kAudioUnitScope_Input, 0, &0).unwrap_err();
+            assert_eq!(*err.root(), Error::AudioUnit(AudioUnitError::InvalidScope));
+        }
+    }
+
+    #[test]
+    fn units_have_typed_properties() {
+        let mut graph = Graph::<Created, PortableBackend>::new().unwrap();
+        let file_node = graph.add_node(core_audio::kAudioUnitType_Generator,
+                                       core_audio::kAudioUnitSubType_AudioFilePlayer,
+                                       core_audio::kAudioUnitManufacturer_Apple).unwrap();
+        let output_node = graph.add_node(core_audio::kAudioUnitType_Output, NULL_OUTPUT_SUB_TYPE,
+                                         core_audio::kAudioUnitManufacturer_Apple).unwrap();
+        let graph = graph.open().unwrap();
+        let player = graph.audio_unit(file_node).unwrap();
+        let output = graph.audio_unit(output_node).unwrap();
+        let global = core_audio::kAudioUnitScope_Global;
+
+        let mut format = player.get::<property::StreamFormat>(core_audio::kAudioUnitScope_Output, 0).unwrap();
+        assert_eq!((format.mSampleRate, format.mChannelsPerFrame), (44100.0, 2));
+        format.mSampleRate = 48000.0;
+        player.set::<property::StreamFormat>(core_audio::kAudioUnitScope_Output, 0, &format).unwrap();
+        assert_eq!(PortableBackend::get_format(&graph.unit(file_node).unwrap(), core_audio::kAudioUnitScope_Output, 0).unwrap(), format);
+
+        assert_eq!(player.get::<property::ElementCount>(core_audio::kAudioUnitScope_Input, 0).unwrap(), 0);
+        assert_eq!(output.get::<property::ElementCount>(core_audio::kAudioUnitScope_Input, 0).unwrap(), 1);
+        assert_eq!(output.get::<property::Latency>(global, 0).unwrap(), 0.0);
+
+        assert_eq!(player.get::<property::CurrentPlayTime>(global, 0).unwrap().mSampleTime, -1.0);
+        let start_time = core_audio::AudioTimeStamp {
+            mFlags: core_audio::kAudioTimeStampSampleTimeValid,
+            mSampleTime: 256.0,
+            ..Default::default()
+        };
+        player.set::<property::ScheduleStartTimeStamp>(global, 0, &start_time).unwrap();
+        assert_eq!(player.get::<property::ScheduleStartTimeStamp>(global, 0).unwrap().mSampleTime, 256.0);
+        player.set::<property::ScheduledFilePrime>(global, 0, &0).unwrap();
+
+        // properties a unit doesn't have, or can't be set, fail the way an AU's would
+        let err = output.set::<property::ScheduledFilePrime>(global, 0, &0).unwrap_err();
+        assert_eq!(*err.root(), Error::AudioUnit(AudioUnitError::InvalidProperty));
+        assert_eq!(err.to_string(), "AudioUnitSetProperty(property 3313, scope Global, element 0) failed");
+        let err = player.set::<property::Latency>(global, 0, &0.5).unwrap_err();
+        assert_eq!(*err.root(), Error::AudioUnit(AudioUnitError::PropertyNotWritable));
+        let err = player.get::<property::CurrentPlayTime>(core_audio::kAudioUnitScope_Output, 0).unwrap_err();
+        assert_eq!(*err.root(), Error::AudioUnit(AudioUnitError::InvalidScope));
+    }
+
+    #[test]
+    fn connections_cannot_close_a_loop() {
+        let mut graph = Graph::<Created, PortableBackend>::new().unwrap();
+        let mixers: Vec<core_audio::AUNode> = (0..3).map(|_| graph.add_mixer().unwrap()).collect();
+        let mut graph = graph.open().unwrap();
+        let invalid = Error::Graph(GraphError::InvalidConnection);
+        assert_eq!(*graph.connect_node_input(mixers[0], 0, mixers[0], 0).unwrap_err().root(), invalid);
+        graph.connect_node_input(mixers[0], 0, mixers[1], 0).unwrap();
+        assert_eq!(*graph.connect_node_input(mixers[1], 0, mixers[0], 0).unwrap_err().root(), invalid);
+        graph.connect_node_input(mixers[1], 0, mixers[2], 0).unwrap();
+        assert_eq!(*graph.connect_node_input(mixers[2], 0, mixers[0], 1).unwrap_err().root(), invalid);
+    }
+}
@@ -0,0 +1,334 @@
+//! The portable output units: kAudioUnitSubType_DefaultOutput, and a null output for rendering
+//! offline.
+//!
+//! Both take a channel layout on their input and on the device side (the output scope), and mix
+//! the input into the device's channels by speaker, so e.g. a 5.1 file in film order comes out in
+//! the device's order, or downmixed if the device is stereo. They also resample the input to the
+//! device's sample rate when it's been set to something else.
+
+use backend::portable::converter::RateConverter;
+use backend::portable::parameters::Parameters;
+use backend::portable::unit::{Buffer, Formats, RenderContext, Unit};
+use channel_layout::ChannelLayout;
+use channel_matrix::{ChannelMatrix, Lfe};
+use core_audio;
+use error::{AudioUnitError, Error};
+use property::{self, Property, PropertyValue};
+use pcm::{Dither, Encoder};
+use std::any::Any;
+use std::io::Write;
+use stream_format::StreamFormat;
+
+/// An output unit. Without a sink it's kAudioUnitSubType_DefaultOutput, which passes whatever is
+/// connected to its input through to the engine to be paced in real time; there's no device
+/// behind it, so the audio goes nowhere. With one it's the null output, which the engine pulls as
+/// fast as it can until everything upstream has finished playing, and rendered frames are
+/// collected, interleaved, in memory or written to a writer, by default as little endian 32 bit
+/// floats.
+pub struct Output {
+    formats: Formats,
+    parameters: Parameters,
+    speakers: Speakers,
+    rate: RateConverter,
+    sink: Option<Sink>,
+}
+
+/// Where a null output puts what it renders.
+enum Sink {
+    Memory(Vec<f32>),
+    Writer(Box<dyn Write + Send>, Encoder),
+}
+
+impl Output {
+
+    pub fn default_output() -> Output {
+        Output::new(None)
+    }
+
+    pub fn null() -> Output {
+        Output::new(Some(Sink::Memory(Vec::new())))
+    }
+
+    fn new(sink: Option<Sink>) -> Output {
+        Output {
+            formats: Formats::new(1, 1),
+            parameters: volume(),
+            speakers: Speakers::new(),
+            rate: RateConverter::new(),
+            sink,
+        }
+    }
+
+    /// Whether the engine should pull it in real time, rather than as fast as it can.
+    pub fn is_paced(&self) -> bool {
+        self.sink.is_none()
+    }
+
+    /// Everything a null output has rendered so far, if it's being collected in memory.
+    pub fn samples(&self) -> Result<&[f32], Error> {
+        match self.sink {
+            Some(Sink::Memory(ref samples)) => Ok(samples),
+            Some(Sink::Writer(..)) => Err(Error::AudioUnit(AudioUnitError::PropertyNotInUse)),
+            None => Err(Error::AudioUnit(AudioUnitError::InvalidProperty)),
+        }
+    }
+
+    /// Sends everything a null output renders from now on to `writer` instead of memory, in
+    /// `format`, which has to be interleaved, requantized with `dither`. The format's channel
+    /// count follows the output's.
+    pub fn set_writer(&mut self, writer: Box<dyn Write + Send>, format: &StreamFormat,
+                      dither: Dither) -> Result<(), Error> {
+        if self.sink.is_none() {
+            return Err(Error::AudioUnit(AudioUnitError::InvalidProperty));
+        }
+        if !format.is_interleaved() {
+            return Err(Error::AudioUnit(AudioUnitError::FormatNotSupported));
+        }
+        self.sink = Some(Sink::Writer(writer, try!(Encoder::new(format, dither))));
+        Ok(())
+    }
+}
+
+/// How an output's input channels get to the device's speakers. The two sides each have a
+/// kAudioUnitProperty_AudioChannelLayout, which until it's set is the default layout for however
+/// many channels that side has, and the input is mixed into the device's channels by the matrix
+/// between the two unless a matrix has been given by hand.
+struct Speakers {
+    input: Option<ChannelLayout>,
+    device: Option<ChannelLayout>,
+    /// the device's channel count and sample rate, once its format has been set
+    device_channels: Option<u32>,
+    device_rate: Option<f64>,
+    custom: Option<ChannelMatrix>,
+    /// the matrix in use, worked out again whenever anything it depends on changes
+    matrix: Option<ChannelMatrix>,
+}
+
+impl Speakers {
+
+    fn new() -> Speakers {
+        Speakers { input: None, device: None, device_channels: None, device_rate: None, custom: None, matrix: None }
+    }
+
+    fn set_format(&mut self, formats: &mut Formats, scope: core_audio::AudioUnitScope,
+                  element: core_audio::AudioUnitElement,
+                  format: &core_audio::AudioStreamBasicDescription) -> Result<(), Error> {
+        try!(formats.set(scope, element, format));
+        if scope == core_audio::kAudioUnitScope_Output {
+            self.device_channels = Some(format.mChannelsPerFrame);
+            self.device_rate = Some(format.mSampleRate);
+        }
+        self.matrix = None;
+        Ok(())
+    }
+
+    /// The device side follows whatever is fed in, apart from its channel count and sample rate if
+    /// they've been set, e.g. to play surround on stereo or 44.1kHz at 48kHz.
+    fn initialize(&mut self, formats: &mut Formats) -> Result<(), Error> {
+        let mut device = try!(StreamFormat::from_description(formats.input(0)));
+        if let Some(channels) = self.device_channels {
+            device = try!(device.with_channels(channels));
+        }
+        if let Some(sample_rate) = self.device_rate {
+            device = try!(device.with_sample_rate(sample_rate));
+        }
+        let device = device.to_description();
+        self.matrix = None;
+        formats.set(core_audio::kAudioUnitScope_Output, 0, &device)
+    }
+
+    fn get(&self, formats: &Formats, scope: core_audio::AudioUnitScope,
+           element: core_audio::AudioUnitElement) -> Result<ChannelLayout, Error> {
+        let format = try!(formats.get(scope, element));
+        let layout = if scope == core_audio::kAudioUnitScope_Input { &self.input } else { &self.device };
+        // a layout set before the format changed no longer says anything about the channels
+        Ok(match *layout {
+            Some(ref layout) if layout.channel_count() == format.mChannelsPerFrame => layout.clone(),
+            _ => ChannelLayout::for_channels(format.mChannelsPerFrame),
+        })
+    }
+
+    /// The layout has to have as many channels as the bus's format.
+    fn set(&mut self, formats: &Formats, scope: core_audio::AudioUnitScope, element: core_audio::AudioUnitElement,
+           layout: ChannelLayout) -> Result<(), Error> {
+        let format = try!(formats.get(scope, element));
+        if layout.channel_count() != format.mChannelsPerFrame {
+            return Err(Error::AudioUnit(AudioUnitError::InvalidPropertyValue));
+        }
+        if scope == core_audio::kAudioUnitScope_Input {
+            self.input = Some(layout);
+        }
+        else {
+            self.device = Some(layout);
+        }
+        self.matrix = None;
+        Ok(())
+    }
+
+    /// Mixes with `matrix` rather than the one between the layouts, or goes back to that with
+    /// `None`. The matrix has to fit the channels on either side.
+    fn set_matrix(&mut self, formats: &Formats, matrix: Option<ChannelMatrix>) -> Result<(), Error> {
+        if let Some(ref matrix) = matrix {
+            let (inputs, outputs) = try!(Speakers::channels(formats));
+            if matrix.inputs() != inputs || matrix.outputs() != outputs {
+                return Err(Error::AudioUnit(AudioUnitError::InvalidPropertyValue));
+            }
+        }
+        self.custom = matrix;
+        self.matrix = None;
+        Ok(())
+    }
+
+    /// The matrix to mix the input into the device's channels with.
+    fn matrix(&mut self, formats: &Formats) -> Result<&ChannelMatrix, Error> {
+        if self.matrix.is_none() {
+            let (inputs, outputs) = try!(Speakers::channels(formats));
+            let matrix = match self.custom {
+                Some(ref custom) if custom.inputs() == inputs && custom.outputs() == outputs => custom.clone(),
+                _ => ChannelMatrix::between(&try!(self.get(formats, core_audio::kAudioUnitScope_Input, 0)),
+                                            &try!(self.get(formats, core_audio::kAudioUnitScope_Output, 0)),
+                                            Lfe::Drop),
+            };
+            self.matrix = Some(matrix);
+        }
+        Ok(self.matrix.as_ref().unwrap())
+    }
+
+    fn channels(formats: &Formats) -> Result<(usize, usize), Error> {
+        let device = try!(formats.get(core_audio::kAudioUnitScope_Output, 0));
+        Ok((formats.input(0).mChannelsPerFrame as usize, device.mChannelsPerFrame as usize))
+    }
+
+    fn get_property(&self, formats: &Formats, property_id: core_audio::AudioUnitPropertyID,
+                    scope: core_audio::AudioUnitScope, element: core_audio::AudioUnitElement) -> Result<Vec<u8>, Error> {
+        match property_id {
+            property::AudioChannelLayout::ID => self.get(formats, scope, element).map(|layout| layout.to_bytes()),
+            _ => Err(Error::AudioUnit(AudioUnitError::InvalidProperty)),
+        }
+    }
+
+    fn set_property(&mut self, formats: &Formats, property_id: core_audio::AudioUnitPropertyID,
+                    scope: core_audio::AudioUnitScope, element: core_audio::AudioUnitElement,
+                    data: &[u8]) -> Result<(), Error> {
+        match property_id {
+            property::AudioChannelLayout::ID =>
+                self.set(formats, scope, element, try!(ChannelLayout::from_bytes(data))),
+            _ => Err(Error::AudioUnit(AudioUnitError::InvalidProperty)),
+        }
+    }
+}
+
+/// kHALOutputParam_Volume, a gain from 0 to 1 on everything going out.
+fn volume() -> Parameters {
+    Parameters::new().with(core_audio::kHALOutputParam_Volume, core_audio::kAudioUnitScope_Global, 0, 0.0, 1.0, 1.0)
+}
+
+/// The volume for each frame of the render cycle `parameters` have just been rendered for.
+fn volume_frames(parameters: &Parameters) -> &[f32] {
+    parameters.frames(core_audio::kHALOutputParam_Volume, core_audio::kAudioUnitScope_Global, 0)
+}
+
+/// The device side's format.
+fn device_format(formats: &Formats) -> core_audio::AudioStreamBasicDescription {
+    formats.get(core_audio::kAudioUnitScope_Output, 0).unwrap_or(*formats.input(0))
+}
+
+/// Mixes `input` into `output` with `matrix`, scaling frame `n` by `volume[n]`.
+pub fn pass_through(input: Option<&Buffer>, output: &mut Buffer, volume: &[f32], matrix: &ChannelMatrix) {
+    if let Some(input) = input {
+        matrix.mix(input, output);
+        for out in output.iter_mut() {
+            for (out, &gain) in out.iter_mut().zip(volume.iter()) {
+                *out *= gain;
+            }
+        }
+    }
+}
+
+impl Unit for Output {
+
+    fn input_count(&self) -> u32 {
+        1
+    }
+
+    fn get_format(&self, scope: core_audio::AudioUnitScope,
+                  element: core_audio::AudioUnitElement) -> Result<core_audio::AudioStreamBasicDescription, Error> {
+        self.formats.get(scope, element)
+    }
+
+    fn set_format(&mut self, scope: core_audio::AudioUnitScope, element: core_audio::AudioUnitElement,
+                  format: &core_audio::AudioStreamBasicDescription) -> Result<(), Error> {
+        self.speakers.set_format(&mut self.formats, scope, element, format)
+    }
+
+    fn get_unit_property(&mut self, property_id: core_audio::AudioUnitPropertyID, scope: core_audio::AudioUnitScope,
+                         element: core_audio::AudioUnitElement) -> Result<Vec<u8>, Error> {
+        match property_id {
+            property::RenderQuality::ID => self.rate.get_property(scope),
+            _ => self.speakers.get_property(&self.formats, property_id, scope, element),
+        }
+    }
+
+    fn set_unit_property(&mut self, property_id: core_audio::AudioUnitPropertyID, scope: core_audio::AudioUnitScope,
+                         element: core_audio::AudioUnitElement, data: &[u8]) -> Result<(), Error> {
+        match property_id {
+            property::RenderQuality::ID => self.rate.set_property(scope, data),
+            _ => self.speakers.set_property(&self.formats, property_id, scope, element, data),
+        }
+    }
+
+    fn set_channel_matrix(&mut self, matrix: Option<ChannelMatrix>) -> Result<(), Error> {
+        self.speakers.set_matrix(&self.formats, matrix)
+    }
+
+    fn initialize(&mut self) -> Result<(), Error> {
+        try!(self.speakers.initialize(&mut self.formats));
+        self.rate.check(self.formats.input(0), &device_format(&self.formats))
+    }
+
+    fn parameters(&mut self) -> Option<&mut Parameters> {
+        Some(&mut self.parameters)
+    }
+
+    fn reset(&mut self) {
+        self.parameters.reset();
+        self.rate.reset();
+    }
+
+    fn input_context(&mut self, context: &RenderContext) -> Result<RenderContext, Error> {
+        self.rate.input_context(self.formats.input(0), &device_format(&self.formats), context)
+    }
+
+    fn input_sample_time(&self, sample_time: f64) -> f64 {
+        self.rate.input_sample_time(sample_time, self.formats.input(0), &device_format(&self.formats))
+    }
+
+    fn render(&mut self, context: &RenderContext, inputs: &[Option<Buffer>], output: &mut Buffer) -> Result<(), Error> {
+        self.parameters.render(context.frames);
+        let input = try!(self.rate.convert(inputs[0].as_ref(), context.frames));
+        let matrix = try!(self.speakers.matrix(&self.formats));
+        pass_through(input.as_deref(), output, volume_frames(&self.parameters), matrix);
+        match self.sink {
+            Some(Sink::Memory(ref mut samples)) => {
+                for frame in 0..context.frames {
+                    samples.extend(output.iter().map(|channel| channel[frame]));
+                }
+            },
+            Some(Sink::Writer(ref mut writer, ref mut encoder)) => {
+                let channels = output.len() as u32;
+                if encoder.format().channels() != channels {
+                    *encoder = try!(Encoder::new(&try!(encoder.format().with_channels(channels)), encoder.dither()));
+                }
+                let mut bytes = vec![0; context.frames * encoder.format().bytes_per_frame() as usize];
+                try!(encoder.encode(output, &mut [&mut bytes[..]]));
+                try!(writer.write_all(&bytes).map_err(|_| Error::AudioUnit(AudioUnitError::InvalidOfflineRender)));
+            },
+            None => {},
+        }
+        Ok(())
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
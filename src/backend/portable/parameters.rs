@@ -0,0 +1,249 @@
+//! Audio unit parameters for the portable units, with events scheduled the way
+//! AudioUnitScheduleParameters schedules them.
+
+use backend::{ParameterChange, ParameterEvent};
+use core_audio;
+use error::{AudioUnitError, Error};
+
+/// A parameter, its current value and what's been scheduled for it.
+struct Parameter {
+    id: core_audio::AudioUnitParameterID,
+    scope: core_audio::AudioUnitScope,
+    element: core_audio::AudioUnitElement,
+    min: f32,
+    max: f32,
+    value: f32,
+    /// applied by the next render cycle, in the order they were scheduled
+    events: Vec<ParameterChange>,
+    /// the value for each frame of the last render cycle
+    frames: Vec<f32>,
+}
+
+/// The parameters a unit has.
+pub struct Parameters {
+    parameters: Vec<Parameter>,
+}
+
+impl Parameters {
+
+    pub fn new() -> Parameters {
+        Parameters { parameters: Vec::new() }
+    }
+
+    /// Adds a parameter ranging over `min..=max`, starting out at `default`.
+    pub fn with(mut self, id: core_audio::AudioUnitParameterID, scope: core_audio::AudioUnitScope,
+                element: core_audio::AudioUnitElement, min: f32, max: f32, default: f32) -> Parameters {
+        self.parameters.push(Parameter {
+            id,
+            scope,
+            element,
+            min,
+            max,
+            value: default,
+            events: Vec::new(),
+            frames: Vec::new(),
+        });
+        self
+    }
+
+    /// AudioUnitGetParameter
+    pub fn get(&self, id: core_audio::AudioUnitParameterID, scope: core_audio::AudioUnitScope,
+               element: core_audio::AudioUnitElement) -> Result<f32, Error> {
+        self.find(id, scope, element).map(|index| self.parameters[index].value)
+    }
+
+    /// AudioUnitSetParameter. The value changes straight away, or `buffer_offset` frames into the
+    /// next render cycle if that's not 0.
+    pub fn set(&mut self, id: core_audio::AudioUnitParameterID, scope: core_audio::AudioUnitScope,
+               element: core_audio::AudioUnitElement, value: f32, buffer_offset: u32) -> Result<(), Error> {
+        let index = try!(self.find(id, scope, element));
+        let parameter = &mut self.parameters[index];
+        try!(parameter.check(value));
+        if buffer_offset == 0 {
+            parameter.value = value;
+        }
+        else {
+            parameter.events.push(ParameterChange::Immediate { buffer_offset, value });
+        }
+        Ok(())
+    }
+
+    /// AudioUnitScheduleParameters. Nothing is scheduled unless all the events are valid.
+    pub fn schedule(&mut self, events: &[ParameterEvent]) -> Result<(), Error> {
+        let mut indices = Vec::with_capacity(events.len());
+        for event in events {
+            let index = try!(self.find(event.parameter, event.scope, event.element));
+            let parameter = &self.parameters[index];
+            match event.change {
+                ParameterChange::Immediate { value, .. } => try!(parameter.check(value)),
+                ParameterChange::Ramp { start_value, end_value, .. } => {
+                    try!(parameter.check(start_value));
+                    try!(parameter.check(end_value));
+                },
+            }
+            indices.push(index);
+        }
+        for (event, index) in events.iter().zip(indices) {
+            self.parameters[index].events.push(event.change);
+        }
+        Ok(())
+    }
+
+    /// Starts a render cycle of `frames` frames: works out every parameter's value for each frame
+    /// from the events scheduled for it, which are then done with.
+    pub fn render(&mut self, frames: usize) {
+        for parameter in &mut self.parameters {
+            parameter.render(frames);
+        }
+    }
+
+    /// The value of a parameter for each frame of the cycle `render` started.
+    pub fn frames(&self, id: core_audio::AudioUnitParameterID, scope: core_audio::AudioUnitScope,
+                  element: core_audio::AudioUnitElement) -> &[f32] {
+        match self.find(id, scope, element) {
+            Ok(index) => &self.parameters[index].frames,
+            Err(_) => &[],
+        }
+    }
+
+    /// Drops whatever's scheduled, the way AudioUnitReset does. Values stay as they are.
+    pub fn reset(&mut self) {
+        for parameter in &mut self.parameters {
+            parameter.events.clear();
+        }
+    }
+
+    fn find(&self, id: core_audio::AudioUnitParameterID, scope: core_audio::AudioUnitScope,
+            element: core_audio::AudioUnitElement) -> Result<usize, Error> {
+        let position = self.parameters.iter().position(|parameter| {
+            parameter.id == id && parameter.scope == scope && parameter.element == element
+        });
+        match position {
+            Some(index) => Ok(index),
+            None if !self.parameters.iter().any(|parameter| parameter.id == id) =>
+                Err(Error::AudioUnit(AudioUnitError::InvalidParameter)),
+            None if !self.parameters.iter().any(|parameter| parameter.id == id && parameter.scope == scope) =>
+                Err(Error::AudioUnit(AudioUnitError::InvalidScope)),
+            None => Err(Error::AudioUnit(AudioUnitError::InvalidElement)),
+        }
+    }
+}
+
+impl Parameter {
+
+    fn check(&self, value: f32) -> Result<(), Error> {
+        if value >= self.min && value <= self.max {
+            Ok(())
+        }
+        else {
+            Err(Error::AudioUnit(AudioUnitError::InvalidParameterValue))
+        }
+    }
+
+    /// Later events take over from earlier ones from the frame they start on.
+    fn render(&mut self, frames: usize) {
+        self.frames.clear();
+        self.frames.resize(frames, self.value);
+        let mut events: Vec<ParameterChange> = self.events.drain(..).collect();
+        // a stable sort, so events starting on the same frame keep the order they came in
+        events.sort_by_key(|change| match *change {
+            ParameterChange::Immediate { buffer_offset, .. } => buffer_offset as i64,
+            ParameterChange::Ramp { start_buffer_offset, .. } => start_buffer_offset as i64,
+        });
+        for change in events {
+            match change {
+                ParameterChange::Immediate { buffer_offset, value } => {
+                    for frame in (buffer_offset as usize).min(frames)..frames {
+                        self.frames[frame] = value;
+                    }
+                },
+                ParameterChange::Ramp { start_buffer_offset, duration_in_frames, start_value, end_value } => {
+                    for frame in (start_buffer_offset.max(0) as usize).min(frames)..frames {
+                        let elapsed = (frame as i64 - start_buffer_offset as i64) as f64;
+                        self.frames[frame] = if elapsed >= duration_in_frames as f64 {
+                            end_value
+                        }
+                        else {
+                            start_value + ((end_value - start_value) as f64 * elapsed / duration_in_frames as f64) as f32
+                        };
+                    }
+                },
+            }
+        }
+        if let Some(&last) = self.frames.last() {
+            self.value = last;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::Parameters;
+    use backend::ParameterEvent;
+    use core_audio;
+    use error::{AudioUnitError, Error};
+
+    const GAIN: core_audio::AudioUnitParameterID = 0;
+    const GLOBAL: core_audio::AudioUnitScope = core_audio::kAudioUnitScope_Global;
+
+    fn gain() -> Parameters {
+        Parameters::new().with(GAIN, GLOBAL, 0, 0.0, 1.0, 1.0)
+    }
+
+    #[test]
+    fn ramps_and_jumps_within_a_cycle() {
+        let mut parameters = gain();
+        parameters.schedule(&[ParameterEvent::ramp(GAIN, GLOBAL, 0, 2, 4, 1.0, 0.0),
+                              ParameterEvent::immediate(GAIN, GLOBAL, 0, 8, 0.5)]).unwrap();
+        parameters.render(10);
+        assert_eq!(parameters.frames(GAIN, GLOBAL, 0), &[1.0, 1.0, 1.0, 0.75, 0.5, 0.25, 0.0, 0.0, 0.5, 0.5]);
+        assert_eq!(parameters.get(GAIN, GLOBAL, 0).unwrap(), 0.5);
+
+        // events only apply to the cycle after they're scheduled
+        parameters.render(2);
+        assert_eq!(parameters.frames(GAIN, GLOBAL, 0), &[0.5, 0.5]);
+    }
+
+    #[test]
+    fn ramps_carry_on_with_a_negative_offset() {
+        let mut parameters = gain();
+        parameters.schedule(&[ParameterEvent::ramp(GAIN, GLOBAL, 0, 0, 8, 0.0, 1.0)]).unwrap();
+        parameters.render(4);
+        assert_eq!(parameters.frames(GAIN, GLOBAL, 0), &[0.0, 0.125, 0.25, 0.375]);
+        // not scheduled again, so it stops where it got to
+        parameters.render(2);
+        assert_eq!(parameters.frames(GAIN, GLOBAL, 0), &[0.375, 0.375]);
+
+        parameters.schedule(&[ParameterEvent::ramp(GAIN, GLOBAL, 0, -4, 8, 0.0, 1.0)]).unwrap();
+        parameters.render(6);
+        assert_eq!(parameters.frames(GAIN, GLOBAL, 0), &[0.5, 0.625, 0.75, 0.875, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn set_applies_now_or_at_an_offset() {
+        let mut parameters = gain();
+        parameters.set(GAIN, GLOBAL, 0, 0.25, 0).unwrap();
+        assert_eq!(parameters.get(GAIN, GLOBAL, 0).unwrap(), 0.25);
+        parameters.set(GAIN, GLOBAL, 0, 0.75, 2).unwrap();
+        assert_eq!(parameters.get(GAIN, GLOBAL, 0).unwrap(), 0.25);
+        parameters.render(3);
+        assert_eq!(parameters.frames(GAIN, GLOBAL, 0), &[0.25, 0.25, 0.75]);
+    }
+
+    #[test]
+    fn refuses_what_the_unit_does_not_have() {
+        let mut parameters = gain();
+        let error = |err| Error::AudioUnit(err);
+        assert_eq!(parameters.get(1, GLOBAL, 0).unwrap_err(), error(AudioUnitError::InvalidParameter));
+        assert_eq!(parameters.get(GAIN, core_audio::kAudioUnitScope_Input, 0).unwrap_err(),
+                   error(AudioUnitError::InvalidScope));
+        assert_eq!(parameters.get(GAIN, GLOBAL, 1).unwrap_err(), error(AudioUnitError::InvalidElement));
+        assert_eq!(parameters.set(GAIN, GLOBAL, 0, 2.0, 0).unwrap_err(), error(AudioUnitError::InvalidParameterValue));
+        // one bad event and none of them are scheduled
+        assert!(parameters.schedule(&[ParameterEvent::immediate(GAIN, GLOBAL, 0, 0, 0.0),
+                                      ParameterEvent::ramp(GAIN, GLOBAL, 0, 0, 4, 0.0, -1.0)]).is_err());
+        parameters.render(1);
+        assert_eq!(parameters.frames(GAIN, GLOBAL, 0), &[1.0]);
+    }
+}
@@ -0,0 +1,31 @@
+//! Decoding interleaved linear PCM file data into the portable backend's float buffers.
+
+use core_audio;
+use backend::portable::unit::Buffer;
+use pcm;
+use stream_format::StreamFormat;
+
+/// Whether `format` is interleaved linear PCM that `decode` can read.
+pub fn can_decode(format: &core_audio::AudioStreamBasicDescription) -> bool {
+    StreamFormat::from_description(format)
+        .map(|format| format.is_interleaved() && pcm::can_convert(&format))
+        .unwrap_or(false)
+}
+
+/// Decodes whole frames from `data` into `output`, starting at `output[channel][offset]`.
+/// `format` must satisfy `can_decode`. File channels beyond the buffer's are dropped and buffer
+/// channels beyond the file's are left alone, except that mono files are copied to every channel.
+pub fn decode(format: &core_audio::AudioStreamBasicDescription, data: &[u8], output: &mut Buffer, offset: usize) {
+    let format = match StreamFormat::from_description(format) {
+        Ok(format) => format,
+        Err(_) => return,
+    };
+    let frames = pcm::decode(&format, &[data], output, offset).unwrap_or(0);
+    if format.channels() == 1 {
+        if let Some((first, rest)) = output.split_first_mut() {
+            for channel in rest {
+                channel[offset..offset + frames].copy_from_slice(&first[offset..offset + frames]);
+            }
+        }
+    }
+}
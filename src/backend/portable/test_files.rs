@@ -0,0 +1,159 @@
+//! WAVE and CAF files for the tests, built in memory and written to the temp directory for as long
+//! as they're needed, and what the parsers' tests build their own files from.
+
+use audio_file::AudioFile;
+use backend::PortableBackend;
+use backend::portable::file::FileData;
+use backend::portable::pcm;
+use error::{AudioFileError, Error};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// tells apart the files of tests running at the same time that happen to use the same name
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// A file in the temp directory, removed again when it's dropped.
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+
+    /// Writes `bytes` to a new file whose name ends in `name`.
+    pub fn new(name: &str, bytes: &[u8]) -> TempFile {
+        let unique = format!("playfile-{}-{}-{}", process::id(), NEXT.fetch_add(1, Ordering::SeqCst), name);
+        let path = ::std::env::temp_dir().join(unique);
+        fs::File::create(&path).and_then(|mut file| file.write_all(bytes)).unwrap();
+        TempFile { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Opens `bytes` as a file named `name`. The portable backend reads the whole file in, so it's
+/// removed again straight away.
+pub fn open(name: &str, bytes: &[u8]) -> AudioFile<PortableBackend> {
+    let file = TempFile::new(name, bytes);
+    AudioFile::open(file.path()).unwrap()
+}
+
+/// A little endian 16 bit WAVE file of `frames` frames of `channels` channels, `sample(frame,
+/// channel)` giving each sample. With a channel mask it's WAVE_FORMAT_EXTENSIBLE.
+pub fn wav<F: Fn(u32, u32) -> i16>(sample_rate: u32, channels: u16, channel_mask: Option<u32>, frames: u32,
+                                   sample: F) -> Vec<u8> {
+    let block_align = channels * 2;
+    let mut fmt = Vec::new();
+    fmt.extend_from_slice(&(if channel_mask.is_some() { 0xfffe } else { 1 } as u16).to_le_bytes());
+    fmt.extend_from_slice(&channels.to_le_bytes());
+    fmt.extend_from_slice(&sample_rate.to_le_bytes());
+    fmt.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    fmt.extend_from_slice(&block_align.to_le_bytes());
+    fmt.extend_from_slice(&16u16.to_le_bytes());
+    if let Some(mask) = channel_mask {
+        fmt.extend_from_slice(&[22, 0, 16, 0]);
+        fmt.extend_from_slice(&mask.to_le_bytes());
+        fmt.extend_from_slice(&1u16.to_le_bytes());
+        fmt.extend_from_slice(&super::wav::SUBTYPE_GUID_TAIL);
+    }
+    let mut data = Vec::with_capacity(frames as usize * block_align as usize);
+    for frame in 0..frames {
+        for channel in 0..channels as u32 {
+            data.extend_from_slice(&sample(frame, channel).to_le_bytes());
+        }
+    }
+
+    let fmt = riff_chunk(b"fmt ", &fmt);
+    let data = riff_chunk(b"data", &data);
+    let mut bytes = b"RIFF".to_vec();
+    bytes.extend_from_slice(&(4 + fmt.len() as u32 + data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(b"WAVE");
+    bytes.extend(fmt);
+    bytes.extend(data);
+    bytes
+}
+
+/// A mono 16 bit WAVE file holding `samples`.
+pub fn mono_wav(sample_rate: u32, samples: &[i16]) -> Vec<u8> {
+    wav(sample_rate, 1, None, samples.len() as u32, |frame, _| samples[frame as usize])
+}
+
+/// A mono big endian 16 bit CAF file holding `samples`, the first `priming` and last `remainder`
+/// of which its packet table says aren't audio.
+pub fn caf(sample_rate: f64, samples: &[i16], priming: u32, remainder: u32) -> Vec<u8> {
+    let frames = samples.len() as u64;
+    let mut desc = sample_rate.to_bits().to_be_bytes().to_vec();
+    desc.extend_from_slice(b"lpcm");
+    for &field in &[0u32, 2, 1, 1, 16] {
+        desc.extend_from_slice(&field.to_be_bytes());
+    }
+    let mut pakt = frames.to_be_bytes().to_vec();
+    pakt.extend_from_slice(&(frames - priming as u64 - remainder as u64).to_be_bytes());
+    pakt.extend_from_slice(&priming.to_be_bytes());
+    pakt.extend_from_slice(&remainder.to_be_bytes());
+    let mut data = 0u32.to_be_bytes().to_vec();
+    for &sample in samples {
+        data.extend_from_slice(&sample.to_be_bytes());
+    }
+
+    let mut bytes = b"caff\x00\x01\x00\x00".to_vec();
+    bytes.extend(caf_chunk(b"desc", &desc));
+    bytes.extend(caf_chunk(b"pakt", &pakt));
+    bytes.extend(caf_chunk(b"data", &data));
+    bytes
+}
+
+/// A RIFF chunk: its id, its size as a little endian 32 bit number, and its body, padded to an
+/// even length.
+pub fn riff_chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+    padded_chunk(id, &(body.len() as u32).to_le_bytes(), body)
+}
+
+/// An IFF chunk, as in AIFF: like a RIFF chunk, with its size big endian.
+pub fn iff_chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+    padded_chunk(id, &(body.len() as u32).to_be_bytes(), body)
+}
+
+fn padded_chunk(id: &[u8], size: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut bytes = id.to_vec();
+    bytes.extend_from_slice(size);
+    bytes.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        bytes.push(0);
+    }
+    bytes
+}
+
+/// A CAF chunk: its id, its size as a big endian 64 bit number, and its body, unpadded.
+pub fn caf_chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut bytes = id.to_vec();
+    bytes.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    bytes.extend_from_slice(body);
+    bytes
+}
+
+/// Fails unless `result` is `expected`.
+pub fn assert_error<T>(result: Result<T, Error>, expected: AudioFileError) {
+    match result {
+        Err(Error::AudioFile(err)) => assert_eq!(err.to_os_status(), expected.to_os_status()),
+        Err(err) => panic!("expected {:?}, got {:?}", expected, err),
+        Ok(_) => panic!("expected {:?}, parsed fine", expected),
+    }
+}
+
+/// The first sample of the first channel of a parsed file's audio.
+pub fn first_sample(file: &FileData) -> f32 {
+    let mut buffer = vec![vec![0.0; 1]];
+    pcm::decode(&file.format, &file.audio_data[..file.format.mBytesPerFrame as usize], &mut buffer, 0);
+    buffer[0][0]
+}
@@ -0,0 +1,232 @@
+//! What every audio unit in the portable backend looks like to the engine.
+
+use backend::portable::parameters::Parameters;
+use channel_matrix::ChannelMatrix;
+use core_audio;
+use error::{AudioUnitError, Error};
+use property::{self, Property, PropertyValue};
+use std::any::Any;
+
+/// Audio flowing between units: one `Vec<f32>` per channel, all the same length. This is the
+/// portable equivalent of the canonical non-interleaved float format.
+pub type Buffer = Vec<Vec<f32>>;
+
+/// Where the graph is up to when a unit is asked to render.
+#[derive(Copy, Clone, Debug)]
+pub struct RenderContext {
+    /// sample time of the first frame of this render cycle
+    pub sample_time: f64,
+    /// number of frames being rendered
+    pub frames: usize,
+}
+
+/// A node's audio unit, as seen by the engine.
+pub trait Unit: Send {
+
+    /// number of input busses
+    fn input_count(&self) -> u32 {
+        0
+    }
+
+    /// kAudioUnitProperty_StreamFormat getter
+    fn get_format(&self, scope: core_audio::AudioUnitScope,
+                  element: core_audio::AudioUnitElement) -> Result<core_audio::AudioStreamBasicDescription, Error>;
+
+    /// kAudioUnitProperty_StreamFormat setter
+    fn set_format(&mut self, scope: core_audio::AudioUnitScope, element: core_audio::AudioUnitElement,
+                  format: &core_audio::AudioStreamBasicDescription) -> Result<(), Error>;
+
+    /// AudioUnitGetProperty for properties only this kind of unit has, see `get_property`
+    fn get_unit_property(&mut self, _property_id: core_audio::AudioUnitPropertyID, _scope: core_audio::AudioUnitScope,
+                         _element: core_audio::AudioUnitElement) -> Result<Vec<u8>, Error> {
+        Err(Error::AudioUnit(AudioUnitError::InvalidProperty))
+    }
+
+    /// AudioUnitSetProperty for properties only this kind of unit has, see `set_property`
+    fn set_unit_property(&mut self, _property_id: core_audio::AudioUnitPropertyID, _scope: core_audio::AudioUnitScope,
+                         _element: core_audio::AudioUnitElement, _data: &[u8]) -> Result<(), Error> {
+        Err(Error::AudioUnit(AudioUnitError::InvalidProperty))
+    }
+
+    /// kAudioUnitProperty_ElementCount setter for the input scope, for units whose number of
+    /// inputs can change
+    fn set_input_count(&mut self, _count: u32) -> Result<(), Error> {
+        Err(Error::AudioUnit(AudioUnitError::PropertyNotWritable))
+    }
+
+    /// mixes with `matrix` instead of working out how from the channel layouts, for units that mix
+    /// channels
+    fn set_channel_matrix(&mut self, _matrix: Option<ChannelMatrix>) -> Result<(), Error> {
+        Err(Error::AudioUnit(AudioUnitError::InvalidProperty))
+    }
+
+    /// the unit's parameters, if it has any
+    fn parameters(&mut self) -> Option<&mut Parameters> {
+        None
+    }
+
+    /// called by AUGraphInitialize once the graph's formats have been propagated
+    fn initialize(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// called by AudioUnitReset, to go back to how it was just after initializing
+    fn reset(&mut self) {
+    }
+
+    /// whether the unit has nothing more of its own to play at or after `sample_time`, e.g. a file
+    /// player whose regions have all played out
+    fn finished(&self, _sample_time: f64) -> bool {
+        true
+    }
+
+    /// what to pull from the inputs to render `context`, which is `context` itself unless the
+    /// unit changes the sample rate, when it's however many frames it needs at the input's rate
+    fn input_context(&mut self, context: &RenderContext) -> Result<RenderContext, Error> {
+        Ok(*context)
+    }
+
+    /// the sample time on the inputs' timeline that `sample_time` on the output's falls on
+    fn input_sample_time(&self, sample_time: f64) -> f64 {
+        sample_time
+    }
+
+    /// fills `output` (already sized to the output format and `context.frames`) from `inputs`,
+    /// where `inputs[bus]` is `None` for unconnected busses
+    fn render(&mut self, context: &RenderContext, inputs: &[Option<Buffer>], output: &mut Buffer) -> Result<(), Error>;
+
+    /// lets the backend get at the concrete unit for unit specific properties
+    fn as_any(&mut self) -> &mut dyn Any;
+}
+
+/// AudioUnitGetProperty: the properties every unit has, then the unit's own.
+pub fn get_property(unit: &mut dyn Unit, property_id: core_audio::AudioUnitPropertyID,
+                    scope: core_audio::AudioUnitScope, element: core_audio::AudioUnitElement) -> Result<Vec<u8>, Error> {
+    match property_id {
+        property::StreamFormat::ID => unit.get_format(scope, element).map(|format| format.to_bytes()),
+        property::ElementCount::ID => {
+            let count = match scope {
+                core_audio::kAudioUnitScope_Global => 1,
+                core_audio::kAudioUnitScope_Input  => unit.input_count(),
+                core_audio::kAudioUnitScope_Output => 1,
+                _ => return Err(Error::AudioUnit(AudioUnitError::InvalidScope)),
+            };
+            Ok(count.to_bytes())
+        },
+        // everything is rendered as it's pulled
+        property::Latency::ID => Ok(0.0f64.to_bytes()),
+        _ => unit.get_unit_property(property_id, scope, element),
+    }
+}
+
+/// AudioUnitSetProperty: the properties every unit has, then the unit's own.
+pub fn set_property(unit: &mut dyn Unit, property_id: core_audio::AudioUnitPropertyID,
+                    scope: core_audio::AudioUnitScope, element: core_audio::AudioUnitElement,
+                    data: &[u8]) -> Result<(), Error> {
+    match property_id {
+        property::StreamFormat::ID => unit.set_format(scope, element, &try!(PropertyValue::from_bytes(data))),
+        property::ElementCount::ID if scope == core_audio::kAudioUnitScope_Input =>
+            unit.set_input_count(try!(u32::from_bytes(data))),
+        property::ElementCount::ID | property::Latency::ID => Err(Error::AudioUnit(AudioUnitError::PropertyNotWritable)),
+        _ => unit.set_unit_property(property_id, scope, element, data),
+    }
+}
+
+/// The canonical format: native endian, packed, non-interleaved 32 bit float.
+pub fn canonical_format(sample_rate: f64, channels: u32) -> core_audio::AudioStreamBasicDescription {
+    let mut flags = core_audio::kAudioFormatFlagIsFloat | core_audio::kAudioFormatFlagIsPacked |
+                    core_audio::kAudioFormatFlagIsNonInterleaved;
+    if cfg!(target_endian = "big") {
+        flags |= core_audio::kAudioFormatFlagIsBigEndian;
+    }
+    core_audio::AudioStreamBasicDescription {
+        mSampleRate: sample_rate,
+        mFormatID: core_audio::kAudioFormatLinearPCM,
+        mFormatFlags: flags as u32,
+        mBytesPerPacket: 4,
+        mFramesPerPacket: 1,
+        mBytesPerFrame: 4,
+        mChannelsPerFrame: channels,
+        mBitsPerChannel: 32,
+        mReserved: 0,
+    }
+}
+
+/// The stream formats of a unit's input and output busses. Units only deal in the canonical
+/// format, so anything else is refused the way an AU refuses a format it can't handle.
+pub struct Formats {
+    inputs: Vec<core_audio::AudioStreamBasicDescription>,
+    outputs: Vec<core_audio::AudioStreamBasicDescription>,
+}
+
+impl Formats {
+
+    /// `inputs` and `outputs` busses, all stereo at 44.1kHz like a freshly opened AU
+    pub fn new(inputs: u32, outputs: u32) -> Formats {
+        let format = canonical_format(44100.0, 2);
+        Formats {
+            inputs: vec![format; inputs as usize],
+            outputs: vec![format; outputs as usize],
+        }
+    }
+
+    pub fn get(&self, scope: core_audio::AudioUnitScope,
+               element: core_audio::AudioUnitElement) -> Result<core_audio::AudioStreamBasicDescription, Error> {
+        let busses = try!(self.busses(scope));
+        busses.get(element as usize).cloned().ok_or(Error::AudioUnit(AudioUnitError::InvalidElement))
+    }
+
+    pub fn set(&mut self, scope: core_audio::AudioUnitScope, element: core_audio::AudioUnitElement,
+               format: &core_audio::AudioStreamBasicDescription) -> Result<(), Error> {
+        try!(check_format(format));
+        let busses = match scope {
+            core_audio::kAudioUnitScope_Input  => &mut self.inputs,
+            core_audio::kAudioUnitScope_Output => &mut self.outputs,
+            _ => return Err(Error::AudioUnit(AudioUnitError::InvalidScope)),
+        };
+        match busses.get_mut(element as usize) {
+            Some(bus) => {
+                *bus = *format;
+                Ok(())
+            },
+            None => Err(Error::AudioUnit(AudioUnitError::InvalidElement)),
+        }
+    }
+
+    /// the format of input bus `element`, which must exist
+    pub fn input(&self, element: usize) -> &core_audio::AudioStreamBasicDescription {
+        &self.inputs[element]
+    }
+
+    pub fn input_count(&self) -> u32 {
+        self.inputs.len() as u32
+    }
+
+    /// adds or removes input busses, new ones starting out like a freshly opened AU's
+    pub fn set_input_count(&mut self, count: u32) {
+        self.inputs.resize(count as usize, canonical_format(44100.0, 2));
+    }
+
+    fn busses(&self, scope: core_audio::AudioUnitScope) -> Result<&Vec<core_audio::AudioStreamBasicDescription>, Error> {
+        match scope {
+            core_audio::kAudioUnitScope_Input  => Ok(&self.inputs),
+            core_audio::kAudioUnitScope_Output => Ok(&self.outputs),
+            _ => Err(Error::AudioUnit(AudioUnitError::InvalidScope)),
+        }
+    }
+}
+
+fn check_format(format: &core_audio::AudioStreamBasicDescription) -> Result<(), Error> {
+    let canonical = canonical_format(format.mSampleRate, format.mChannelsPerFrame);
+    let flags = core_audio::kAudioFormatFlagIsFloat | core_audio::kAudioFormatFlagIsNonInterleaved |
+                core_audio::kAudioFormatFlagIsBigEndian;
+    if format.mFormatID != canonical.mFormatID ||
+       format.mFormatFlags & flags as u32 != canonical.mFormatFlags & flags as u32 ||
+       format.mBitsPerChannel != 32 ||
+       format.mBytesPerFrame != 4 ||
+       format.mChannelsPerFrame == 0 ||
+       format.mSampleRate.is_nan() || format.mSampleRate <= 0.0 {
+        return Err(Error::AudioUnit(AudioUnitError::FormatNotSupported));
+    }
+    Ok(())
+}
@@ -0,0 +1,301 @@
+//! RIFF/WAVE files: integer PCM, IEEE float and WAVE_FORMAT_EXTENSIBLE.
+
+use backend::portable::bytes::ByteReader;
+use backend::portable::file::FileData;
+use core_audio;
+use error::{AudioFileError, Error};
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// The tail shared by the KSDATAFORMAT_SUBTYPE GUIDs, whose first two bytes are the format tag.
+pub const SUBTYPE_GUID_TAIL: [u8; 14] = [0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00,
+                                         0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71];
+
+/// The parts of a `fmt ` chunk we care about.
+struct Fmt {
+    format_tag: u16,
+    channels: u16,
+    sample_rate: u32,
+    block_align: u16,
+    bits_per_sample: u16,
+    valid_bits_per_sample: u16,
+    channel_mask: Option<u32>,
+}
+
+/// Whether `bytes` starts like a WAVE file.
+pub fn sniff(bytes: &[u8]) -> bool {
+    bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WAVE"
+}
+
+pub fn parse(bytes: &[u8]) -> Result<FileData, Error> {
+    let mut reader = ByteReader::new(bytes);
+    // RIFF, its size (which plenty of writers get wrong, so it's ignored) and WAVE
+    try!(reader.skip(12));
+
+    let mut fmt = None;
+    let mut data = None;
+    while reader.remaining() >= 8 {
+        let id = try!(reader.fourcc());
+        let size = try!(reader.u32_le()) as usize;
+        match &id {
+            b"fmt " => fmt = Some(try!(parse_fmt(try!(reader.take(size))))),
+            // a data chunk running off the end is usually a file that was never finalized,
+            // so play what's there
+            b"data" => {
+                let available = size.min(reader.remaining());
+                data = Some(try!(reader.take(available)));
+            },
+            _ => try!(reader.skip(size)),
+        }
+        // chunks are padded to an even length
+        if size % 2 == 1 && reader.remaining() > 0 {
+            try!(reader.skip(1));
+        }
+    }
+
+    let fmt = try!(fmt.ok_or(Error::AudioFile(AudioFileError::InvalidFile)));
+    let data = try!(data.ok_or(Error::AudioFile(AudioFileError::InvalidFile)));
+    let format = try!(stream_format(&fmt));
+    let packet_count = (data.len() / fmt.block_align as usize) as u64;
+    let mut file = FileData::new(format, packet_count,
+                                 data[..packet_count as usize * fmt.block_align as usize].to_vec());
+    file.channel_mask = fmt.channel_mask;
+    Ok(file)
+}
+
+fn parse_fmt(body: &[u8]) -> Result<Fmt, Error> {
+    let mut reader = ByteReader::new(body);
+    let format_tag = try!(reader.u16_le());
+    let channels = try!(reader.u16_le());
+    let sample_rate = try!(reader.u32_le());
+    try!(reader.skip(4)); // average bytes per second
+    let block_align = try!(reader.u16_le());
+    let bits_per_sample = try!(reader.u16_le());
+    let mut fmt = Fmt {
+        format_tag,
+        channels,
+        sample_rate,
+        block_align,
+        bits_per_sample,
+        valid_bits_per_sample: bits_per_sample,
+        channel_mask: None,
+    };
+
+    if fmt.format_tag == WAVE_FORMAT_EXTENSIBLE {
+        let extension_size = try!(reader.u16_le());
+        if extension_size < 22 {
+            return Err(Error::AudioFile(AudioFileError::InvalidChunk));
+        }
+        let valid_bits = try!(reader.u16_le());
+        if valid_bits != 0 {
+            fmt.valid_bits_per_sample = valid_bits;
+        }
+        fmt.channel_mask = Some(try!(reader.u32_le()));
+        let sub_format = try!(reader.take(16));
+        if sub_format[2..] != SUBTYPE_GUID_TAIL {
+            return Err(Error::AudioFile(AudioFileError::UnsupportedDataFormat));
+        }
+        fmt.format_tag = sub_format[0] as u16 | (sub_format[1] as u16) << 8;
+    }
+    Ok(fmt)
+}
+
+/// The description AudioFileGetProperty gives for a WAVE file's data format: little endian,
+/// unsigned 8 bit and signed wider integers, and samples narrower than their container aligned
+/// high in it.
+fn stream_format(fmt: &Fmt) -> Result<core_audio::AudioStreamBasicDescription, Error> {
+    if fmt.channels == 0 || fmt.sample_rate == 0 || fmt.block_align == 0 ||
+       !fmt.block_align.is_multiple_of(fmt.channels) {
+        return Err(Error::AudioFile(AudioFileError::InvalidFile));
+    }
+    let container_bits = (fmt.block_align / fmt.channels) as u32 * 8;
+    let bits = fmt.valid_bits_per_sample as u32;
+    if bits == 0 || bits > container_bits || fmt.bits_per_sample as u32 > container_bits {
+        return Err(Error::AudioFile(AudioFileError::InvalidFile));
+    }
+
+    let mut flags = match fmt.format_tag {
+        WAVE_FORMAT_PCM if container_bits <= 32 => {
+            if container_bits == 8 { 0 } else { core_audio::kAudioFormatFlagIsSignedInteger }
+        },
+        WAVE_FORMAT_IEEE_FLOAT if bits == 32 || bits == 64 => core_audio::kAudioFormatFlagIsFloat,
+        _ => return Err(Error::AudioFile(AudioFileError::UnsupportedDataFormat)),
+    };
+    flags |= if bits == container_bits {
+        core_audio::kAudioFormatFlagIsPacked
+    }
+    else {
+        core_audio::kAudioFormatFlagIsAlignedHigh
+    };
+
+    Ok(core_audio::AudioStreamBasicDescription {
+        mSampleRate: fmt.sample_rate as f64,
+        mFormatID: core_audio::kAudioFormatLinearPCM,
+        mFormatFlags: flags as u32,
+        mBytesPerPacket: fmt.block_align as u32,
+        mFramesPerPacket: 1,
+        mBytesPerFrame: fmt.block_align as u32,
+        mChannelsPerFrame: fmt.channels as u32,
+        mBitsPerChannel: bits,
+        mReserved: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{parse, sniff};
+    use backend::portable::pcm;
+    use backend::portable::test_files::{assert_error, first_sample, riff_chunk};
+    use core_audio;
+    use error::AudioFileError;
+
+    fn fmt_body(tag: u16, channels: u16, rate: u32, block_align: u16, bits: u16) -> Vec<u8> {
+        let byte_rate = rate * block_align as u32;
+        let mut body = vec![tag as u8, (tag >> 8) as u8, channels as u8, (channels >> 8) as u8];
+        for value in &[rate, byte_rate] {
+            body.extend_from_slice(&[*value as u8, (*value >> 8) as u8, (*value >> 16) as u8, (*value >> 24) as u8]);
+        }
+        body.extend_from_slice(&[block_align as u8, (block_align >> 8) as u8, bits as u8, (bits >> 8) as u8]);
+        body
+    }
+
+    fn extensible_body(sub_format: u16, channels: u16, block_align: u16, bits: u16, valid_bits: u16,
+                       mask: u32) -> Vec<u8> {
+        let mut body = fmt_body(0xfffe, channels, 48000, block_align, bits);
+        body.extend_from_slice(&[22, 0, valid_bits as u8, (valid_bits >> 8) as u8]);
+        body.extend_from_slice(&[mask as u8, (mask >> 8) as u8, (mask >> 16) as u8, (mask >> 24) as u8]);
+        body.extend_from_slice(&[sub_format as u8, (sub_format >> 8) as u8]);
+        body.extend_from_slice(&super::SUBTYPE_GUID_TAIL);
+        body
+    }
+
+    fn wave(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = chunks.iter().flat_map(|chunk| chunk.iter().cloned()).collect();
+        let size = body.len() as u32 + 4;
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&[size as u8, (size >> 8) as u8, (size >> 16) as u8, (size >> 24) as u8]);
+        bytes.extend_from_slice(b"WAVE");
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    #[test]
+    fn reads_16_bit_stereo_pcm() {
+        let bytes = wave(&[riff_chunk(b"fmt ", &fmt_body(1, 2, 44100, 4, 16)),
+                           riff_chunk(b"LIST", b"odd"),
+                           riff_chunk(b"data", &[0, 0x40, 0, 0xc0, 1, 0, 2, 0, 3])]);
+        assert!(sniff(&bytes));
+        let file = parse(&bytes).unwrap();
+        assert_eq!(file.format.mSampleRate, 44100.0);
+        assert_eq!(file.format.mFormatID, core_audio::kAudioFormatLinearPCM);
+        assert_eq!(file.format.mFormatFlags,
+                   (core_audio::kAudioFormatFlagIsSignedInteger | core_audio::kAudioFormatFlagIsPacked) as u32);
+        assert_eq!(file.format.mBytesPerPacket, 4);
+        assert_eq!(file.format.mFramesPerPacket, 1);
+        assert_eq!(file.format.mBytesPerFrame, 4);
+        assert_eq!(file.format.mChannelsPerFrame, 2);
+        assert_eq!(file.format.mBitsPerChannel, 16);
+        // the odd byte at the end isn't a whole packet
+        assert_eq!(file.packet_count, 2);
+        assert_eq!(file.audio_data.len(), 8);
+        assert_eq!(file.channel_mask, None);
+        assert_eq!(first_sample(&parse(&bytes).unwrap()), 0.5);
+    }
+
+    #[test]
+    fn reads_8_bit_pcm_as_unsigned() {
+        let bytes = wave(&[riff_chunk(b"fmt ", &fmt_body(1, 1, 8000, 1, 8)), riff_chunk(b"data", &[0x40, 0x80])]);
+        let file = parse(&bytes).unwrap();
+        assert_eq!(file.format.mFormatFlags, core_audio::kAudioFormatFlagIsPacked as u32);
+        assert_eq!(file.format.mBitsPerChannel, 8);
+        assert_eq!(file.packet_count, 2);
+        assert_eq!(first_sample(&parse(&bytes).unwrap()), -0.5);
+    }
+
+    #[test]
+    fn reads_24_and_32_bit_pcm() {
+        let bytes = wave(&[riff_chunk(b"fmt ", &fmt_body(1, 1, 96000, 3, 24)), riff_chunk(b"data", &[0, 0, 0xc0])]);
+        let file = parse(&bytes).unwrap();
+        assert_eq!(file.format.mBitsPerChannel, 24);
+        assert_eq!(file.format.mBytesPerFrame, 3);
+        assert_eq!(first_sample(&parse(&bytes).unwrap()), -0.5);
+
+        let bytes = wave(&[riff_chunk(b"fmt ", &fmt_body(1, 1, 96000, 4, 32)), riff_chunk(b"data", &[0, 0, 0, 0x40])]);
+        let file = parse(&bytes).unwrap();
+        assert_eq!(file.format.mBitsPerChannel, 32);
+        assert_eq!(first_sample(&parse(&bytes).unwrap()), 0.5);
+    }
+
+    #[test]
+    fn reads_samples_narrower_than_their_container() {
+        // 24 valid bits in the high bytes of 4, with junk below them
+        let bytes = wave(&[riff_chunk(b"fmt ", &extensible_body(1, 1, 4, 32, 24, 0x4)), riff_chunk(b"data", &[0x7f, 0, 0, 0xc0])]);
+        assert!(pcm::can_decode(&parse(&bytes).unwrap().format));
+        assert_eq!(first_sample(&parse(&bytes).unwrap()), -0.5);
+    }
+
+    #[test]
+    fn reads_ieee_float() {
+        let half = 0.5f32.to_bits();
+        let bytes = wave(&[riff_chunk(b"fmt ", &fmt_body(3, 1, 48000, 4, 32)),
+                           riff_chunk(b"data", &[half as u8, (half >> 8) as u8, (half >> 16) as u8, (half >> 24) as u8])]);
+        let file = parse(&bytes).unwrap();
+        assert_eq!(file.format.mFormatFlags,
+                   (core_audio::kAudioFormatFlagIsFloat | core_audio::kAudioFormatFlagIsPacked) as u32);
+        assert_eq!(first_sample(&parse(&bytes).unwrap()), 0.5);
+
+        let quarter = 0.25f64.to_bits();
+        let data: Vec<u8> = (0..8).map(|i| (quarter >> (i * 8)) as u8).collect();
+        let bytes = wave(&[riff_chunk(b"fmt ", &fmt_body(3, 1, 48000, 8, 64)), riff_chunk(b"data", &data)]);
+        let file = parse(&bytes).unwrap();
+        assert_eq!(file.format.mBitsPerChannel, 64);
+        assert_eq!(first_sample(&parse(&bytes).unwrap()), 0.25);
+    }
+
+    #[test]
+    fn reads_extensible_with_channel_mask() {
+        // 5.1, 24 bit samples in 32 bit containers
+        let bytes = wave(&[riff_chunk(b"fmt ", &extensible_body(1, 6, 24, 32, 24, 0x3f)),
+                           riff_chunk(b"data", &[0; 48])]);
+        let file = parse(&bytes).unwrap();
+        assert_eq!(file.channel_mask, Some(0x3f));
+        assert_eq!(file.format.mChannelsPerFrame, 6);
+        assert_eq!(file.format.mBitsPerChannel, 24);
+        assert_eq!(file.format.mBytesPerFrame, 24);
+        assert_eq!(file.format.mFormatFlags,
+                   (core_audio::kAudioFormatFlagIsSignedInteger | core_audio::kAudioFormatFlagIsAlignedHigh) as u32);
+        assert_eq!(file.packet_count, 2);
+
+        let bytes = wave(&[riff_chunk(b"fmt ", &extensible_body(3, 2, 8, 32, 32, 0x3)), riff_chunk(b"data", &[0; 8])]);
+        let file = parse(&bytes).unwrap();
+        assert_eq!(file.format.mFormatFlags,
+                   (core_audio::kAudioFormatFlagIsFloat | core_audio::kAudioFormatFlagIsPacked) as u32);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        // no data chunk
+        assert_error(parse(&wave(&[riff_chunk(b"fmt ", &fmt_body(1, 1, 44100, 2, 16))])), AudioFileError::InvalidFile);
+        // no fmt chunk
+        assert_error(parse(&wave(&[riff_chunk(b"data", &[0, 0])])), AudioFileError::InvalidFile);
+        // fmt chunk too short for its fields
+        assert_error(parse(&wave(&[riff_chunk(b"fmt ", &[1, 0, 1, 0]), riff_chunk(b"data", &[0, 0])])),
+                     AudioFileError::InvalidChunk);
+        // a chunk claiming more bytes than the file has
+        let mut bytes = wave(&[riff_chunk(b"fmt ", &fmt_body(1, 1, 44100, 2, 16)), riff_chunk(b"data", &[0, 0])]);
+        bytes.extend_from_slice(b"LIST\xff\x00\x00\x00");
+        assert_error(parse(&bytes), AudioFileError::InvalidChunk);
+        // block align that doesn't fit the channels
+        assert_error(parse(&wave(&[riff_chunk(b"fmt ", &fmt_body(1, 2, 44100, 3, 16)), riff_chunk(b"data", &[0; 6])])),
+                     AudioFileError::InvalidFile);
+        // ADPCM
+        assert_error(parse(&wave(&[riff_chunk(b"fmt ", &fmt_body(2, 1, 44100, 2, 4)), riff_chunk(b"data", &[0, 0])])),
+                     AudioFileError::UnsupportedDataFormat);
+        // float that isn't 32 or 64 bit
+        assert_error(parse(&wave(&[riff_chunk(b"fmt ", &fmt_body(3, 1, 44100, 2, 16)), riff_chunk(b"data", &[0, 0])])),
+                     AudioFileError::UnsupportedDataFormat);
+    }
+}
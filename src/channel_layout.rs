@@ -0,0 +1,393 @@
+//! Which speaker each channel of a stream is for, mirroring AudioChannelLayout.
+//!
+//! A layout says it either with a tag naming a standard layout, with a bitmap of the speakers
+//! present (in a fixed order, like a WAVE file's channel mask) or with a label for each channel.
+//! `ChannelLayout::labels` works out the per-channel labels whichever way it was given.
+
+use error::{AudioUnitError, Error};
+use property::PropertyValue;
+
+/// A kAudioChannelLabel_ constant.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChannelLabel(pub u32);
+
+impl ChannelLabel {
+    pub const UNKNOWN: ChannelLabel = ChannelLabel(0xffff_ffff);
+    pub const UNUSED: ChannelLabel = ChannelLabel(0);
+    pub const USE_COORDINATES: ChannelLabel = ChannelLabel(100);
+    pub const LEFT: ChannelLabel = ChannelLabel(1);
+    pub const RIGHT: ChannelLabel = ChannelLabel(2);
+    pub const CENTER: ChannelLabel = ChannelLabel(3);
+    pub const LFE_SCREEN: ChannelLabel = ChannelLabel(4);
+    pub const LEFT_SURROUND: ChannelLabel = ChannelLabel(5);
+    pub const RIGHT_SURROUND: ChannelLabel = ChannelLabel(6);
+    pub const LEFT_CENTER: ChannelLabel = ChannelLabel(7);
+    pub const RIGHT_CENTER: ChannelLabel = ChannelLabel(8);
+    pub const CENTER_SURROUND: ChannelLabel = ChannelLabel(9);
+    pub const LEFT_SURROUND_DIRECT: ChannelLabel = ChannelLabel(10);
+    pub const RIGHT_SURROUND_DIRECT: ChannelLabel = ChannelLabel(11);
+    pub const TOP_CENTER_SURROUND: ChannelLabel = ChannelLabel(12);
+    pub const VERTICAL_HEIGHT_LEFT: ChannelLabel = ChannelLabel(13);
+    pub const VERTICAL_HEIGHT_CENTER: ChannelLabel = ChannelLabel(14);
+    pub const VERTICAL_HEIGHT_RIGHT: ChannelLabel = ChannelLabel(15);
+    pub const TOP_BACK_LEFT: ChannelLabel = ChannelLabel(16);
+    pub const TOP_BACK_CENTER: ChannelLabel = ChannelLabel(17);
+    pub const TOP_BACK_RIGHT: ChannelLabel = ChannelLabel(18);
+    pub const REAR_SURROUND_LEFT: ChannelLabel = ChannelLabel(33);
+    pub const REAR_SURROUND_RIGHT: ChannelLabel = ChannelLabel(34);
+    pub const LEFT_WIDE: ChannelLabel = ChannelLabel(35);
+    pub const RIGHT_WIDE: ChannelLabel = ChannelLabel(36);
+    pub const LFE2: ChannelLabel = ChannelLabel(37);
+    pub const MONO: ChannelLabel = ChannelLabel(42);
+    pub const HEADPHONES_LEFT: ChannelLabel = ChannelLabel(301);
+    pub const HEADPHONES_RIGHT: ChannelLabel = ChannelLabel(302);
+
+    /// kAudioChannelLabel_Discrete_N, a channel that isn't for any speaker in particular.
+    pub fn discrete(index: u16) -> ChannelLabel {
+        ChannelLabel(1 << 16 | index as u32)
+    }
+
+    /// The bit standing for this speaker in a channel bitmap, for the labels that have one.
+    pub fn bit(&self) -> Option<u32> {
+        if self.0 >= 1 && self.0 <= 18 { Some(1 << (self.0 - 1)) } else { None }
+    }
+
+    /// Whether this is a speaker, so that a channel with this label in one layout is the same
+    /// channel as one with it in another.
+    pub fn is_speaker(&self) -> bool {
+        *self != ChannelLabel::UNKNOWN && *self != ChannelLabel::UNUSED && *self != ChannelLabel::USE_COORDINATES
+    }
+}
+
+/// The abbreviations Apple's layout tag comments use, e.g. `Ls` for the left surround.
+impl ::std::fmt::Display for ChannelLabel {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        let name = match *self {
+            ChannelLabel::LEFT => "L",
+            ChannelLabel::RIGHT => "R",
+            ChannelLabel::CENTER => "C",
+            ChannelLabel::LFE_SCREEN => "LFE",
+            ChannelLabel::LEFT_SURROUND => "Ls",
+            ChannelLabel::RIGHT_SURROUND => "Rs",
+            ChannelLabel::LEFT_CENTER => "Lc",
+            ChannelLabel::RIGHT_CENTER => "Rc",
+            ChannelLabel::CENTER_SURROUND => "Cs",
+            ChannelLabel::LEFT_SURROUND_DIRECT => "Lsd",
+            ChannelLabel::RIGHT_SURROUND_DIRECT => "Rsd",
+            ChannelLabel::TOP_CENTER_SURROUND => "Ts",
+            ChannelLabel::VERTICAL_HEIGHT_LEFT => "Vhl",
+            ChannelLabel::VERTICAL_HEIGHT_CENTER => "Vhc",
+            ChannelLabel::VERTICAL_HEIGHT_RIGHT => "Vhr",
+            ChannelLabel::TOP_BACK_LEFT => "Ltr",
+            ChannelLabel::TOP_BACK_CENTER => "Ctr",
+            ChannelLabel::TOP_BACK_RIGHT => "Rtr",
+            ChannelLabel::REAR_SURROUND_LEFT => "Rls",
+            ChannelLabel::REAR_SURROUND_RIGHT => "Rrs",
+            ChannelLabel::LEFT_WIDE => "Lw",
+            ChannelLabel::RIGHT_WIDE => "Rw",
+            ChannelLabel::LFE2 => "LFE2",
+            ChannelLabel::MONO => "M",
+            ChannelLabel::HEADPHONES_LEFT => "HL",
+            ChannelLabel::HEADPHONES_RIGHT => "HR",
+            ChannelLabel::UNKNOWN => "?",
+            ChannelLabel::UNUSED => "-",
+            ChannelLabel(label) if label >> 16 == 1 => return write!(f, "D{}", label & 0xffff),
+            ChannelLabel(label) => return write!(f, "{}", label),
+        };
+        f.write_str(name)
+    }
+}
+
+/// A kAudioChannelLayoutTag_ constant: the layout's number in the top 16 bits and how many
+/// channels it has in the bottom 16.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChannelLayoutTag(pub u32);
+
+impl ChannelLayoutTag {
+    /// the layout is `ChannelLayout::descriptions`
+    pub const USE_CHANNEL_DESCRIPTIONS: ChannelLayoutTag = ChannelLayoutTag(0);
+    /// the layout is `ChannelLayout::bitmap`
+    pub const USE_CHANNEL_BITMAP: ChannelLayoutTag = ChannelLayoutTag(1 << 16);
+    pub const MONO: ChannelLayoutTag = ChannelLayoutTag(100 << 16 | 1);
+    pub const STEREO: ChannelLayoutTag = ChannelLayoutTag(101 << 16 | 2);
+    pub const STEREO_HEADPHONES: ChannelLayoutTag = ChannelLayoutTag(102 << 16 | 2);
+    pub const QUADRAPHONIC: ChannelLayoutTag = ChannelLayoutTag(108 << 16 | 4);
+    pub const MPEG_3_0_A: ChannelLayoutTag = ChannelLayoutTag(113 << 16 | 3);
+    pub const MPEG_3_0_B: ChannelLayoutTag = ChannelLayoutTag(114 << 16 | 3);
+    pub const MPEG_4_0_A: ChannelLayoutTag = ChannelLayoutTag(115 << 16 | 4);
+    pub const MPEG_4_0_B: ChannelLayoutTag = ChannelLayoutTag(116 << 16 | 4);
+    pub const MPEG_5_0_A: ChannelLayoutTag = ChannelLayoutTag(117 << 16 | 5);
+    pub const MPEG_5_0_B: ChannelLayoutTag = ChannelLayoutTag(118 << 16 | 5);
+    pub const MPEG_5_0_C: ChannelLayoutTag = ChannelLayoutTag(119 << 16 | 5);
+    pub const MPEG_5_0_D: ChannelLayoutTag = ChannelLayoutTag(120 << 16 | 5);
+    pub const MPEG_5_1_A: ChannelLayoutTag = ChannelLayoutTag(121 << 16 | 6);
+    pub const MPEG_5_1_B: ChannelLayoutTag = ChannelLayoutTag(122 << 16 | 6);
+    pub const MPEG_5_1_C: ChannelLayoutTag = ChannelLayoutTag(123 << 16 | 6);
+    pub const MPEG_5_1_D: ChannelLayoutTag = ChannelLayoutTag(124 << 16 | 6);
+    pub const MPEG_6_1_A: ChannelLayoutTag = ChannelLayoutTag(125 << 16 | 7);
+    pub const MPEG_7_1_A: ChannelLayoutTag = ChannelLayoutTag(126 << 16 | 8);
+    pub const MPEG_7_1_B: ChannelLayoutTag = ChannelLayoutTag(127 << 16 | 8);
+    pub const MPEG_7_1_C: ChannelLayoutTag = ChannelLayoutTag(128 << 16 | 8);
+    pub const ITU_2_1: ChannelLayoutTag = ChannelLayoutTag(131 << 16 | 3);
+    pub const ITU_2_2: ChannelLayoutTag = ChannelLayoutTag(132 << 16 | 4);
+    /// as many discrete channels as the bottom 16 bits say, in order
+    pub const DISCRETE_IN_ORDER: ChannelLayoutTag = ChannelLayoutTag(147 << 16);
+
+    /// How many channels the layout has. 0 for the tags that leave it to the bitmap or the
+    /// descriptions.
+    pub fn channel_count(&self) -> u32 {
+        self.0 & 0xffff
+    }
+
+    /// The label of each channel, in order, for the layouts this knows.
+    pub fn labels(&self) -> Option<Vec<ChannelLabel>> {
+        const L: ChannelLabel = ChannelLabel::LEFT;
+        const R: ChannelLabel = ChannelLabel::RIGHT;
+        const C: ChannelLabel = ChannelLabel::CENTER;
+        const LFE: ChannelLabel = ChannelLabel::LFE_SCREEN;
+        const LS: ChannelLabel = ChannelLabel::LEFT_SURROUND;
+        const RS: ChannelLabel = ChannelLabel::RIGHT_SURROUND;
+        const LC: ChannelLabel = ChannelLabel::LEFT_CENTER;
+        const RC: ChannelLabel = ChannelLabel::RIGHT_CENTER;
+        const CS: ChannelLabel = ChannelLabel::CENTER_SURROUND;
+        const RLS: ChannelLabel = ChannelLabel::REAR_SURROUND_LEFT;
+        const RRS: ChannelLabel = ChannelLabel::REAR_SURROUND_RIGHT;
+        let labels: &[ChannelLabel] = match *self {
+            ChannelLayoutTag::MONO => &[ChannelLabel::MONO],
+            ChannelLayoutTag::STEREO => &[L, R],
+            ChannelLayoutTag::STEREO_HEADPHONES => &[ChannelLabel::HEADPHONES_LEFT, ChannelLabel::HEADPHONES_RIGHT],
+            ChannelLayoutTag::QUADRAPHONIC => &[L, R, LS, RS],
+            ChannelLayoutTag::MPEG_3_0_A => &[L, R, C],
+            ChannelLayoutTag::MPEG_3_0_B => &[C, L, R],
+            ChannelLayoutTag::MPEG_4_0_A => &[L, R, C, CS],
+            ChannelLayoutTag::MPEG_4_0_B => &[C, L, R, CS],
+            ChannelLayoutTag::MPEG_5_0_A => &[L, R, C, LS, RS],
+            ChannelLayoutTag::MPEG_5_0_B => &[L, R, LS, RS, C],
+            ChannelLayoutTag::MPEG_5_0_C => &[L, C, R, LS, RS],
+            ChannelLayoutTag::MPEG_5_0_D => &[C, L, R, LS, RS],
+            ChannelLayoutTag::MPEG_5_1_A => &[L, R, C, LFE, LS, RS],
+            ChannelLayoutTag::MPEG_5_1_B => &[L, R, LS, RS, C, LFE],
+            ChannelLayoutTag::MPEG_5_1_C => &[L, C, R, LS, RS, LFE],
+            ChannelLayoutTag::MPEG_5_1_D => &[C, L, R, LS, RS, LFE],
+            ChannelLayoutTag::MPEG_6_1_A => &[L, R, C, LFE, LS, RS, CS],
+            ChannelLayoutTag::MPEG_7_1_A => &[L, R, C, LFE, LS, RS, LC, RC],
+            ChannelLayoutTag::MPEG_7_1_B => &[C, LC, RC, L, R, LS, RS, LFE],
+            ChannelLayoutTag::MPEG_7_1_C => &[L, R, C, LFE, LS, RS, RLS, RRS],
+            ChannelLayoutTag::ITU_2_1 => &[L, R, CS],
+            ChannelLayoutTag::ITU_2_2 => &[L, R, LS, RS],
+            ChannelLayoutTag(tag) if tag & 0xffff_0000 == ChannelLayoutTag::DISCRETE_IN_ORDER.0 =>
+                return Some((0..self.channel_count()).map(|index| ChannelLabel::discrete(index as u16)).collect()),
+            _ => return None,
+        };
+        Some(labels.to_vec())
+    }
+}
+
+/// Mirrors AudioChannelDescription.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ChannelDescription {
+    pub label: ChannelLabel,
+    /// kAudioChannelFlags_*, saying how to read `coordinates`
+    pub flags: u32,
+    /// where the speaker is, for kAudioChannelLabel_UseCoordinates
+    pub coordinates: [f32; 3],
+}
+
+impl ChannelDescription {
+
+    pub fn new(label: ChannelLabel) -> ChannelDescription {
+        ChannelDescription { label, flags: 0, coordinates: [0.0; 3] }
+    }
+}
+
+/// Mirrors AudioChannelLayout: `bitmap` only means anything with `USE_CHANNEL_BITMAP` and
+/// `descriptions` with `USE_CHANNEL_DESCRIPTIONS`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelLayout {
+    pub tag: ChannelLayoutTag,
+    /// kAudioChannelBit_*, see `ChannelLabel::bit`
+    pub bitmap: u32,
+    pub descriptions: Vec<ChannelDescription>,
+}
+
+/// The labels WAVE_FORMAT_EXTENSIBLE speaker bits stand for, from SPEAKER_FRONT_LEFT up. The back
+/// pair are the surrounds unless the side pair are there too, when they're the rear surrounds.
+const WAVE_SPEAKERS: [ChannelLabel; 18] = [
+    ChannelLabel::LEFT, ChannelLabel::RIGHT, ChannelLabel::CENTER, ChannelLabel::LFE_SCREEN,
+    ChannelLabel::LEFT_SURROUND, ChannelLabel::RIGHT_SURROUND, ChannelLabel::LEFT_CENTER, ChannelLabel::RIGHT_CENTER,
+    ChannelLabel::CENTER_SURROUND, ChannelLabel::LEFT_SURROUND, ChannelLabel::RIGHT_SURROUND,
+    ChannelLabel::TOP_CENTER_SURROUND, ChannelLabel::VERTICAL_HEIGHT_LEFT, ChannelLabel::VERTICAL_HEIGHT_CENTER,
+    ChannelLabel::VERTICAL_HEIGHT_RIGHT, ChannelLabel::TOP_BACK_LEFT, ChannelLabel::TOP_BACK_CENTER,
+    ChannelLabel::TOP_BACK_RIGHT,
+];
+
+/// SPEAKER_SIDE_LEFT | SPEAKER_SIDE_RIGHT
+const WAVE_SIDES: u32 = 0x600;
+
+impl ChannelLayout {
+
+    /// One of the standard layouts.
+    pub fn from_tag(tag: ChannelLayoutTag) -> ChannelLayout {
+        ChannelLayout { tag, bitmap: 0, descriptions: Vec::new() }
+    }
+
+    /// The speakers whose bits are set, in bit order.
+    pub fn from_bitmap(bitmap: u32) -> ChannelLayout {
+        ChannelLayout { tag: ChannelLayoutTag::USE_CHANNEL_BITMAP, bitmap, descriptions: Vec::new() }
+    }
+
+    /// A label for each channel.
+    pub fn from_labels(labels: &[ChannelLabel]) -> ChannelLayout {
+        ChannelLayout {
+            tag: ChannelLayoutTag::USE_CHANNEL_DESCRIPTIONS,
+            bitmap: 0,
+            descriptions: labels.iter().map(|&label| ChannelDescription::new(label)).collect(),
+        }
+    }
+
+    /// The layout of a WAVE_FORMAT_EXTENSIBLE file with `channels` channels and a dwChannelMask of
+    /// `mask`. The channels are the speakers in the mask in bit order; channels beyond those the
+    /// mask has bits for aren't for any speaker.
+    pub fn from_wave_channel_mask(mask: u32, channels: u32) -> ChannelLayout {
+        let mut labels: Vec<ChannelLabel> = WAVE_SPEAKERS.iter().enumerate()
+            .filter(|&(bit, _)| mask & 1 << bit != 0)
+            .map(|(bit, &label)| match bit {
+                4 if mask & WAVE_SIDES != 0 => ChannelLabel::REAR_SURROUND_LEFT,
+                5 if mask & WAVE_SIDES != 0 => ChannelLabel::REAR_SURROUND_RIGHT,
+                _ => label,
+            })
+            .collect();
+        labels.resize(channels as usize, ChannelLabel::UNKNOWN);
+        ChannelLayout::from_labels(&labels)
+    }
+
+    /// The layout audio units take `channels` channels to be in when they aren't told otherwise,
+    /// i.e. kAudioChannelLayoutTag_AudioUnit_N, or the channels in order where there isn't one.
+    pub fn for_channels(channels: u32) -> ChannelLayout {
+        ChannelLayout::from_tag(match channels {
+            1 => ChannelLayoutTag::MONO,
+            2 => ChannelLayoutTag::STEREO,
+            4 => ChannelLayoutTag::QUADRAPHONIC,
+            5 => ChannelLayoutTag::MPEG_5_0_A,
+            6 => ChannelLayoutTag::MPEG_5_1_A,
+            7 => ChannelLayoutTag::MPEG_6_1_A,
+            8 => ChannelLayoutTag::MPEG_7_1_C,
+            _ => ChannelLayoutTag(ChannelLayoutTag::DISCRETE_IN_ORDER.0 | channels & 0xffff),
+        })
+    }
+
+    /// Each channel's label. Channels of a tag this doesn't know are all `UNKNOWN`.
+    pub fn labels(&self) -> Vec<ChannelLabel> {
+        match self.tag {
+            ChannelLayoutTag::USE_CHANNEL_DESCRIPTIONS =>
+                self.descriptions.iter().map(|description| description.label).collect(),
+            ChannelLayoutTag::USE_CHANNEL_BITMAP =>
+                (1..19).map(ChannelLabel).filter(|label| label.bit().is_some_and(|bit| self.bitmap & bit != 0))
+                       .collect(),
+            tag => tag.labels().unwrap_or_else(|| vec![ChannelLabel::UNKNOWN; tag.channel_count() as usize]),
+        }
+    }
+
+    pub fn channel_count(&self) -> u32 {
+        self.labels().len() as u32
+    }
+
+    /// For each of `to`'s channels, which of this layout's channels is for the same speaker, if
+    /// any is.
+    pub fn channel_map(&self, to: &ChannelLayout) -> Vec<Option<usize>> {
+        let labels = self.labels();
+        to.labels().iter().map(|to| {
+            if to.is_speaker() { labels.iter().position(|label| label == to) } else { None }
+        }).collect()
+    }
+}
+
+/// Laid out as an AudioChannelLayout: the tag, the bitmap and the number of descriptions, then
+/// the descriptions, all native endian.
+impl PropertyValue for ChannelLayout {
+
+    fn from_bytes(bytes: &[u8]) -> Result<ChannelLayout, Error> {
+        let invalid = Error::AudioUnit(AudioUnitError::InvalidPropertyValue);
+        let words: Vec<u32> = try!(Vec::<u32>::from_bytes(bytes).map_err(|_| invalid.clone()));
+        if words.len() < 3 || words.len() != 3 + words[2] as usize * 5 {
+            return Err(invalid);
+        }
+        let descriptions = words[3..].chunks(5).map(|description| ChannelDescription {
+            label: ChannelLabel(description[0]),
+            flags: description[1],
+            coordinates: [f32::from_bits(description[2]), f32::from_bits(description[3]),
+                          f32::from_bits(description[4])],
+        }).collect();
+        Ok(ChannelLayout { tag: ChannelLayoutTag(words[0]), bitmap: words[1], descriptions })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut words = vec![self.tag.0, self.bitmap, self.descriptions.len() as u32];
+        for description in &self.descriptions {
+            words.push(description.label.0);
+            words.push(description.flags);
+            words.extend(description.coordinates.iter().map(|coordinate| coordinate.to_bits()));
+        }
+        words.to_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{ChannelLabel, ChannelLayout, ChannelLayoutTag};
+    use property::PropertyValue;
+
+    fn names(layout: &ChannelLayout) -> String {
+        layout.labels().iter().map(|label| label.to_string()).collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn labels_channels_however_the_layout_is_given() {
+        assert_eq!(names(&ChannelLayout::from_tag(ChannelLayoutTag::MPEG_5_1_C)), "L C R Ls Rs LFE");
+        assert_eq!(names(&ChannelLayout::from_tag(ChannelLayoutTag::MPEG_7_1_C)), "L R C LFE Ls Rs Rls Rrs");
+        assert_eq!(names(&ChannelLayout::from_tag(ChannelLayoutTag(ChannelLayoutTag::DISCRETE_IN_ORDER.0 | 3))),
+                   "D0 D1 D2");
+        assert_eq!(names(&ChannelLayout::from_tag(ChannelLayoutTag(999 << 16 | 2))), "? ?");
+        assert_eq!(names(&ChannelLayout::from_bitmap(0x3f)), "L R C LFE Ls Rs");
+        assert_eq!(ChannelLabel::LEFT_SURROUND.bit(), Some(0x10));
+        assert_eq!(names(&ChannelLayout::for_channels(6)), "L R C LFE Ls Rs");
+        assert_eq!(ChannelLayout::for_channels(3).channel_count(), 3);
+    }
+
+    #[test]
+    fn reads_wave_channel_masks() {
+        // 5.1 with either the back or the side pair
+        assert_eq!(names(&ChannelLayout::from_wave_channel_mask(0x3f, 6)), "L R C LFE Ls Rs");
+        assert_eq!(names(&ChannelLayout::from_wave_channel_mask(0x60f, 6)), "L R C LFE Ls Rs");
+        // 7.1 has both, the back pair being the rear surrounds
+        assert_eq!(names(&ChannelLayout::from_wave_channel_mask(0x63f, 8)), "L R C LFE Rls Rrs Ls Rs");
+        // more channels than speakers, and fewer
+        assert_eq!(names(&ChannelLayout::from_wave_channel_mask(0x3, 3)), "L R ?");
+        assert_eq!(names(&ChannelLayout::from_wave_channel_mask(0x7, 2)), "L R");
+    }
+
+    #[test]
+    fn maps_channels_to_the_same_speakers() {
+        let film = ChannelLayout::from_tag(ChannelLayoutTag::MPEG_5_1_C);
+        let smpte = ChannelLayout::from_tag(ChannelLayoutTag::MPEG_5_1_A);
+        assert_eq!(film.channel_map(&smpte), vec![Some(0), Some(2), Some(1), Some(5), Some(3), Some(4)]);
+        let quad = ChannelLayout::from_tag(ChannelLayoutTag::QUADRAPHONIC);
+        assert_eq!(quad.channel_map(&smpte), vec![Some(0), Some(1), None, None, Some(2), Some(3)]);
+        let unknown = ChannelLayout::from_labels(&[ChannelLabel::UNKNOWN, ChannelLabel::UNKNOWN]);
+        assert_eq!(unknown.channel_map(&unknown), vec![None, None]);
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let mut layout = ChannelLayout::from_labels(&[ChannelLabel::CENTER, ChannelLabel::USE_COORDINATES]);
+        layout.descriptions[1].coordinates = [0.0, 1.0, -1.0];
+        let bytes = layout.to_bytes();
+        assert_eq!(bytes.len(), 12 + 2 * 20);
+        assert_eq!(ChannelLayout::from_bytes(&bytes).unwrap(), layout);
+        let tagged = ChannelLayout::from_tag(ChannelLayoutTag::STEREO);
+        assert_eq!(ChannelLayout::from_bytes(&tagged.to_bytes()).unwrap(), tagged);
+        assert!(ChannelLayout::from_bytes(&bytes[..bytes.len() - 4]).is_err());
+        assert!(ChannelLayout::from_bytes(&[0; 8]).is_err());
+    }
+}
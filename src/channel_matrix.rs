@@ -0,0 +1,286 @@
+//! Mixing one set of channels into another: downmixing surround to stereo or mono, upmixing mono,
+//! or any matrix of gains given by hand.
+//!
+//! `ChannelMatrix::between` works the gains out from the two channel layouts. Channels for the
+//! same speaker go straight across, and a speaker the destination doesn't have is folded into the
+//! ones nearest it with the ITU-R BS.775 coefficients, e.g. 5.1 to stereo is
+//!
+//! ```text
+//! L' = L + 0.7071 C + 0.7071 Ls
+//! R' = R + 0.7071 C + 0.7071 Rs
+//! ```
+//!
+//! with the LFE left out, as BS.775 leaves it out, unless it's asked for with `Lfe::Mix`.
+
+use channel_layout::{ChannelLabel, ChannelLayout};
+use error::{AudioError, Error};
+use std::f32::consts::FRAC_1_SQRT_2;
+
+/// What to do with an LFE channel when the destination has no LFE of its own.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Lfe {
+    /// leave it out
+    Drop,
+    /// mix it in with the center at this gain
+    Mix(f32),
+}
+
+/// How much of each source channel goes into each destination channel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelMatrix {
+    inputs: usize,
+    /// `gains[output][input]`
+    gains: Vec<Vec<f32>>,
+}
+
+/// Folding stops after this many steps, for labels that fold into each other with neither there.
+const MAX_FOLDS: u32 = 4;
+
+/// Where the sound for `label` goes when the destination has no channel for it: the first of
+/// these whose speakers the destination all has, or failing that the last, folded again.
+fn folds(label: ChannelLabel, lfe: Lfe) -> Vec<Vec<(ChannelLabel, f32)>> {
+    const L: ChannelLabel = ChannelLabel::LEFT;
+    const R: ChannelLabel = ChannelLabel::RIGHT;
+    const C: ChannelLabel = ChannelLabel::CENTER;
+    const M: ChannelLabel = ChannelLabel::MONO;
+    const LS: ChannelLabel = ChannelLabel::LEFT_SURROUND;
+    const RS: ChannelLabel = ChannelLabel::RIGHT_SURROUND;
+    const CS: ChannelLabel = ChannelLabel::CENTER_SURROUND;
+    const HALF: f32 = FRAC_1_SQRT_2;
+    match label {
+        // mono goes to both sides at full level, the way the file player copies it
+        ChannelLabel::MONO => vec![vec![(C, 1.0)], vec![(L, 1.0), (R, 1.0)]],
+        ChannelLabel::CENTER => vec![vec![(M, 1.0)], vec![(L, HALF), (R, HALF)]],
+        ChannelLabel::LEFT => vec![vec![(M, HALF)], vec![(C, HALF)]],
+        ChannelLabel::RIGHT => vec![vec![(M, HALF)], vec![(C, HALF)]],
+        ChannelLabel::LEFT_SURROUND => vec![vec![(CS, HALF)], vec![(L, HALF)]],
+        ChannelLabel::RIGHT_SURROUND => vec![vec![(CS, HALF)], vec![(R, HALF)]],
+        ChannelLabel::CENTER_SURROUND => vec![vec![(LS, HALF), (RS, HALF)], vec![(L, HALF), (R, HALF)]],
+        ChannelLabel::REAR_SURROUND_LEFT | ChannelLabel::TOP_BACK_LEFT => vec![vec![(LS, HALF)]],
+        ChannelLabel::REAR_SURROUND_RIGHT | ChannelLabel::TOP_BACK_RIGHT => vec![vec![(RS, HALF)]],
+        ChannelLabel::TOP_BACK_CENTER => vec![vec![(CS, HALF)]],
+        ChannelLabel::LEFT_SURROUND_DIRECT => vec![vec![(LS, 1.0)]],
+        ChannelLabel::RIGHT_SURROUND_DIRECT => vec![vec![(RS, 1.0)]],
+        ChannelLabel::LEFT_CENTER => vec![vec![(L, HALF), (C, HALF)], vec![(L, 1.0)]],
+        ChannelLabel::RIGHT_CENTER => vec![vec![(R, HALF), (C, HALF)], vec![(R, 1.0)]],
+        ChannelLabel::LEFT_WIDE | ChannelLabel::HEADPHONES_LEFT => vec![vec![(L, 1.0)]],
+        ChannelLabel::RIGHT_WIDE | ChannelLabel::HEADPHONES_RIGHT => vec![vec![(R, 1.0)]],
+        ChannelLabel::VERTICAL_HEIGHT_LEFT => vec![vec![(L, HALF)]],
+        ChannelLabel::VERTICAL_HEIGHT_RIGHT => vec![vec![(R, HALF)]],
+        ChannelLabel::VERTICAL_HEIGHT_CENTER | ChannelLabel::TOP_CENTER_SURROUND => vec![vec![(C, HALF)]],
+        ChannelLabel::LFE_SCREEN | ChannelLabel::LFE2 => match lfe {
+            Lfe::Drop => Vec::new(),
+            Lfe::Mix(gain) => vec![vec![(C, gain)]],
+        },
+        _ => Vec::new(),
+    }
+}
+
+/// Adds where `gain` of `label` ends up among `to`'s channels to `row`.
+fn spread(label: ChannelLabel, gain: f32, to: &[ChannelLabel], lfe: Lfe, folded: u32, row: &mut [f32]) {
+    if !label.is_speaker() || gain == 0.0 || folded > MAX_FOLDS {
+        return;
+    }
+    if let Some(channel) = to.iter().position(|&speaker| speaker == label) {
+        row[channel] += gain;
+        return;
+    }
+    let folds = folds(label, lfe);
+    let fold = folds.iter().find(|fold| fold.iter().all(|&(speaker, _)| to.contains(&speaker))).or(folds.last());
+    if let Some(fold) = fold {
+        for &(speaker, fold_gain) in fold {
+            spread(speaker, gain * fold_gain, to, lfe, folded + 1, row);
+        }
+    }
+}
+
+impl ChannelMatrix {
+
+    /// Channel `n` to channel `n`.
+    pub fn identity(channels: usize) -> ChannelMatrix {
+        ChannelMatrix::straight_across(channels, channels)
+    }
+
+    /// A matrix given by hand, a row of gains for each destination channel with a gain for each
+    /// source channel in it. The rows all have to be the same length.
+    pub fn from_gains(gains: Vec<Vec<f32>>) -> Result<ChannelMatrix, Error> {
+        let inputs = gains.first().map_or(0, |row| row.len());
+        if inputs == 0 || gains.iter().any(|row| row.len() != inputs) {
+            return Err(Error::Audio(AudioError::Param));
+        }
+        Ok(ChannelMatrix { inputs, gains })
+    }
+
+    /// The standard mix from `from` to `to`, see the module docs. Where the layouts have no
+    /// speakers in common, e.g. because they're both discrete channels, the channels go straight
+    /// across.
+    pub fn between(from: &ChannelLayout, to: &ChannelLayout, lfe: Lfe) -> ChannelMatrix {
+        let from = from.labels();
+        let to = to.labels();
+        let mut gains = vec![vec![0.0; from.len()]; to.len()];
+        let mut row = vec![0.0; to.len()];
+        for (input, &label) in from.iter().enumerate() {
+            for gain in row.iter_mut() {
+                *gain = 0.0;
+            }
+            spread(label, 1.0, &to, lfe, 0, &mut row);
+            for (output, &gain) in row.iter().enumerate() {
+                gains[output][input] = gain;
+            }
+        }
+        if gains.iter().all(|row| row.iter().all(|&gain| gain == 0.0)) {
+            return ChannelMatrix::straight_across(from.len(), to.len());
+        }
+        ChannelMatrix { inputs: from.len(), gains }
+    }
+
+    fn straight_across(inputs: usize, outputs: usize) -> ChannelMatrix {
+        let gains = (0..outputs).map(|output| {
+            (0..inputs).map(|input| if input == output { 1.0 } else { 0.0 }).collect()
+        }).collect();
+        ChannelMatrix { inputs, gains }
+    }
+
+    /// How many source channels it mixes.
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    /// How many destination channels it mixes into.
+    pub fn outputs(&self) -> usize {
+        self.gains.len()
+    }
+
+    /// How much of source channel `input` goes into destination channel `output`.
+    pub fn gain(&self, output: usize, input: usize) -> f32 {
+        self.gains[output][input]
+    }
+
+    /// `gains()[output][input]`
+    pub fn gains(&self) -> &[Vec<f32>] {
+        &self.gains
+    }
+
+    /// The same mix turned down, if need be, so that no destination channel can go past full
+    /// scale when every source channel is at full scale.
+    pub fn normalized(&self) -> ChannelMatrix {
+        let loudest = self.gains.iter().map(|row| row.iter().map(|gain| gain.abs()).sum::<f32>())
+                                       .fold(0.0, f32::max);
+        let scale = if loudest > 1.0 { 1.0 / loudest } else { 1.0 };
+        ChannelMatrix {
+            inputs: self.inputs,
+            gains: self.gains.iter().map(|row| row.iter().map(|gain| gain * scale).collect()).collect(),
+        }
+    }
+
+    /// Mixes `input`, one slice per source channel, into `output`, one per destination channel,
+    /// replacing what was there. Channels missing from `input` count as silent.
+    pub fn mix(&self, input: &[Vec<f32>], output: &mut [Vec<f32>]) {
+        for (out, gains) in output.iter_mut().zip(self.gains.iter()) {
+            for sample in out.iter_mut() {
+                *sample = 0.0;
+            }
+            for (samples, &gain) in input.iter().zip(gains.iter()) {
+                if gain == 0.0 {
+                    continue;
+                }
+                for (out, &sample) in out.iter_mut().zip(samples.iter()) {
+                    *out += sample * gain;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{ChannelMatrix, Lfe};
+    use channel_layout::{ChannelLabel, ChannelLayout, ChannelLayoutTag};
+    use error::{AudioError, Error};
+    use std::f32::consts::FRAC_1_SQRT_2 as HALF;
+
+    fn layout(tag: ChannelLayoutTag) -> ChannelLayout {
+        ChannelLayout::from_tag(tag)
+    }
+
+    fn assert_gains(matrix: &ChannelMatrix, expected: &[&[f32]]) {
+        assert_eq!(matrix.outputs(), expected.len(), "{:?}", matrix);
+        for (row, expected) in matrix.gains().iter().zip(expected.iter()) {
+            assert_eq!(row.len(), expected.len(), "{:?}", matrix);
+            for (gain, expected) in row.iter().zip(expected.iter()) {
+                assert!((gain - expected).abs() < 1e-6, "{:?} != {:?}", matrix.gains(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn downmixes_surround_the_bs_775_way() {
+        // L R C LFE Ls Rs
+        let surround = layout(ChannelLayoutTag::MPEG_5_1_A);
+        assert_gains(&ChannelMatrix::between(&surround, &layout(ChannelLayoutTag::STEREO), Lfe::Drop),
+                     &[&[1.0, 0.0, HALF, 0.0, HALF, 0.0],
+                       &[0.0, 1.0, HALF, 0.0, 0.0, HALF]]);
+        assert_gains(&ChannelMatrix::between(&surround, &layout(ChannelLayoutTag::MONO), Lfe::Drop),
+                     &[&[HALF, HALF, 1.0, 0.0, 0.5, 0.5]]);
+        // 3/1: the surrounds go to the one at the back
+        assert_gains(&ChannelMatrix::between(&surround, &layout(ChannelLayoutTag::MPEG_4_0_A), Lfe::Drop),
+                     &[&[1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+                       &[0.0, 1.0, 0.0, 0.0, 0.0, 0.0],
+                       &[0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+                       &[0.0, 0.0, 0.0, 0.0, HALF, HALF]]);
+        // 7.1's rear pair go into the surrounds
+        let seven_one = ChannelMatrix::between(&layout(ChannelLayoutTag::MPEG_7_1_C), &surround, Lfe::Drop);
+        assert_eq!(seven_one.gain(4, 4), 1.0);
+        assert!((seven_one.gain(4, 6) - HALF).abs() < 1e-6);
+        assert!((seven_one.gain(5, 7) - HALF).abs() < 1e-6);
+    }
+
+    #[test]
+    fn drops_or_mixes_in_the_lfe() {
+        let surround = layout(ChannelLayoutTag::MPEG_5_1_A);
+        let stereo = layout(ChannelLayoutTag::STEREO);
+        assert_eq!(ChannelMatrix::between(&surround, &stereo, Lfe::Drop).gain(0, 3), 0.0);
+        let mixed = ChannelMatrix::between(&surround, &stereo, Lfe::Mix(0.5));
+        assert!((mixed.gain(0, 3) - 0.5 * HALF).abs() < 1e-6);
+        assert!((mixed.gain(1, 3) - 0.5 * HALF).abs() < 1e-6);
+        // a destination with an LFE gets it as it is
+        assert_eq!(ChannelMatrix::between(&surround, &surround, Lfe::Drop), ChannelMatrix::identity(6));
+    }
+
+    #[test]
+    fn upmixes_and_reorders() {
+        assert_gains(&ChannelMatrix::between(&layout(ChannelLayoutTag::MONO), &layout(ChannelLayoutTag::STEREO),
+                                             Lfe::Drop),
+                     &[&[1.0], &[1.0]]);
+        // stereo into 5.1 stays at the front
+        assert_gains(&ChannelMatrix::between(&layout(ChannelLayoutTag::STEREO), &layout(ChannelLayoutTag::MPEG_5_1_A),
+                                             Lfe::Drop),
+                     &[&[1.0, 0.0], &[0.0, 1.0], &[0.0, 0.0], &[0.0, 0.0], &[0.0, 0.0], &[0.0, 0.0]]);
+        let film = ChannelMatrix::between(&layout(ChannelLayoutTag::MPEG_5_1_C), &layout(ChannelLayoutTag::MPEG_5_1_A),
+                                          Lfe::Drop);
+        assert_eq!(film.gain(1, 2), 1.0);
+        assert_eq!(film.gain(3, 5), 1.0);
+        // nothing in common, so straight across
+        let discrete = ChannelLayout::from_labels(&[ChannelLabel::discrete(0), ChannelLabel::discrete(1),
+                                                    ChannelLabel::discrete(2)]);
+        assert_gains(&ChannelMatrix::between(&discrete, &layout(ChannelLayoutTag::STEREO), Lfe::Drop),
+                     &[&[1.0, 0.0, 0.0], &[0.0, 1.0, 0.0]]);
+    }
+
+    #[test]
+    fn mixes_with_a_matrix_given_by_hand() {
+        let matrix = ChannelMatrix::from_gains(vec![vec![0.5, 0.5, 1.0], vec![0.0, 2.0, -1.0]]).unwrap();
+        let input = vec![vec![1.0, 0.0], vec![0.5, 1.0], vec![0.25, -1.0]];
+        let mut output = vec![vec![9.0; 2]; 2];
+        matrix.mix(&input, &mut output);
+        assert_eq!(output, vec![vec![1.0, -0.5], vec![0.75, 3.0]]);
+
+        assert_gains(&matrix.normalized(), &[&[0.5 / 3.0, 0.5 / 3.0, 1.0 / 3.0], &[0.0, 2.0 / 3.0, -1.0 / 3.0]]);
+        assert_eq!(ChannelMatrix::identity(2).normalized(), ChannelMatrix::identity(2));
+
+        let param = Error::Audio(AudioError::Param);
+        assert_eq!(ChannelMatrix::from_gains(vec![vec![1.0], vec![1.0, 0.0]]).unwrap_err(), param);
+        assert_eq!(ChannelMatrix::from_gains(Vec::new()).unwrap_err(), param);
+    }
+}
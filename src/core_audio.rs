@@ -0,0 +1,122 @@
+//! The subset of the CoreAudio types and constants this crate uses, for platforms without
+//! coreaudio-sys. Names, layouts and values match the CoreAudio headers so the rest of the crate
+//! can use `core_audio::*` the same way everywhere.
+
+#![allow(non_camel_case_types, non_upper_case_globals, non_snake_case)]
+
+pub type OSStatus = i32;
+pub type SInt16 = i16;
+pub type UInt32 = u32;
+pub type SInt32 = i32;
+pub type UInt64 = u64;
+pub type Float64 = f64;
+pub type Boolean = u8;
+
+pub type AUNode = SInt32;
+pub type AudioUnitPropertyID = UInt32;
+pub type AudioUnitScope = UInt32;
+pub type AudioUnitElement = UInt32;
+pub type AudioUnitParameterID = UInt32;
+pub type AudioUnitParameterValue = f32;
+pub type AudioFormatID = UInt32;
+pub type AudioFormatFlags = UInt32;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AudioStreamBasicDescription {
+    pub mSampleRate: Float64,
+    pub mFormatID: AudioFormatID,
+    pub mFormatFlags: AudioFormatFlags,
+    pub mBytesPerPacket: UInt32,
+    pub mFramesPerPacket: UInt32,
+    pub mBytesPerFrame: UInt32,
+    pub mChannelsPerFrame: UInt32,
+    pub mBitsPerChannel: UInt32,
+    pub mReserved: UInt32,
+}
+
+pub type Struct_AudioStreamBasicDescription = AudioStreamBasicDescription;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SMPTETime {
+    pub mSubframes: SInt16,
+    pub mSubframeDivisor: SInt16,
+    pub mCounter: UInt32,
+    pub mType: UInt32,
+    pub mFlags: UInt32,
+    pub mHours: SInt16,
+    pub mMinutes: SInt16,
+    pub mSeconds: SInt16,
+    pub mFrames: SInt16,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AudioTimeStamp {
+    pub mSampleTime: Float64,
+    pub mHostTime: UInt64,
+    pub mRateScalar: Float64,
+    pub mWordClockTime: UInt64,
+    pub mSMPTETime: SMPTETime,
+    pub mFlags: UInt32,
+    pub mReserved: UInt32,
+}
+
+pub const kAudioTimeStampSampleTimeValid: UInt32 = 1 << 0;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AudioComponentDescription {
+    pub componentType: UInt32,
+    pub componentSubType: UInt32,
+    pub componentManufacturer: UInt32,
+    pub componentFlags: UInt32,
+    pub componentFlagsMask: UInt32,
+}
+
+pub type AudioFilePropertyID = UInt32;
+
+pub const kAudioFilePropertyChannelLayout: AudioFilePropertyID = 0x636d6170; // 'cmap'
+
+pub const kAudioFormatLinearPCM: AudioFormatID = 0x6c70636d; // 'lpcm'
+
+pub const kAudioFormatFlagIsFloat: i32 = 1 << 0;
+pub const kAudioFormatFlagIsBigEndian: i32 = 1 << 1;
+pub const kAudioFormatFlagIsSignedInteger: i32 = 1 << 2;
+pub const kAudioFormatFlagIsPacked: i32 = 1 << 3;
+pub const kAudioFormatFlagIsAlignedHigh: i32 = 1 << 4;
+pub const kAudioFormatFlagIsNonInterleaved: i32 = 1 << 5;
+pub const kAudioFormatFlagIsNonMixable: i32 = 1 << 6;
+pub const kAudioFormatFlagsAreAllClear: i32 = 0x80000000u32 as i32;
+
+pub const kAudioUnitType_Output: UInt32 = 0x61756f75; // 'auou'
+pub const kAudioUnitType_Generator: UInt32 = 0x6175676e; // 'augn'
+pub const kAudioUnitType_Mixer: UInt32 = 0x61756d78; // 'aumx'
+pub const kAudioUnitType_FormatConverter: UInt32 = 0x61756663; // 'aufc'
+pub const kAudioUnitSubType_DefaultOutput: UInt32 = 0x64656620; // 'def '
+pub const kAudioUnitSubType_AudioFilePlayer: UInt32 = 0x6166706c; // 'afpl'
+pub const kAudioUnitSubType_MultiChannelMixer: UInt32 = 0x6d636d78; // 'mcmx'
+pub const kAudioUnitSubType_AUConverter: UInt32 = 0x636f6e76; // 'conv'
+pub const kAudioUnitManufacturer_Apple: UInt32 = 0x6170706c; // 'appl'
+
+pub const kAudioUnitScope_Global: AudioUnitScope = 0;
+pub const kAudioUnitScope_Input: AudioUnitScope = 1;
+pub const kAudioUnitScope_Output: AudioUnitScope = 2;
+
+pub const kAudioUnitProperty_StreamFormat: AudioUnitPropertyID = 8;
+pub const kAudioUnitProperty_ElementCount: AudioUnitPropertyID = 11;
+pub const kAudioUnitProperty_Latency: AudioUnitPropertyID = 12;
+pub const kAudioUnitProperty_MaximumFramesPerSlice: AudioUnitPropertyID = 14;
+pub const kAudioUnitProperty_AudioChannelLayout: AudioUnitPropertyID = 19;
+pub const kAudioUnitProperty_RenderQuality: AudioUnitPropertyID = 26;
+pub const kAudioUnitProperty_ScheduledFileIDs: AudioUnitPropertyID = 3310;
+pub const kAudioUnitProperty_ScheduledFileRegion: AudioUnitPropertyID = 3311;
+pub const kAudioUnitProperty_ScheduleStartTimeStamp: AudioUnitPropertyID = 3312;
+pub const kAudioUnitProperty_ScheduledFilePrime: AudioUnitPropertyID = 3313;
+pub const kAudioUnitProperty_CurrentPlayTime: AudioUnitPropertyID = 3314;
+
+pub const kHALOutputParam_Volume: AudioUnitParameterID = 14;
+pub const kMultiChannelMixerParam_Volume: AudioUnitParameterID = 0;
+pub const kMultiChannelMixerParam_Enable: AudioUnitParameterID = 1;
+pub const kMultiChannelMixerParam_Pan: AudioUnitParameterID = 2;
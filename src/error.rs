@@ -1,491 +1,586 @@
 //! This module is an attempt at rustifying the OSStatus result.
-
+//!
+//! Each Core Audio API has its own table of statuses, and a few statuses, mostly four character
+//! codes like `'fmt?'` and `'!siz'`, turn up in more than one of them with the same meaning, so a
+//! status alone doesn't always say which error it was. Given the API it came from,
+//! `Error::from_domain_os_status` looks in that API's table first, and that's what undoes
+//! `to_os_status`: every error from a table comes back as itself given its `domain()`. The errors
+//! with no domain, the crate's own among them, have statuses no table uses, and come back as
+//! themselves from `Error::from_os_status`.
+//!
+//! Errors from the backends carry a `Context` saying which call failed and what it was called
+//! with, which `Display` prints and `source` unwraps.
+
+use core_audio;
 use core_audio::OSStatus;
+use four_cc::FourCC;
+use std::error::Error as StdError;
+use std::path::{Path, PathBuf};
 pub use self::audio::Error as AudioError;
 pub use self::audio_codec::Error as AudioCodecError;
+pub use self::audio_converter::Error as AudioConverterError;
+pub use self::audio_file::Error as AudioFileError;
+pub use self::audio_file_stream::Error as AudioFileStreamError;
 pub use self::audio_format::Error as AudioFormatError;
+pub use self::audio_queue::Error as AudioQueueError;
 pub use self::audio_unit::Error as AudioUnitError;
-pub use self::audio_file::Error as AudioFileError;
+pub use self::ext_audio_file::Error as ExtAudioFileError;
 pub use self::graph::Error as GraphError;
+pub use self::hardware::Error as HardwareError;
 
-pub mod audio {
-    use core_audio::OSStatus;
-
-    #[derive(Copy, Clone, Debug)]
-    pub enum Error {
-        Unimplemented    = -4,
-        FileNotFound     = -43,
-        FilePermission   = -54,
-        TooManyFilesOpen = -42,
-        BadFilePath      = 561017960,
-        Param            = -50,
-        MemFull          = -108,
-        Unknown,
-    }
-
-    impl Error {
-
-        pub fn from_os_status(os_status: OSStatus) -> Result<(), Error> {
-            match os_status {
-                0         => Ok(()),
-                -4        => Err(Error::Unimplemented),
-                -43       => Err(Error::FileNotFound),
-                -54       => Err(Error::FilePermission),
-                -42       => Err(Error::TooManyFilesOpen),
-                561017960 => Err(Error::BadFilePath),
-                -50       => Err(Error::Param),
-                -108      => Err(Error::MemFull),
-                _         => Err(Error::Unknown),
-            }
-        }
+/// The status spelt by a four character code, e.g. `code(b"fmt?")`.
+fn code(bytes: &[u8; 4]) -> OSStatus {
+    FourCC::new(*bytes).value() as OSStatus
+}
 
-        pub fn to_os_status(&self) -> OSStatus {
-            *self as OSStatus
+/// What an `Unknown` error in one of the tables converts to, as it doesn't remember the status it
+/// was made from. The same as `Error::Unspecified`, which it converts back to without a domain.
+const UNSPECIFIED: OSStatus = -1500;
+
+/// Defines the `Error` enum of an API's table of statuses: a variant for each status, and
+/// `Unknown` for any status that isn't in the table.
+macro_rules! os_status_table {
+    ($($variant:ident = $os_status:expr => $description:expr,)*) => {
+        use core_audio::OSStatus;
+        #[allow(unused_imports)]
+        use super::{code, UNSPECIFIED};
+
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        pub enum Error {
+            $($variant,)*
+            Unknown,
         }
 
-    }
+        impl Error {
 
-    impl ::std::fmt::Display for Error {
-        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
-            write!(f, "{:?}", self)
-        }
-    }
+            /// Every error in the table, i.e. all of them but `Unknown`.
+            pub const ALL: &'static [Error] = &[$(Error::$variant,)*];
 
-    impl ::std::error::Error for Error {
-        fn description(&self) -> &str {
-            match *self {
-                Error::Unimplemented    => "Unimplemented",
-                Error::FileNotFound     => "File not found",
-                Error::FilePermission   => "File permission",
-                Error::TooManyFilesOpen => "Too many files open",
-                Error::BadFilePath      => "Bad file path",
-                Error::Param            => "Param",
-                Error::MemFull          => "Memory full",
-                Error::Unknown          => "An unknown error occurred",
+            pub fn from_os_status(os_status: OSStatus) -> Result<(), Error> {
+                if os_status == 0 {
+                    return Ok(());
+                }
+                $(
+                    if os_status == $os_status {
+                        return Err(Error::$variant);
+                    }
+                )*
+                Err(Error::Unknown)
             }
-        }
-    }
-
-}
 
-
-pub mod audio_codec {
-    use core_audio::OSStatus;
-
-    #[derive(Copy, Clone, Debug)]
-    pub enum Error {
-        Unspecified          = 2003329396,
-        UnknownProperty      = 2003332927,
-        BadPropertySize      = 561211770,
-        IllegalOperation     = 1852797029,
-        UnsupportedFormat    = 560226676,
-        State                = 561214580,
-        NotEnoughBufferSpace = 560100710,
-        Unknown,
-    }
-
-    impl Error {
-
-        pub fn from_os_status(os_status: OSStatus) -> Result<(), Error> {
-            match os_status {
-                0          => Ok(()),
-                2003329396 => Err(Error::Unspecified),
-                2003332927 => Err(Error::UnknownProperty),
-                561211770  => Err(Error::BadPropertySize),
-                1852797029 => Err(Error::IllegalOperation),
-                560226676  => Err(Error::UnsupportedFormat),
-                561214580  => Err(Error::State),
-                560100710  => Err(Error::NotEnoughBufferSpace),
-                _          => Err(Error::Unknown),
+            pub fn to_os_status(&self) -> OSStatus {
+                match *self {
+                    $(Error::$variant => $os_status,)*
+                    Error::Unknown => UNSPECIFIED,
+                }
             }
-        }
 
-        pub fn to_os_status(&self) -> OSStatus {
-            *self as OSStatus
         }
 
-    }
-
-    impl ::std::fmt::Display for Error {
-        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
-            write!(f, "{:?}", self)
+        impl ::std::fmt::Display for Error {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+                match *self {
+                    Error::Unknown => write!(f, "{:?}", self),
+                    _              => {
+                        try!(write!(f, "{:?} ", self));
+                        super::write_status(f, self.to_os_status())
+                    },
+                }
+            }
         }
-    }
 
-    impl ::std::error::Error for Error {
-        fn description(&self) -> &str {
-            match *self {
-                Error::Unspecified          => "Unspecified",
-                Error::UnknownProperty      => "Unknown property",
-                Error::BadPropertySize      => "Bad property size",
-                Error::IllegalOperation     => "Illegal operation",
-                Error::UnsupportedFormat    => "Unsupported format",
-                Error::State                => "State",
-                Error::NotEnoughBufferSpace => "Not enough buffer space",
-                Error::Unknown              => "Unknown error occurred",
+        impl ::std::error::Error for Error {
+            fn description(&self) -> &str {
+                match *self {
+                    $(Error::$variant => $description,)*
+                    Error::Unknown => "Unknown error occurred",
+                }
             }
         }
     }
+}
 
+/// kAudio_ statuses from CoreAudioTypes.h, which any API can return.
+pub mod audio {
+    os_status_table! {
+        Unimplemented    = -4            => "Unimplemented",
+        FileNotFound     = -43           => "File not found",
+        FilePermission   = -54           => "File permission",
+        TooManyFilesOpen = -42           => "Too many files open",
+        BadFilePath      = code(b"!pth") => "Bad file path",
+        Param            = -50           => "Param",
+        MemFull          = -108          => "Memory full",
+    }
 }
 
+/// kAudioCodec statuses from AudioCodec.h.
+pub mod audio_codec {
+    os_status_table! {
+        Unspecified          = code(b"what") => "Unspecified",
+        UnknownProperty      = code(b"who?") => "Unknown property",
+        BadPropertySize      = code(b"!siz") => "Bad property size",
+        IllegalOperation     = code(b"nope") => "Illegal operation",
+        UnsupportedFormat    = code(b"!dat") => "Unsupported format",
+        State                = code(b"!stt") => "State",
+        NotEnoughBufferSpace = code(b"!buf") => "Not enough buffer space",
+        BadData              = code(b"bada") => "Bad data",
+    }
+}
 
+/// kAudioFormat statuses from AudioFormat.h.
 pub mod audio_format {
-    use core_audio::OSStatus;
-
-    // TODO: Finish implementing these values.
-    #[derive(Copy, Clone, Debug)]
-    pub enum Error {
-        Unspecified, // 'what'
-        UnsupportedProperty, // 'prop'
-        BadPropertySize, // '!siz'
-        BadSpecifierSize, // '!spc'
-        UnsupportedDataFormat = 1718449215, // 'fmt?'
-        UnknownFormat, // '!fmt'
-        Unknown, //
-    }
-
-    impl Error {
-
-        pub fn from_os_status(os_status: OSStatus) -> Result<(), Error> {
-            match os_status {
-                0          => Ok(()),
-                1718449215 => Err(Error::UnsupportedDataFormat),
-                _ => Err(Error::Unknown),
-            }
-        }
-
-        pub fn to_os_status(&self) -> OSStatus {
-            *self as OSStatus
-        }
+    os_status_table! {
+        Unspecified           = code(b"what") => "An unspecified error",
+        UnsupportedProperty   = code(b"prop") => "The specified property is not supported",
+        BadPropertySize       = code(b"!siz") => "Bad property size",
+        BadSpecifierSize      = code(b"!spc") => "Bad specifier size",
+        UnsupportedDataFormat = code(b"fmt?") => "The specified data format is not supported",
+        UnknownFormat         = code(b"!fmt") => "The specified data format is not a known format",
+    }
+}
 
+/// kAudioUnitErr statuses from AUComponent.h.
+pub mod audio_unit {
+    os_status_table! {
+        InvalidProperty          = -10879 => "Invalid property",
+        InvalidParameter         = -10878 => "Invalid parameter",
+        InvalidElement           = -10877 => "Invalid element",
+        NoConnection             = -10876 => "No connection",
+        FailedInitialization     = -10875 => "Failed initialization",
+        TooManyFramesToProcess   = -10874 => "Too many frames to process",
+        InvalidFile              = -10871 => "Invalid file",
+        UnknownFileType          = -10870 => "Unknown file type",
+        FileNotSpecified         = -10869 => "File not specified",
+        FormatNotSupported       = -10868 => "Format not supported",
+        Uninitialized            = -10867 => "Uninitialized",
+        InvalidScope             = -10866 => "Invalid scope",
+        PropertyNotWritable      = -10865 => "Property not writable",
+        CannotDoInCurrentContext = -10863 => "Cannot do in current context",
+        InvalidPropertyValue     = -10851 => "Invalid property value",
+        PropertyNotInUse         = -10850 => "Property not in use",
+        Initialized              = -10849 => "Initialized",
+        InvalidOfflineRender     = -10848 => "Invalid offline render",
+        Unauthorized             = -10847 => "Unauthorized",
+        MidiOutputBufferFull     = -66753 => "MIDI output buffer full",
+        RenderTimeout            = -66745 => "Render timeout",
+        ExtensionNotFound        = -66744 => "Extension not found",
+        InvalidParameterValue    = -66743 => "Invalid parameter value",
+        InvalidFilePath          = -66742 => "Invalid file path",
+        MissingKey               = -66741 => "Missing key",
     }
+}
 
-    impl ::std::fmt::Display for Error {
-        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
-            write!(f, "{:?}", self)
-        }
+/// kAUGraphErr statuses from AUGraph.h.
+pub mod graph {
+    os_status_table! {
+        NodeNotFound             = -10860 => "NodeNotFound",
+        InvalidConnection        = -10861 => "Invalid Connection",
+        OutputNodeErr            = -10862 => "Output Node Err",
+        CannotDoInCurrentContext = -10863 => "Cannot Do In Current Context",
+        InvalidAudioUnit         = -10864 => "Invalid Audio Unit",
     }
+}
 
-    impl ::std::error::Error for Error {
-        fn description(&self) -> &str {
-            match *self {
-                Error::Unspecified           => "An unspecified error",
-                Error::UnsupportedProperty   => "The specified property is not supported",
-                Error::BadPropertySize       => "Bad property size",
-                Error::BadSpecifierSize      => "Bad specifier size",
-                Error::UnsupportedDataFormat => "The specified data format is not supported",
-                Error::UnknownFormat         => "The specified data format is not a known format",
-                Error::Unknown               => "Unknown error occurred",
-            }
-        }
+/// kAudioFile statuses from AudioFile.h.
+pub mod audio_file {
+    os_status_table! {
+        Unspecified               = code(b"wht?") => "Unspecified",
+        UnsupportedFileType       = code(b"typ?") => "Unsupported File Type",
+        UnsupportedDataFormat     = code(b"fmt?") => "Unsupported Data Format",
+        UnsupportedProperty       = code(b"pty?") => "Unsupported Property",
+        BadPropertySize           = code(b"!siz") => "Bad Property Size",
+        Permissions               = code(b"prm?") => "Permissions",
+        NotOptimized              = code(b"optm") => "Not Optimized",
+        InvalidChunk              = code(b"chk?") => "Invalid Chunk",
+        DoesNotAllow64BitDataSize = code(b"off?") => "Does Not Allow 64 Bit Data Size",
+        InvalidPacketOffset       = code(b"pck?") => "Invalid Packet Offset",
+        InvalidPacketDependency   = code(b"dep?") => "Invalid Packet Dependency",
+        InvalidFile               = code(b"dta?") => "Invalid File",
+        OperationNotSupported     = code(b"op??") => "Operation Not Supported",
+        NotOpen                   = -38           => "Not Open",
+        EndOfFile                 = -39           => "End Of File",
+        Position                  = -40           => "Position",
+        FileNotFound              = -43           => "File Not Found",
     }
 }
 
+/// kAudioFileStream statuses from AudioFileStream.h.
+pub mod audio_file_stream {
+    os_status_table! {
+        UnsupportedFileType      = code(b"typ?") => "Unsupported file type",
+        UnsupportedDataFormat    = code(b"fmt?") => "Unsupported data format",
+        UnsupportedProperty      = code(b"pty?") => "Unsupported property",
+        BadPropertySize          = code(b"!siz") => "Bad property size",
+        NotOptimized             = code(b"optm") => "Not optimized",
+        InvalidPacketOffset      = code(b"pck?") => "Invalid packet offset",
+        InvalidFile              = code(b"dta?") => "Invalid file",
+        ValueUnknown             = code(b"unk?") => "Value unknown",
+        DataUnavailable          = code(b"more") => "Data unavailable",
+        IllegalOperation         = code(b"nope") => "Illegal operation",
+        Unspecified              = code(b"wht?") => "Unspecified",
+        DiscontinuityCantRecover = code(b"dsc!") => "Discontinuity can't recover",
+    }
+}
 
-pub mod audio_unit {
-    use core_audio::OSStatus;
-
-    #[derive(Copy, Clone, Debug)]
-    pub enum Error {
-        InvalidProperty          = -10879,
-        InvalidParameter         = -10878,
-        InvalidElement           = -10877,
-        NoConnection             = -10876,
-        FailedInitialization     = -10875,
-        TooManyFramesToProcess   = -10874,
-        InvalidFile              = -10871,
-        FormatNotSupported       = -10868,
-        Uninitialized            = -10867,
-        InvalidScope             = -10866,
-        PropertyNotWritable      = -10865,
-        CannotDoInCurrentContext = -10863,
-        InvalidPropertyValue     = -10851,
-        PropertyNotInUse         = -10850,
-        Initialized              = -10849,
-        InvalidOfflineRender     = -10848,
-        Unauthorized             = -10847,
-        Unknown,
-    }
-
-    impl Error {
-
-        pub fn from_os_status(os_status: OSStatus) -> Result<(), Error> {
-            match os_status {
-                -10879 => Err(Error::InvalidProperty),
-                -10878 => Err(Error::InvalidParameter),
-                -10877 => Err(Error::InvalidElement),
-                -10876 => Err(Error::NoConnection),
-                -10875 => Err(Error::FailedInitialization),
-                -10874 => Err(Error::TooManyFramesToProcess),
-                -10871 => Err(Error::InvalidFile),
-                -10868 => Err(Error::FormatNotSupported),
-                -10867 => Err(Error::Uninitialized),
-                -10866 => Err(Error::InvalidScope),
-                -10865 => Err(Error::PropertyNotWritable),
-                -10863 => Err(Error::CannotDoInCurrentContext),
-                -10851 => Err(Error::InvalidPropertyValue),
-                -10850 => Err(Error::PropertyNotInUse),
-                -10849 => Err(Error::Initialized),
-                -10848 => Err(Error::InvalidOfflineRender),
-                -10847 => Err(Error::Unauthorized),
-                _      => Err(Error::Unknown),
-            }
-        }
+/// kExtAudioFileError statuses from ExtendedAudioFile.h.
+pub mod ext_audio_file {
+    os_status_table! {
+        CodecUnavailableInputConsumed    = -66559 => "Codec unavailable, input consumed",
+        CodecUnavailableInputNotConsumed = -66560 => "Codec unavailable, input not consumed",
+        InvalidProperty                  = -66561 => "Invalid property",
+        InvalidPropertySize              = -66562 => "Invalid property size",
+        NonPcmClientFormat               = -66563 => "Non-PCM client format",
+        InvalidChannelMap                = -66564 => "Invalid channel map",
+        InvalidOperationOrder            = -66565 => "Invalid operation order",
+        InvalidDataFormat                = -66566 => "Invalid data format",
+        MaxPacketSizeUnknown             = -66567 => "Max packet size unknown",
+        InvalidSeek                      = -66568 => "Invalid seek",
+        AsyncWriteTooLarge               = -66569 => "Async write too large",
+        AsyncWriteBufferOverflow         = -66570 => "Async write buffer overflow",
+    }
+}
 
-        pub fn to_os_status(&self) -> OSStatus {
-            *self as OSStatus
-        }
+/// kAudioConverterErr statuses from AudioConverter.h.
+pub mod audio_converter {
+    os_status_table! {
+        FormatNotSupported         = code(b"fmt?") => "Format not supported",
+        OperationNotSupported      = code(b"op??") => "Operation not supported",
+        PropertyNotSupported       = code(b"prop") => "Property not supported",
+        InvalidInputSize           = code(b"insz") => "Invalid input size",
+        InvalidOutputSize          = code(b"otsz") => "Invalid output size",
+        Unspecified                = code(b"what") => "Unspecified",
+        BadPropertySize            = code(b"!siz") => "Bad property size",
+        RequiresPacketDescriptions = code(b"!pkd") => "Requires packet descriptions",
+        InputSampleRateOutOfRange  = code(b"!isr") => "Input sample rate out of range",
+        OutputSampleRateOutOfRange = code(b"!osr") => "Output sample rate out of range",
+        HardwareInUse              = code(b"hwiu") => "Hardware in use",
+        NoHardwarePermission       = code(b"perm") => "No hardware permission",
+    }
+}
 
+/// kAudioQueueErr statuses from AudioQueue.h.
+pub mod audio_queue {
+    os_status_table! {
+        InvalidBuffer        = -66687 => "Invalid buffer",
+        BufferEmpty          = -66686 => "Buffer empty",
+        DisposalPending      = -66685 => "Disposal pending",
+        InvalidProperty      = -66684 => "Invalid property",
+        InvalidPropertySize  = -66683 => "Invalid property size",
+        InvalidParameter     = -66682 => "Invalid parameter",
+        CannotStart          = -66681 => "Cannot start",
+        InvalidDevice        = -66680 => "Invalid device",
+        BufferInQueue        = -66679 => "Buffer in queue",
+        InvalidRunState      = -66678 => "Invalid run state",
+        InvalidQueueType     = -66677 => "Invalid queue type",
+        Permissions          = -66676 => "Permissions",
+        InvalidPropertyValue = -66675 => "Invalid property value",
+        PrimeTimedOut        = -66674 => "Prime timed out",
+        CodecNotFound        = -66673 => "Codec not found",
+        InvalidCodecAccess   = -66672 => "Invalid codec access",
+        QueueInvalidated     = -66671 => "Queue invalidated",
+        TooManyTaps          = -66670 => "Too many taps",
+        InvalidTapContext    = -66669 => "Invalid tap context",
+        RecordUnderrun       = -66668 => "Record underrun",
+        InvalidTapType       = -66667 => "Invalid tap type",
+        BufferEnqueuedTwice  = -66666 => "Buffer enqueued twice",
+        CannotStartYet       = -66665 => "Cannot start yet",
+        EnqueueDuringReset   = -66632 => "Enqueue during reset",
+        InvalidOfflineMode   = -66626 => "Invalid offline mode",
     }
+}
 
-    impl ::std::fmt::Display for Error {
-        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
-            write!(f, "{:?}", self)
-        }
+/// kAudioHardware and kAudioDevice statuses from AudioHardwareBase.h, returned by the HAL.
+pub mod hardware {
+    os_status_table! {
+        NotRunning           = code(b"stop") => "Not running",
+        Unspecified          = code(b"what") => "Unspecified",
+        UnknownProperty      = code(b"who?") => "Unknown property",
+        BadPropertySize      = code(b"!siz") => "Bad property size",
+        IllegalOperation     = code(b"nope") => "Illegal operation",
+        BadObject            = code(b"!obj") => "Bad object",
+        BadDevice            = code(b"!dev") => "Bad device",
+        BadStream            = code(b"!str") => "Bad stream",
+        UnsupportedOperation = code(b"unop") => "Unsupported operation",
+        NotReady             = code(b"nrdy") => "Not ready",
+        UnsupportedFormat    = code(b"!dat") => "Unsupported format",
+        Permissions          = code(b"!hog") => "Permissions",
     }
+}
 
-    impl ::std::error::Error for Error {
-        fn description(&self) -> &str {
-            match *self {
-                Error::InvalidProperty          => "Invalid property",
-                Error::InvalidParameter         => "Invalid parameter",
-                Error::InvalidElement           => "Invalid element",
-                Error::NoConnection             => "No connection",
-                Error::FailedInitialization     => "Failed initialization",
-                Error::TooManyFramesToProcess   => "Too many frames to process",
-                Error::InvalidFile              => "Invalid file",
-                Error::FormatNotSupported       => "Format not supported",
-                Error::Uninitialized            => "Uninitialized",
-                Error::InvalidScope             => "Invalid scope",
-                Error::PropertyNotWritable      => "Property not writable",
-                Error::CannotDoInCurrentContext => "Cannot do in current context",
-                Error::InvalidPropertyValue     => "Invalid property value",
-                Error::PropertyNotInUse         => "Property not in use",
-                Error::Initialized              => "Initialized",
-                Error::InvalidOfflineRender     => "Invalid offline render",
-                Error::Unauthorized             => "Unauthorized",
-                Error::Unknown                  => "Unknown error occurred",
-            }
-        }
+/// Writes the status an error stands for in brackets after its name, spelt out too when it's a
+/// four character code, as in `UnsupportedDataFormat ('fmt?', 1718449215)` or `Param (-50)`.
+fn write_status(f: &mut ::std::fmt::Formatter, os_status: OSStatus) -> Result<(), ::std::fmt::Error> {
+    let four_cc = FourCC::from_os_status(os_status);
+    if four_cc.is_printable() {
+        write!(f, "({}, {})", four_cc, os_status)
+    }
+    else {
+        write!(f, "({})", os_status)
     }
+}
 
+/// The API a status came from, which picks between the tables that share a status.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Domain {
+    Audio,
+    AudioCodec,
+    AudioFormat,
+    AudioUnit,
+    AudioFile,
+    Graph,
+    AudioConverter,
+    AudioFileStream,
+    ExtAudioFile,
+    AudioQueue,
+    Hardware,
 }
 
-pub mod graph {
-    use core_audio::OSStatus;
-
-    #[derive(Copy, Clone, Debug)]
-    pub enum Error {
-        NodeNotFound                = -10860,
-        InvalidConnection           = -10861,
-        OutputNodeErr               = -10862,
-//		CannotDoInCurrentContext    = -10863,
-        InvalidAudioUnit            = -10864,
-        Unknown
-    }
-
-    impl Error {
-
-        pub fn from_os_status(os_status: OSStatus) -> Result<(), Error> {
-            match os_status {
-                -10860 => Err(Error::NodeNotFound),
-                -10861 => Err(Error::InvalidConnection),
-                -10862 => Err(Error::OutputNodeErr),
-//				-10863 => Err(Error::CannotDoInCurrentContext),
-                -10864 => Err(Error::InvalidAudioUnit),
-                _      => Err(Error::Unknown),
-            }
-        }
+impl Domain {
 
-        pub fn to_os_status(&self) -> OSStatus {
-            *self as OSStatus
-        }
+    /// Every domain, in the order `Error::from_os_status` looks through their tables.
+    pub const ALL: &'static [Domain] = &[Domain::Audio, Domain::AudioCodec, Domain::AudioFormat, Domain::AudioUnit,
+                                         Domain::AudioFile, Domain::Graph, Domain::AudioConverter,
+                                         Domain::AudioFileStream, Domain::ExtAudioFile, Domain::AudioQueue,
+                                         Domain::Hardware];
+}
+
+/// Which call failed, and the property, scope, element or file it was about.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Context {
+    /// the Core Audio function, e.g. `AudioUnitSetProperty`
+    pub operation: &'static str,
+    pub property: Option<u32>,
+    pub parameter: Option<u32>,
+    pub scope: Option<core_audio::AudioUnitScope>,
+    pub element: Option<core_audio::AudioUnitElement>,
+    pub path: Option<PathBuf>,
+}
+
+impl Context {
 
+    pub fn new(operation: &'static str) -> Context {
+        Context { operation, property: None, parameter: None, scope: None, element: None, path: None }
     }
 
-    impl ::std::fmt::Display for Error {
-        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
-            write!(f, "{:?}", self)
-        }
+    /// The property ID got or set.
+    pub fn property(mut self, property: u32) -> Context {
+        self.property = Some(property);
+        self
     }
 
-    impl ::std::error::Error for Error {
-        fn description(&self) -> &str {
-            match *self {
-                Error::NodeNotFound                 => "NodeNotFound",
-                Error::InvalidConnection          	=> "Invalid Connection",
-                Error::OutputNodeErr        		=> "Output Node Err",
-//				Error::CannotDoInCurrentContext     => "Cannot Do In Current Context",
-                Error::InvalidAudioUnit             => "Invalid Audio Unit",
-                Error::Unknown                      => "Unknown error occurred",
-            }
-        }
+    /// The parameter ID got, set or scheduled.
+    pub fn parameter(mut self, parameter: u32) -> Context {
+        self.parameter = Some(parameter);
+        self
+    }
+
+    /// The scope and element of the unit the call was about.
+    pub fn scope(mut self, scope: core_audio::AudioUnitScope, element: core_audio::AudioUnitElement) -> Context {
+        self.scope = Some(scope);
+        self.element = Some(element);
+        self
     }
 
+    /// The file the call was about.
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Context {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
 }
 
-pub mod audio_file {
-    use core_audio::OSStatus;
-
-    #[derive(Copy, Clone, Debug)]
-    pub enum Error {
-        Unspecified = 2003334207,
-        UnsupportedFileType = 1954115647,
-        UnsupportedDataFormat = 1718449215,
-        UnsupportedProperty = 1886681407,
-        BadPropertySize = 561211770,
-        Permissions = 1886547263,
-        NotOptimized = 1869640813,
-        InvalidChunk = 1667787583,
-        DoesNotAllow64BitDataSize = 1868981823,
-        InvalidPacketOffset = 1885563711,
-        InvalidFile = 1685348671,
-        OperationNotSupported = 1869627199,
-        NotOpen = -38,
-        EndOfFile = -39,
-        Position = -40,
-        FileNotFound = -43,
-        Unknown
-    }
-
-    impl Error {
-
-        pub fn from_os_status(os_status: OSStatus) -> Result<(), Error> {
-            match os_status {
-                2003334207 => Err(Error::Unspecified),
-                1954115647 => Err(Error::UnsupportedFileType),
-                1718449215 => Err(Error::UnsupportedDataFormat),
-                1886681407 => Err(Error::UnsupportedProperty),
-                561211770 => Err(Error::BadPropertySize),
-                1886547263 => Err(Error::Permissions),
-                1869640813 => Err(Error::NotOptimized),
-                1667787583 => Err(Error::InvalidChunk),
-                1868981823 => Err(Error::DoesNotAllow64BitDataSize),
-                1885563711 => Err(Error::InvalidPacketOffset),
-                1685348671 => Err(Error::InvalidFile),
-                1869627199 => Err(Error::OperationNotSupported),
-                -38 => Err(Error::NotOpen),
-                -39 => Err(Error::EndOfFile),
-                -40 => Err(Error::Position),
-                -43 => Err(Error::FileNotFound),
-                _      => Err(Error::Unknown),
-            }
+/// The call with what it was about in brackets, e.g.
+/// `AudioUnitSetProperty(property 8, scope Output, element 0)`. Property IDs that are four
+/// character codes, like Audio File Services', are spelt out.
+impl ::std::fmt::Display for Context {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        let mut details = Vec::new();
+        if let Some(property) = self.property {
+            details.push(format!("property {}", FourCC(property)));
         }
-
-        pub fn to_os_status(&self) -> OSStatus {
-            *self as OSStatus
+        if let Some(parameter) = self.parameter {
+            details.push(format!("parameter {}", parameter));
         }
-
-    }
-
-    impl ::std::fmt::Display for Error {
-        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
-            write!(f, "{:?}", self)
+        if let Some(scope) = self.scope {
+            details.push(match scope {
+                core_audio::kAudioUnitScope_Global => "scope Global".to_string(),
+                core_audio::kAudioUnitScope_Input  => "scope Input".to_string(),
+                core_audio::kAudioUnitScope_Output => "scope Output".to_string(),
+                _                                  => format!("scope {}", scope),
+            });
         }
-    }
-
-    impl ::std::error::Error for Error {
-        fn description(&self) -> &str {
-            match *self {
-                Error::Unspecified                  => "Unspecified",
-                Error::UnsupportedFileType          => "Unsupported File Type",
-                Error::UnsupportedDataFormat        => "Unsupported Data Format",
-                Error::UnsupportedProperty          => "Unsupported Property",
-                Error::BadPropertySize              => "Bad Property Size",
-                Error::Permissions                  => "Permissions",
-                Error::NotOptimized                 => "Not Optimized",
-                Error::InvalidChunk                 => "Invalid Chunk",
-                Error::DoesNotAllow64BitDataSize    => "Does Not Allow 64 Bit Data Size",
-                Error::InvalidPacketOffset          => "Invalid Packet Offset",
-                Error::InvalidFile                  => "Invalid File",
-                Error::OperationNotSupported        => "Operation Not Supported",
-                Error::NotOpen                      => "Not Open",
-                Error::EndOfFile                    => "End Of File",
-                Error::Position                     => "Position",
-                Error::FileNotFound                 => "File Not Found",
-                Error::Unknown                      => "Unknown error occurred",
-            }
+        if let Some(element) = self.element {
+            details.push(format!("element {}", element));
+        }
+        if let Some(ref path) = self.path {
+            details.push(format!("{:?}", path));
         }
+        write!(f, "{}({})", self.operation, details.join(", "))
     }
-
 }
 
-
 /// A wrapper around all possible Core Audio errors.
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Error {
     Unspecified,
     SystemSoundClientMessageTimedOut,
     NoMatchingDefaultAudioUnitFound,
     RenderCallbackBufferFormatDoesNotMatchAudioUnitStreamFormat,
     NoKnownSubtype,
+    InvalidStreamFormat,
     Audio(AudioError),
     AudioCodec(AudioCodecError),
     AudioFormat(AudioFormatError),
     AudioUnit(AudioUnitError),
     AudioFile(AudioFileError),
     Graph(GraphError),
+    AudioConverter(AudioConverterError),
+    AudioFileStream(AudioFileStreamError),
+    ExtAudioFile(ExtAudioFileError),
+    AudioQueue(AudioQueueError),
+    Hardware(HardwareError),
     Unknown(OSStatus),
+    /// an error together with the call it came from
+    Context(Context, Box<Error>),
+}
+
+/// The errors with no domain that a status converts back to. Apart from the first two, which are
+/// Audio Services', Core Audio doesn't have them, so they're four character codes of the crate's
+/// own that no table uses.
+const OWN_ERRORS: &[Error] = &[Error::Unspecified, Error::SystemSoundClientMessageTimedOut,
+                              Error::NoMatchingDefaultAudioUnitFound,
+                              Error::RenderCallbackBufferFormatDoesNotMatchAudioUnitStreamFormat,
+                              Error::NoKnownSubtype, Error::InvalidStreamFormat];
+
+/// The error in a table's result, which is the table's `Unknown` for a status not in it.
+fn table<E>(result: Result<(), E>, unknown: E) -> E {
+    match result {
+        Err(err) => err,
+        Ok(())   => unknown,
+    }
 }
 
 impl Error {
 
-    /// Convert an OSStatus to a std Rust Result.
+    /// Convert an OSStatus to a std Rust Result. A status in more than one table comes back as
+    /// the error from the first of `Domain::ALL` with it in, so an error from a table only comes
+    /// back as itself for sure from `from_domain_os_status`. Errors with no domain always do.
     pub fn from_os_status(os_status: OSStatus) -> Result<(), Error> {
-        match os_status {
-            0     => Ok(()),
-            -1500 => Err(Error::Unspecified),
-            -1501 => Err(Error::SystemSoundClientMessageTimedOut),
-            _     => {
-                match AudioError::from_os_status(os_status) {
-                    Ok(())                   => return Ok(()),
-                    Err(AudioError::Unknown) => (),
-                    Err(err)                 => return Err(Error::Audio(err)),
-                }
-                match AudioCodecError::from_os_status(os_status) {
-                    Ok(())                        => return Ok(()),
-                    Err(AudioCodecError::Unknown) => (),
-                    Err(err)                      => return Err(Error::AudioCodec(err)),
-                }
-                match AudioFormatError::from_os_status(os_status) {
-                    Ok(()) => return Ok(()),
-                    Err(AudioFormatError::Unknown) => (),
-                    Err(err) => return Err(Error::AudioFormat(err)),
-                }
-                match AudioUnitError::from_os_status(os_status) {
-                    Ok(())                       => return Ok(()),
-                    Err(AudioUnitError::Unknown) => (),
-                    Err(err)                     => return Err(Error::AudioUnit(err)),
-                }
-                match AudioFileError::from_os_status(os_status) {
-                    Ok(())                       => return Ok(()),
-                    Err(AudioFileError::Unknown) => (),
-                    Err(err)                     => return Err(Error::AudioFile(err)),
-                }
-                match GraphError::from_os_status(os_status) {
-                    Ok(())                       => return Ok(()),
-                    Err(GraphError::Unknown) => (),
-                    Err(err)                     => return Err(Error::Graph(err)),
-                }
-                Err(Error::Unknown(os_status))
-            },
+        if os_status == 0 {
+            return Ok(());
+        }
+        if let Some(err) = OWN_ERRORS.iter().find(|err| err.to_os_status() == os_status) {
+            return Err(err.clone());
+        }
+        let err = Domain::ALL.iter().filter_map(|&domain| Error::in_domain(domain, os_status)).next();
+        Err(err.unwrap_or(Error::Unknown(os_status)))
+    }
+
+    /// Convert an OSStatus returned by an API in `domain` to a std Rust Result, looking in that
+    /// API's table before the rest. This undoes `to_os_status` for an error whose `domain()` is
+    /// `domain`, including the table's `Unknown`.
+    pub fn from_domain_os_status(domain: Domain, os_status: OSStatus) -> Result<(), Error> {
+        match Error::in_domain(domain, os_status) {
+            Some(err) => Err(err),
+            None      => Error::from_os_status(os_status),
+        }
+    }
+
+    /// The error `os_status` is in `domain`'s table, if it's there. `UNSPECIFIED` is the table's
+    /// `Unknown`, which is what it converts to.
+    fn in_domain(domain: Domain, os_status: OSStatus) -> Option<Error> {
+        let err = match domain {
+            Domain::Audio =>
+                Error::Audio(table(AudioError::from_os_status(os_status), AudioError::Unknown)),
+            Domain::AudioCodec =>
+                Error::AudioCodec(table(AudioCodecError::from_os_status(os_status), AudioCodecError::Unknown)),
+            Domain::AudioFormat =>
+                Error::AudioFormat(table(AudioFormatError::from_os_status(os_status), AudioFormatError::Unknown)),
+            Domain::AudioUnit =>
+                Error::AudioUnit(table(AudioUnitError::from_os_status(os_status), AudioUnitError::Unknown)),
+            Domain::AudioFile =>
+                Error::AudioFile(table(AudioFileError::from_os_status(os_status), AudioFileError::Unknown)),
+            Domain::Graph =>
+                Error::Graph(table(GraphError::from_os_status(os_status), GraphError::Unknown)),
+            Domain::AudioConverter =>
+                Error::AudioConverter(table(AudioConverterError::from_os_status(os_status),
+                                            AudioConverterError::Unknown)),
+            Domain::AudioFileStream =>
+                Error::AudioFileStream(table(AudioFileStreamError::from_os_status(os_status),
+                                             AudioFileStreamError::Unknown)),
+            Domain::ExtAudioFile =>
+                Error::ExtAudioFile(table(ExtAudioFileError::from_os_status(os_status), ExtAudioFileError::Unknown)),
+            Domain::AudioQueue =>
+                Error::AudioQueue(table(AudioQueueError::from_os_status(os_status), AudioQueueError::Unknown)),
+            Domain::Hardware =>
+                Error::Hardware(table(HardwareError::from_os_status(os_status), HardwareError::Unknown)),
+        };
+        // a status the table doesn't have gives the `Unknown`, whose status is `UNSPECIFIED`
+        if err.to_os_status() == os_status { Some(err) } else { None }
+    }
+
+    /// The error with `context` saying where it came from.
+    pub fn with_context(self, context: Context) -> Error {
+        Error::Context(context, Box::new(self))
+    }
+
+    /// The context of the call the error came from, if it has one.
+    pub fn context(&self) -> Option<&Context> {
+        match *self {
+            Error::Context(ref context, _) => Some(context),
+            _                              => None,
         }
     }
 
-    /// Convert an Error to an OSStatus.
+    /// The error without any context, for matching on.
+    pub fn root(&self) -> &Error {
+        match *self {
+            Error::Context(_, ref err) => err.root(),
+            _                          => self,
+        }
+    }
+
+    /// The API whose table the error is from, if it's from one.
+    pub fn domain(&self) -> Option<Domain> {
+        match *self {
+            Error::Context(_, ref err) => err.domain(),
+            Error::Audio(_)           => Some(Domain::Audio),
+            Error::AudioCodec(_)      => Some(Domain::AudioCodec),
+            Error::AudioFormat(_)     => Some(Domain::AudioFormat),
+            Error::AudioUnit(_)       => Some(Domain::AudioUnit),
+            Error::AudioFile(_)       => Some(Domain::AudioFile),
+            Error::Graph(_)           => Some(Domain::Graph),
+            Error::AudioConverter(_)  => Some(Domain::AudioConverter),
+            Error::AudioFileStream(_) => Some(Domain::AudioFileStream),
+            Error::ExtAudioFile(_)    => Some(Domain::ExtAudioFile),
+            Error::AudioQueue(_)      => Some(Domain::AudioQueue),
+            Error::Hardware(_)        => Some(Domain::Hardware),
+            _                         => None,
+        }
+    }
+
+    /// Convert an Error to an OSStatus. The crate's own errors, which Core Audio doesn't have,
+    /// convert to four character codes that none of its tables use.
     pub fn to_os_status(&self) -> OSStatus {
         match *self {
-            Error::Unspecified                                                 => -1500,
-            Error::NoMatchingDefaultAudioUnitFound                             => -1500,
-            Error::RenderCallbackBufferFormatDoesNotMatchAudioUnitStreamFormat => -1500,
+            Error::Unspecified                                                 => UNSPECIFIED,
             Error::SystemSoundClientMessageTimedOut                            => -1501,
-            Error::Audio(err)                                                  => err as OSStatus,
-            Error::AudioCodec(err)                                             => err as OSStatus,
-            Error::AudioUnit(err)                                              => err as OSStatus,
-            Error::AudioFile(err)                                              => err as OSStatus,
-            Error::Graph(err)                  		                           => err as OSStatus,
-            _                                                                  => -1500,
+            Error::NoMatchingDefaultAudioUnitFound                             => code(b"nodf"),
+            Error::RenderCallbackBufferFormatDoesNotMatchAudioUnitStreamFormat => code(b"rcf?"),
+            Error::NoKnownSubtype                                              => code(b"sub?"),
+            Error::InvalidStreamFormat                                         => code(b"asbd"),
+            Error::Audio(err)                                                  => err.to_os_status(),
+            Error::AudioCodec(err)                                             => err.to_os_status(),
+            Error::AudioFormat(err)                                            => err.to_os_status(),
+            Error::AudioUnit(err)                                              => err.to_os_status(),
+            Error::AudioFile(err)                                              => err.to_os_status(),
+            Error::Graph(err)                                                  => err.to_os_status(),
+            Error::AudioConverter(err)                                         => err.to_os_status(),
+            Error::AudioFileStream(err)                                        => err.to_os_status(),
+            Error::ExtAudioFile(err)                                           => err.to_os_status(),
+            Error::AudioQueue(err)                                             => err.to_os_status(),
+            Error::Hardware(err)                                               => err.to_os_status(),
+            Error::Unknown(os_status)                                          => os_status,
+            Error::Context(_, ref err)                                         => err.to_os_status(),
         }
     }
 
@@ -493,7 +588,29 @@ impl Error {
 
 impl ::std::fmt::Display for Error {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
-        write!(f, "{:?}", self)
+        match *self {
+            Error::Audio(ref err)           => err.fmt(f),
+            Error::AudioCodec(ref err)      => err.fmt(f),
+            Error::AudioFormat(ref err)     => err.fmt(f),
+            Error::AudioUnit(ref err)       => err.fmt(f),
+            Error::AudioFile(ref err)       => err.fmt(f),
+            Error::Graph(ref err)           => err.fmt(f),
+            Error::AudioConverter(ref err)  => err.fmt(f),
+            Error::AudioFileStream(ref err) => err.fmt(f),
+            Error::ExtAudioFile(ref err)    => err.fmt(f),
+            Error::AudioQueue(ref err)      => err.fmt(f),
+            Error::Hardware(ref err)        => err.fmt(f),
+            Error::Unknown(os_status)       => {
+                try!(write!(f, "Unknown "));
+                write_status(f, os_status)
+            },
+            // what it failed with is the error's source, so reporters walking the chain print it once
+            Error::Context(ref context, _) => write!(f, "{} failed", context),
+            _                               => {
+                try!(write!(f, "{:?} ", self));
+                write_status(f, self.to_os_status())
+            },
+        }
     }
 }
 
@@ -506,14 +623,186 @@ impl ::std::error::Error for Error {
                 "The given render callback buffer format does not match the `AudioUnit` `StreamFormat`",
             Error::SystemSoundClientMessageTimedOut => "The system sound client message timed out",
             Error::NoKnownSubtype                   => "The type has no known subtypes",
+            Error::InvalidStreamFormat              => "The stream format is malformed or its fields are inconsistent",
             Error::Audio(ref err)                   => err.description(),
             Error::AudioCodec(ref err)              => err.description(),
             Error::AudioFormat(ref err)             => err.description(),
             Error::AudioUnit(ref err)               => err.description(),
             Error::AudioFile(ref err)               => err.description(),
-            Error::Graph(ref err)               	=> err.description(),
+            Error::Graph(ref err)                   => err.description(),
+            Error::AudioConverter(ref err)          => err.description(),
+            Error::AudioFileStream(ref err)         => err.description(),
+            Error::ExtAudioFile(ref err)            => err.description(),
+            Error::AudioQueue(ref err)              => err.description(),
+            Error::Hardware(ref err)                => err.description(),
             Error::Unknown(_)                       => "An unknown error unknown to the coreaudio-rs API occurred",
+            Error::Context(_, ref err)              => err.description(),
+        }
+    }
+
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            Error::Context(_, ref err) => Some(&**err),
+            _                          => None,
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+
+    use super::{AudioCodecError, AudioConverterError, AudioError, AudioFileError, AudioFileStreamError,
+                AudioFormatError, AudioQueueError, AudioUnitError, Context, Domain, Error, ExtAudioFileError,
+                GraphError, HardwareError, OWN_ERRORS};
+    use core_audio;
+    use std::error::Error as StdError;
+
+    /// every error there is without a context: each table's, `Unknown` included, and the rest
+    fn every_error() -> Vec<Error> {
+        let mut errors = OWN_ERRORS.to_vec();
+        macro_rules! table {
+            ($variant:ident, $table:ident) => {
+                errors.extend($table::ALL.iter().chain(&[$table::Unknown]).map(|&err| Error::$variant(err)));
+            }
+        }
+        table!(Audio, AudioError);
+        table!(AudioCodec, AudioCodecError);
+        table!(AudioFormat, AudioFormatError);
+        table!(AudioUnit, AudioUnitError);
+        table!(AudioFile, AudioFileError);
+        table!(Graph, GraphError);
+        table!(AudioConverter, AudioConverterError);
+        table!(AudioFileStream, AudioFileStreamError);
+        table!(ExtAudioFile, ExtAudioFileError);
+        table!(AudioQueue, AudioQueueError);
+        table!(Hardware, HardwareError);
+        errors.push(Error::Unknown(1));
+        errors.push(Error::Unknown(-12345));
+        errors.push(Error::Unknown(0x6875683f));
+        errors
+    }
+
+    #[test]
+    fn tables_round_trip() {
+        macro_rules! round_trip {
+            ($table:ident) => {
+                for &err in $table::ALL {
+                    assert!(err.to_os_status() != 0, "{:?}", err);
+                    assert_eq!($table::from_os_status(err.to_os_status()), Err(err));
+                }
+                assert_eq!($table::from_os_status(0), Ok(()));
+                assert_eq!($table::from_os_status(1), Err($table::Unknown));
+            }
+        }
+        round_trip!(AudioError);
+        round_trip!(AudioCodecError);
+        round_trip!(AudioFormatError);
+        round_trip!(AudioUnitError);
+        round_trip!(AudioFileError);
+        round_trip!(GraphError);
+        round_trip!(AudioConverterError);
+        round_trip!(AudioFileStreamError);
+        round_trip!(ExtAudioFileError);
+        round_trip!(AudioQueueError);
+        round_trip!(HardwareError);
+    }
+
+    #[test]
+    fn errors_round_trip() {
+        for err in every_error() {
+            let os_status = err.to_os_status();
+            assert!(os_status != 0, "{:?}", err);
+            let found = match err.domain() {
+                Some(domain) => Error::from_domain_os_status(domain, os_status),
+                None         => Error::from_os_status(os_status),
+            };
+            assert_eq!(found, Err(err.clone()));
+            // context doesn't change the status
+            let with_context = err.clone().with_context(Context::new("AudioUnitInitialize"));
+            assert_eq!(with_context.to_os_status(), os_status);
+        }
+        assert_eq!(Error::from_os_status(0), Ok(()));
+        assert_eq!(Error::from_domain_os_status(Domain::Graph, 0), Ok(()));
+    }
+
+    #[test]
+    fn the_domain_picks_between_tables_sharing_a_status() {
+        let fmt = 0x666d743f;
+        assert_eq!(Error::from_domain_os_status(Domain::AudioFile, fmt),
+                   Err(Error::AudioFile(AudioFileError::UnsupportedDataFormat)));
+        assert_eq!(Error::from_domain_os_status(Domain::AudioFormat, fmt),
+                   Err(Error::AudioFormat(AudioFormatError::UnsupportedDataFormat)));
+        assert_eq!(Error::from_domain_os_status(Domain::AudioConverter, fmt),
+                   Err(Error::AudioConverter(AudioConverterError::FormatNotSupported)));
+        assert_eq!(Error::from_domain_os_status(Domain::Graph, -10863),
+                   Err(Error::Graph(GraphError::CannotDoInCurrentContext)));
+        assert_eq!(Error::from_domain_os_status(Domain::AudioUnit, -10863),
+                   Err(Error::AudioUnit(AudioUnitError::CannotDoInCurrentContext)));
+        assert_eq!(Error::from_domain_os_status(Domain::AudioFile, -43),
+                   Err(Error::AudioFile(AudioFileError::FileNotFound)));
+        // without one, the first table in `Domain::ALL` with the status wins
+        assert_eq!(Error::from_os_status(-43), Err(Error::Audio(AudioError::FileNotFound)));
+        // a status that isn't in the domain's table is looked for in the rest
+        assert_eq!(Error::from_domain_os_status(Domain::AudioFile, -50), Err(Error::Audio(AudioError::Param)));
+        assert_eq!(Error::from_domain_os_status(Domain::AudioFile, -12345), Err(Error::Unknown(-12345)));
+        // `UNSPECIFIED` is the domain's `Unknown`, or `Unspecified` without one
+        assert_eq!(Error::from_domain_os_status(Domain::AudioQueue, -1500),
+                   Err(Error::AudioQueue(AudioQueueError::Unknown)));
+        assert_eq!(Error::from_os_status(-1500), Err(Error::Unspecified));
+    }
+
+    #[test]
+    fn statuses_are_the_documented_ones() {
+        assert_eq!(AudioFormatError::Unspecified.to_os_status(), 0x77686174);
+        assert_eq!(AudioFormatError::UnknownFormat.to_os_status(), 0x21666d74);
+        assert_eq!(Error::AudioFormat(AudioFormatError::BadSpecifierSize).to_os_status(), 0x21737063);
+        assert_eq!(GraphError::CannotDoInCurrentContext.to_os_status(), -10863);
+        assert_eq!(Error::SystemSoundClientMessageTimedOut.to_os_status(), -1501);
+    }
+
+    #[test]
+    fn displays_the_status() {
+        assert_eq!(AudioError::BadFilePath.to_string(), "BadFilePath ('!pth', 561017960)");
+        assert_eq!(AudioError::Param.to_string(), "Param (-50)");
+        assert_eq!(AudioError::Unknown.to_string(), "Unknown");
+        assert_eq!(Error::AudioFile(AudioFileError::UnsupportedDataFormat).to_string(),
+                   "UnsupportedDataFormat ('fmt?', 1718449215)");
+        assert_eq!(Error::AudioUnit(AudioUnitError::FormatNotSupported).to_string(), "FormatNotSupported (-10868)");
+        assert_eq!(Error::Unknown(0x6875683f).to_string(), "Unknown ('huh?', 1752524863)");
+        assert_eq!(Error::Unknown(-1).to_string(), "Unknown (-1)");
+        assert_eq!(Error::Unspecified.to_string(), "Unspecified (-1500)");
+        assert_eq!(Error::NoKnownSubtype.to_string(), "NoKnownSubtype ('sub?', 1937072703)");
+    }
+
+    #[test]
+    fn context_says_which_call_failed() {
+        let inner = Error::AudioUnit(AudioUnitError::FormatNotSupported);
+        let err = inner.clone().with_context(Context::new("AudioUnitSetProperty")
+                                             .property(core_audio::kAudioUnitProperty_StreamFormat)
+                                             .scope(core_audio::kAudioUnitScope_Output, 0));
+        assert_eq!(err.to_string(), "AudioUnitSetProperty(property 8, scope Output, element 0) failed");
+        assert_eq!(err.to_os_status(), -10868);
+        assert_eq!(err.domain(), Some(Domain::AudioUnit));
+        assert_eq!(*err.root(), inner);
+        assert_eq!(err.source().map(|source| source.to_string()), Some(inner.to_string()));
+        assert!(inner.source().is_none());
+        assert!(inner.context().is_none());
+
+        // walking the chain gives each error once
+        let outer = err.clone().with_context(Context::new("AudioUnitInitialize"));
+        let mut chain = Vec::new();
+        let mut next: Option<&dyn StdError> = Some(&outer);
+        while let Some(err) = next {
+            chain.push(err.to_string());
+            next = err.source();
+        }
+        assert_eq!(chain, vec!["AudioUnitInitialize() failed".to_string(),
+                               "AudioUnitSetProperty(property 8, scope Output, element 0) failed".to_string(),
+                               "FormatNotSupported (-10868)".to_string()]);
+
+        let err = Error::AudioFile(AudioFileError::FileNotFound)
+            .with_context(Context::new("AudioFileOpenURL").path("/tmp/a.wav"));
+        assert_eq!(err.to_string(), "AudioFileOpenURL(\"/tmp/a.wav\") failed");
+        assert_eq!(err.context().unwrap().to_string(), "AudioFileOpenURL(\"/tmp/a.wav\")");
+    }
+}
@@ -0,0 +1,117 @@
+//! Four character codes, the big endian packed ASCII that Core Audio uses for IDs, types and a
+//! lot of its `OSStatus` values, e.g. `'lpcm'` or `'fmt?'`.
+
+use core_audio::OSStatus;
+use error::Error;
+
+/// A four character code.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FourCC(pub u32);
+
+impl FourCC {
+
+    /// The code spelt by `bytes`, as in `FourCC::new(*b"lpcm")`.
+    pub fn new(bytes: [u8; 4]) -> FourCC {
+        FourCC((bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32)
+    }
+
+    /// The code an `OSStatus` holds, if it's one of the statuses that are four character codes.
+    pub fn from_os_status(os_status: OSStatus) -> FourCC {
+        FourCC(os_status as u32)
+    }
+
+    pub fn value(&self) -> u32 {
+        self.0
+    }
+
+    /// The four characters, first to last.
+    pub fn bytes(&self) -> [u8; 4] {
+        [(self.0 >> 24) as u8, (self.0 >> 16) as u8, (self.0 >> 8) as u8, self.0 as u8]
+    }
+
+    /// Whether all four characters are printable ASCII, so that this is likely to really be a
+    /// four character code rather than just a number.
+    pub fn is_printable(&self) -> bool {
+        self.bytes().iter().all(|byte| (0x20..0x7f).contains(byte))
+    }
+}
+
+impl From<u32> for FourCC {
+    fn from(value: u32) -> FourCC {
+        FourCC(value)
+    }
+}
+
+impl From<[u8; 4]> for FourCC {
+    fn from(bytes: [u8; 4]) -> FourCC {
+        FourCC::new(bytes)
+    }
+}
+
+impl From<FourCC> for u32 {
+    fn from(four_cc: FourCC) -> u32 {
+        four_cc.0
+    }
+}
+
+/// The characters in single quotes, `'lpcm'`, or the number if they aren't printable.
+impl ::std::fmt::Display for FourCC {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        if self.is_printable() {
+            let bytes = self.bytes();
+            write!(f, "'{}{}{}{}'", bytes[0] as char, bytes[1] as char, bytes[2] as char, bytes[3] as char)
+        }
+        else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+impl ::std::fmt::Debug for FourCC {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        write!(f, "FourCC({})", self)
+    }
+}
+
+/// Reads four printable ASCII characters, with or without the single quotes around them.
+impl ::std::str::FromStr for FourCC {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<FourCC, Error> {
+        let quoted = text.len() == 6 && text.starts_with('\'') && text.ends_with('\'');
+        let code = if quoted { &text[1..5] } else { text };
+        let bytes = code.as_bytes();
+        if bytes.len() != 4 {
+            return Err(Error::Unspecified);
+        }
+        let four_cc = FourCC::new([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        if four_cc.is_printable() { Ok(four_cc) } else { Err(Error::Unspecified) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::FourCC;
+    use core_audio;
+
+    #[test]
+    fn spells_out_codes() {
+        let lpcm = FourCC::new(*b"lpcm");
+        assert_eq!(lpcm.value(), core_audio::kAudioFormatLinearPCM);
+        assert_eq!(lpcm.to_string(), "'lpcm'");
+        assert_eq!(FourCC::from_os_status(561017960).to_string(), "'!pth'");
+        assert_eq!(format!("{:?}", FourCC::from(1718449215)), "FourCC('fmt?')");
+        // -50 isn't a code
+        assert_eq!(FourCC::from_os_status(-50).to_string(), "4294967246");
+    }
+
+    #[test]
+    fn parses_codes() {
+        assert_eq!("lpcm".parse::<FourCC>().unwrap(), FourCC::new(*b"lpcm"));
+        assert_eq!("'fmt?'".parse::<FourCC>().unwrap().value(), 1718449215);
+        assert!("lpc".parse::<FourCC>().is_err());
+        assert!("lpcm?".parse::<FourCC>().is_err());
+        assert!("lp\ncm".parse::<FourCC>().is_err());
+    }
+}
@@ -0,0 +1,207 @@
+//! An owned audio graph with its lifecycle tracked in the type.
+//!
+//! A graph moves through `Created` -> `Opened` -> `Initialized` -> `Running`, and each transition
+//! consumes the graph in one state and hands back the next one, so e.g. `node_info` can't be
+//! called before the graph is open. However far a graph got, dropping it stops, uninitializes,
+//! closes and disposes of it in that order.
+
+use audio_file::AudioFile;
+use audio_unit::AudioUnit;
+use backend::{AudioBackend, CompletionProc, DefaultBackend, FileRegion, PortableBackend};
+use core_audio;
+use error::Error;
+use mixer::Mixer;
+use std::marker::PhantomData;
+
+/// The graph has been created but its audio units haven't been opened yet.
+pub struct Created;
+/// The graph's audio units are open, so they can be looked up and configured.
+pub struct Opened;
+/// The graph is initialized and ready to start.
+pub struct Initialized;
+/// The graph is rendering.
+pub struct Running;
+
+/// Implemented by the states in which the graph's audio units are open.
+pub trait Open {}
+impl Open for Opened {}
+impl Open for Initialized {}
+impl Open for Running {}
+
+/// Owns the backend's graph and remembers how far through the lifecycle it got, so that dropping
+/// it can unwind exactly the steps that were taken.
+struct Handle<B: AudioBackend> {
+    graph: B::Graph,
+    opened: bool,
+    initialized: bool,
+    running: bool,
+}
+
+impl<B: AudioBackend> Drop for Handle<B> {
+    fn drop(&mut self) {
+        // errors are ignored here, there is nobody to report them to and we still want to get
+        // as far as disposing of the graph
+        if self.running {
+            let _ = B::graph_stop(&mut self.graph);
+        }
+        if self.initialized {
+            let _ = B::graph_uninitialize(&mut self.graph);
+        }
+        if self.opened {
+            let _ = B::graph_close(&mut self.graph);
+        }
+        let _ = B::dispose_au_graph(&mut self.graph);
+    }
+}
+
+/// An audio graph in lifecycle state `S`. The graph borrows any `AudioFile` it schedules for
+/// `'a`, so it can't outlive them.
+pub struct Graph<'a, S, B: AudioBackend = DefaultBackend> {
+    handle: Handle<B>,
+    _state: PhantomData<S>,
+    _files: PhantomData<&'a AudioFile<B>>,
+}
+
+impl<'a, B: AudioBackend> Graph<'a, Created, B> {
+
+    /// wraps NewAUGraph
+    pub fn new() -> Result<Graph<'a, Created, B>, Error> {
+        let graph = try!(B::new_au_graph());
+        Ok(Graph {
+            handle: Handle { graph, opened: false, initialized: false, running: false },
+            _state: PhantomData,
+            _files: PhantomData,
+        })
+    }
+
+    /// wraps AUGraphOpen
+    pub fn open(mut self) -> Result<Graph<'a, Opened, B>, Error> {
+        try!(B::graph_open(&mut self.handle.graph));
+        self.handle.opened = true;
+        Ok(self.into_state())
+    }
+}
+
+impl<'a, B: AudioBackend> Graph<'a, Opened, B> {
+
+    /// wraps AUGraphInitialize
+    pub fn initialize(mut self) -> Result<Graph<'a, Initialized, B>, Error> {
+        try!(B::graph_initialize(&mut self.handle.graph));
+        self.handle.initialized = true;
+        Ok(self.into_state())
+    }
+}
+
+impl<'a, B: AudioBackend> Graph<'a, Initialized, B> {
+
+    /// wraps AUGraphStart
+    pub fn start(mut self) -> Result<Graph<'a, Running, B>, Error> {
+        try!(B::graph_start(&mut self.handle.graph));
+        self.handle.running = true;
+        Ok(self.into_state())
+    }
+}
+
+impl<'a, B: AudioBackend> Graph<'a, Running, B> {
+
+    /// wraps AUGraphStop
+    pub fn stop(mut self) -> Result<Graph<'a, Initialized, B>, Error> {
+        try!(B::graph_stop(&mut self.handle.graph));
+        self.handle.running = false;
+        Ok(self.into_state())
+    }
+}
+
+impl<'a> Graph<'a, Running, PortableBackend> {
+
+    /// Blocks until a graph ending in a null output has rendered everything, see
+    /// `PortableGraph::wait_until_finished`.
+    pub fn wait_until_finished(&self) -> Result<(), Error> {
+        self.handle.graph.wait_until_finished()
+    }
+}
+
+impl<'a, S, B: AudioBackend> Graph<'a, S, B> {
+
+    /// wraps AUGraphAddNode
+    pub fn add_node(&mut self, component_type : u32, component_sub_type : u32,
+                    manufacturer : u32) -> Result<core_audio::AUNode, Error> {
+        let description = core_audio::AudioComponentDescription { componentType: component_type,
+                                                                  componentSubType: component_sub_type,
+                                                                  componentManufacturer: manufacturer,
+                                                                  componentFlags: 0,
+                                                                  componentFlagsMask: 0 };
+        B::graph_add_node(&mut self.handle.graph, &description)
+    }
+
+    /// Adds a multichannel mixer, see `mixer`.
+    pub fn add_mixer(&mut self) -> Result<core_audio::AUNode, Error> {
+        self.add_node(core_audio::kAudioUnitType_Mixer, core_audio::kAudioUnitSubType_MultiChannelMixer,
+                      core_audio::kAudioUnitManufacturer_Apple)
+    }
+
+    /// Adds a format converter (kAudioUnitSubType_AUConverter), which resamples its input to the
+    /// sample rate of its output.
+    pub fn add_converter(&mut self) -> Result<core_audio::AUNode, Error> {
+        self.add_node(core_audio::kAudioUnitType_FormatConverter, core_audio::kAudioUnitSubType_AUConverter,
+                      core_audio::kAudioUnitManufacturer_Apple)
+    }
+
+    /// wraps AUGraphConnectNodeInput
+    pub fn connect_node_input(&mut self, source_node : core_audio::AUNode, source_output : u32,
+                              dest_node : core_audio::AUNode, dest_input : u32) -> Result<(), Error> {
+        B::graph_connect_node_input(&mut self.handle.graph, source_node, source_output, dest_node, dest_input)
+    }
+
+    fn into_state<T>(self) -> Graph<'a, T, B> {
+        Graph { handle: self.handle, _state: PhantomData, _files: PhantomData }
+    }
+}
+
+impl<'a, S: Open, B: AudioBackend> Graph<'a, S, B> {
+
+    /// wraps AUGraphNodeInfo. The unit borrows the graph, so it can't be kept once the graph has
+    /// moved on to another state or been disposed of.
+    pub fn node_info<'g>(&'g self, node : core_audio::AUNode) -> Result<AudioUnit<'g, B>, Error> {
+        Ok(AudioUnit::new(try!(self.unit(node))))
+    }
+
+    /// The node's audio unit, for getting and setting its properties.
+    pub fn audio_unit<'g>(&'g self, node : core_audio::AUNode) -> Result<AudioUnit<'g, B>, Error> {
+        self.node_info(node)
+    }
+
+    /// Volume, pan and mute for each input of a mixer node added with `add_mixer`.
+    pub fn mixer<'g>(&'g self, node : core_audio::AUNode) -> Result<Mixer<'g, B>, Error> {
+        Ok(Mixer::new(try!(self.audio_unit(node))))
+    }
+
+    /// The backend's unit for the node, not tied to `self`. Only for the players, which own their
+    /// graph and keep its units alongside it.
+    pub(crate) fn unit(&self, node : core_audio::AUNode) -> Result<B::Unit, Error> {
+        B::graph_node_info(&self.handle.graph, node)
+    }
+
+    /// Hands `audio_files` to the file player of `node`, replacing any it had before. Only regions
+    /// of these files can then be scheduled on it.
+    pub fn set_scheduled_file_ids(&self, node : core_audio::AUNode,
+                                  scope : core_audio::AudioUnitScope,
+                                  element : core_audio::AudioUnitElement,
+                                  audio_files : &[&'a AudioFile<B>]) -> Result<(), Error> {
+        let audio_unit = try!(self.unit(node));
+        let files : Vec<&B::File> = audio_files.iter().map(|audio_file| audio_file.raw()).collect();
+        // the unit is looked up in this graph, which borrows the files for 'a, so they're open
+        // for as long as it can play them
+        unsafe { B::set_scheduled_file_ids(&audio_unit, scope, element, &files) }
+    }
+
+    /// Schedules a region of `audio_file` on the file player of `node`. `completion` is called
+    /// once the file player has finished with the region.
+    pub fn set_scheduled_file_region(&self, node : core_audio::AUNode,
+                                     audio_file : &'a AudioFile<B>,
+                                     region : &FileRegion,
+                                     completion : Option<CompletionProc>) -> Result<(), Error> {
+        let audio_unit = try!(self.unit(node));
+        unsafe { B::audio_unit_set_scheduled_file_region(&audio_unit, audio_file.raw(), region, completion) }
+    }
+}
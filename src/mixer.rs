@@ -0,0 +1,199 @@
+//! A multichannel mixer node, for playing several sources at once at their own levels.
+
+use audio_unit::AudioUnit;
+use backend::{AudioBackend, DefaultBackend, MIXER_MUTE_PARAMETER};
+use core_audio;
+use error::Error;
+use property;
+
+/// Controls a kAudioUnitSubType_MultiChannelMixer node, see `Graph::add_mixer`. Each input bus
+/// has a volume, pan and enable switch as the AU has them, and can also be muted, which silences
+/// it without forgetting its volume. All of it is kept by the unit, so every `Mixer` for the same
+/// node sees the same settings.
+pub struct Mixer<'g, B: AudioBackend = DefaultBackend> {
+    unit: AudioUnit<'g, B>,
+}
+
+impl<'g, B: AudioBackend> Mixer<'g, B> {
+
+    /// Takes charge of a mixer's unit.
+    pub fn new(unit: AudioUnit<'g, B>) -> Mixer<'g, B> {
+        Mixer { unit }
+    }
+
+    /// How many inputs the mixer has, 8 to begin with.
+    pub fn input_count(&self) -> Result<u32, Error> {
+        self.unit.get::<property::ElementCount>(core_audio::kAudioUnitScope_Input, 0)
+    }
+
+    /// Adds or removes inputs. It has to be done before the graph is initialized.
+    pub fn set_input_count(&mut self, count: u32) -> Result<(), Error> {
+        self.unit.set::<property::ElementCount>(core_audio::kAudioUnitScope_Input, 0, &count)
+    }
+
+    /// An input's volume, from 0 to 1. A muted input keeps the volume it will have once unmuted,
+    /// and changing it, or ramping it, leaves the input silent until then.
+    pub fn volume(&self, bus: u32) -> Result<f32, Error> {
+        self.get(core_audio::kMultiChannelMixerParam_Volume, core_audio::kAudioUnitScope_Input, bus)
+    }
+
+    pub fn set_volume(&mut self, bus: u32, volume: f32) -> Result<(), Error> {
+        self.set(core_audio::kMultiChannelMixerParam_Volume, core_audio::kAudioUnitScope_Input, bus, volume)
+    }
+
+    /// An input's pan, from -1 (left) to 1 (right).
+    pub fn pan(&self, bus: u32) -> Result<f32, Error> {
+        self.get(core_audio::kMultiChannelMixerParam_Pan, core_audio::kAudioUnitScope_Input, bus)
+    }
+
+    pub fn set_pan(&mut self, bus: u32, pan: f32) -> Result<(), Error> {
+        self.set(core_audio::kMultiChannelMixerParam_Pan, core_audio::kAudioUnitScope_Input, bus, pan)
+    }
+
+    /// Whether an input is switched on. A disabled input isn't mixed in at all.
+    pub fn is_enabled(&self, bus: u32) -> Result<bool, Error> {
+        self.get(core_audio::kMultiChannelMixerParam_Enable, core_audio::kAudioUnitScope_Input, bus)
+            .map(|enable| enable != 0.0)
+    }
+
+    pub fn set_enabled(&mut self, bus: u32, enabled: bool) -> Result<(), Error> {
+        self.set(core_audio::kMultiChannelMixerParam_Enable, core_audio::kAudioUnitScope_Input, bus,
+                 if enabled { 1.0 } else { 0.0 })
+    }
+
+    pub fn is_muted(&self, bus: u32) -> Result<bool, Error> {
+        self.get(MIXER_MUTE_PARAMETER, core_audio::kAudioUnitScope_Input, bus).map(|mute| mute != 0.0)
+    }
+
+    /// Silences an input, or brings it back at the volume it had.
+    pub fn set_muted(&mut self, bus: u32, muted: bool) -> Result<(), Error> {
+        self.set(MIXER_MUTE_PARAMETER, core_audio::kAudioUnitScope_Input, bus, if muted { 1.0 } else { 0.0 })
+    }
+
+    /// The volume of the mix as a whole, from 0 to 1.
+    pub fn master_volume(&self) -> Result<f32, Error> {
+        self.get(core_audio::kMultiChannelMixerParam_Volume, core_audio::kAudioUnitScope_Output, 0)
+    }
+
+    pub fn set_master_volume(&mut self, volume: f32) -> Result<(), Error> {
+        self.set(core_audio::kMultiChannelMixerParam_Volume, core_audio::kAudioUnitScope_Output, 0, volume)
+    }
+
+    /// The mixer's unit, e.g. for scheduling ramps of its parameters.
+    pub fn audio_unit(&self) -> &AudioUnit<'g, B> {
+        &self.unit
+    }
+
+    fn get(&self, parameter: core_audio::AudioUnitParameterID, scope: core_audio::AudioUnitScope,
+           bus: u32) -> Result<f32, Error> {
+        self.unit.get_parameter(parameter, scope, bus)
+    }
+
+    fn set(&self, parameter: core_audio::AudioUnitParameterID, scope: core_audio::AudioUnitScope,
+           bus: u32, value: f32) -> Result<(), Error> {
+        self.unit.set_parameter(parameter, scope, bus, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use audio_file::AudioFile;
+    use backend::{AudioBackend, FileRegion, ParameterEvent, PortableBackend};
+    use backend::portable::NULL_OUTPUT_SUB_TYPE;
+    use backend::portable::test_files;
+    use core_audio;
+    use error::{AudioUnitError, Error};
+    use graph::{Created, Graph};
+
+    /// a mono 16 bit 44.1kHz WAVE file of `frames` frames all holding `sample`
+    fn wav(name: &str, sample: i16, frames: u32) -> AudioFile<PortableBackend> {
+        test_files::open(name, &test_files::wav(44100, 1, None, frames, |_, _| sample))
+    }
+
+    #[test]
+    fn plays_files_at_once_at_their_own_levels() {
+        let quiet = wav("quiet.wav", 8192, 600);
+        let loud = wav("loud.wav", 16384, 300);
+        let files = [&quiet, &loud];
+
+        let mut graph = Graph::<Created, PortableBackend>::new().unwrap();
+        let output_node = graph.add_node(core_audio::kAudioUnitType_Output, NULL_OUTPUT_SUB_TYPE,
+                                         core_audio::kAudioUnitManufacturer_Apple).unwrap();
+        let mixer_node = graph.add_mixer().unwrap();
+        let mut player_nodes = Vec::new();
+        for _ in 0..2 {
+            player_nodes.push(graph.add_node(core_audio::kAudioUnitType_Generator,
+                                             core_audio::kAudioUnitSubType_AudioFilePlayer,
+                                             core_audio::kAudioUnitManufacturer_Apple).unwrap());
+        }
+        let mut graph = graph.open().unwrap();
+
+        graph.mixer(mixer_node).unwrap().set_input_count(3).unwrap();
+        for (bus, (&node, &file)) in player_nodes.iter().zip(files.iter()).enumerate() {
+            graph.node_info(node).unwrap().set_number_of_channels(core_audio::kAudioUnitScope_Output, 0, 1).unwrap();
+            graph.set_scheduled_file_ids(node, core_audio::kAudioUnitScope_Global, 0, &[file]).unwrap();
+            let frames = file.frame_count().unwrap() as u32;
+            let region = FileRegion { sample_time: 0.0, start_frame: 0, frames_to_play: frames, loop_count: 0 };
+            graph.set_scheduled_file_region(node, file, &region, None).unwrap();
+            PortableBackend::audio_unit_set_schedule_start_time_stamp(&graph.unit(node).unwrap(), -1.0).unwrap();
+            graph.connect_node_input(node, 0, mixer_node, bus as u32).unwrap();
+        }
+        graph.connect_node_input(mixer_node, 0, output_node, 0).unwrap();
+
+        // the mixer borrows the graph, so it's taken once the graph is connected up
+        let mut mixer = graph.mixer(mixer_node).unwrap();
+        // one hard left at full volume, the other hard right at a quarter
+        mixer.set_pan(0, -1.0).unwrap();
+        mixer.set_pan(1, 1.0).unwrap();
+        mixer.set_volume(1, 0.25).unwrap();
+        mixer.set_master_volume(0.5).unwrap();
+        assert_eq!(mixer.volume(1).unwrap(), 0.25);
+        assert!(mixer.is_enabled(2).unwrap());
+
+        let graph = graph.initialize().unwrap().start().unwrap();
+        graph.wait_until_finished().unwrap();
+        let samples = graph.node_info(output_node).unwrap().rendered_samples().unwrap();
+
+        assert_eq!(samples.len(), 1024 * 2);
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-6;
+        for frame in 0..1024 {
+            let left = if frame < 600 { 0.25 * 0.5 } else { 0.0 };
+            let right = if frame < 300 { 0.5 * 0.25 * 0.5 } else { 0.0 };
+            assert!(close(samples[frame * 2], left), "left {} at {}", samples[frame * 2], frame);
+            assert!(close(samples[frame * 2 + 1], right), "right {} at {}", samples[frame * 2 + 1], frame);
+        }
+    }
+
+    #[test]
+    fn mutes_without_forgetting_the_volume() {
+        let mut graph = Graph::<Created, PortableBackend>::new().unwrap();
+        let mixer_node = graph.add_mixer().unwrap();
+        let graph = graph.open().unwrap();
+        let mut mixer = graph.mixer(mixer_node).unwrap();
+
+        mixer.set_volume(3, 0.75).unwrap();
+        mixer.set_muted(3, true).unwrap();
+        assert!(mixer.is_muted(3).unwrap() && !mixer.is_muted(2).unwrap());
+        assert_eq!(mixer.volume(3).unwrap(), 0.75);
+        // while it's muted its volume can change, even by a ramp, without unmuting it
+        mixer.set_volume(3, 0.5).unwrap();
+        assert!(mixer.set_volume(3, 2.0).is_err());
+        let ramp = ParameterEvent::ramp(core_audio::kMultiChannelMixerParam_Volume, core_audio::kAudioUnitScope_Input,
+                                        3, 0, 512, 0.5, 1.0);
+        mixer.audio_unit().schedule_parameters(&[ramp]).unwrap();
+        assert!(mixer.is_muted(3).unwrap());
+
+        // the unit keeps it, so another handle on the same node sees it too
+        let mut again = graph.mixer(mixer_node).unwrap();
+        assert!(again.is_muted(3).unwrap());
+        again.set_muted(3, false).unwrap();
+        assert!(!mixer.is_muted(3).unwrap());
+        assert_eq!(mixer.volume(3).unwrap(), 0.5);
+
+        mixer.set_enabled(3, false).unwrap();
+        assert!(!mixer.is_enabled(3).unwrap());
+        assert_eq!(*mixer.set_muted(8, true).unwrap_err().root(), Error::AudioUnit(AudioUnitError::InvalidElement));
+        assert!(mixer.is_muted(8).is_err());
+    }
+}
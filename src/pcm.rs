@@ -0,0 +1,464 @@
+//! Converts samples between any linear PCM layout a `StreamFormat` describes and the native 32 bit
+//! floats the audio units work in.
+//!
+//! Integer samples are scaled so that full scale is 1.0. A signed `n` bit sample `s` becomes
+//! `s / 2^(n - 1)`, and unsigned samples are offset by half their range first. Going the other
+//! way, floats are rounded to the nearest step and clipped to the integer's range, so 1.0 becomes
+//! the largest positive sample. Float samples are converted as they are, without clipping.
+//!
+//! An `Encoder` can dither instead (see `Dither`), adding noise before rounding to integers. The
+//! noise comes from a generator seeded by the `Dither`, so converting the same samples the same
+//! way always gives the same bytes, and it carries on from one buffer to the next.
+//!
+//! Samples are laid out in `format.buffers()` buffers: one holding whole frames when the format
+//! is interleaved, otherwise one per channel.
+
+use error::{AudioConverterError, AudioError, Error};
+use stream_format::{Alignment, Endianness, SampleFormat, StreamFormat};
+
+/// The noise shaping filter, applied to the previous requantization errors, most recent first.
+/// It's the 5 tap approximation of an F-weighted curve that Audacity uses, which takes the noise
+/// down by about 16dB at low frequencies and puts it up above 10kHz at 44.1kHz.
+const SHAPING: [f64; 5] = [2.033, -2.165, 1.959, -1.590, 0.6149];
+
+/// How floats are requantized when they're converted to integer samples.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Dither {
+    /// round to the nearest step
+    None,
+    /// add triangular noise of up to a step either way before rounding, which leaves an even
+    /// hiss in place of the distortion rounding causes
+    Triangular { seed: u32 },
+    /// triangular dither with the error fed back through a filter, so that the hiss is moved up
+    /// to frequencies where it's harder to hear
+    NoiseShaped { seed: u32 },
+}
+
+/// Whether samples in `format` can be converted. That's every format a `StreamFormat` can
+/// describe, except integers of more than 32 bits or in more than 8 bytes.
+pub fn can_convert(format: &StreamFormat) -> bool {
+    format.bytes_per_sample() <= 8 &&
+        (format.sample_format() == SampleFormat::Float || format.bits_per_sample() <= 32)
+}
+
+/// How many whole frames `buffers` hold, or an error if there aren't `format.buffers()` of them.
+pub fn frames(format: &StreamFormat, buffers: &[&[u8]]) -> Result<usize, Error> {
+    try!(check(format, buffers.len()));
+    let frame_bytes = format.bytes_per_frame() as usize;
+    Ok(buffers.iter().map(|buffer| buffer.len() / frame_bytes).min().unwrap_or(0))
+}
+
+/// Converts the whole frames in `buffers` into `output`, starting at `output[channel][offset]`,
+/// and returns how many frames that was. Conversion stops at the end of the shortest output
+/// channel. Channels beyond the output's are dropped, and output channels beyond the format's
+/// are left alone.
+pub fn decode(format: &StreamFormat, buffers: &[&[u8]], output: &mut [Vec<f32>], offset: usize) -> Result<usize, Error> {
+    let room = output.iter().map(|channel| channel.len().saturating_sub(offset)).min().unwrap_or(0);
+    let frames = try!(frames(format, buffers)).min(room);
+    if frames == 0 {
+        return Ok(0);
+    }
+    let sample_bytes = format.bytes_per_sample() as usize;
+    for (channel, samples) in output.iter_mut().enumerate().take(format.channels() as usize) {
+        let (buffer, start, stride) = locate(format, channel);
+        let words = buffers[buffer][start..].chunks(stride).take(frames);
+        for (sample, word) in samples[offset..offset + frames].iter_mut().zip(words) {
+            *sample = decode_sample(format, &word[..sample_bytes]);
+        }
+    }
+    Ok(frames)
+}
+
+/// Converts `input` into the whole frames `buffers` have room for and returns how many frames
+/// that was. Conversion stops at the end of the shortest input channel. Channels the input
+/// doesn't have are filled with silence, and input channels beyond the format's are dropped.
+pub fn encode(format: &StreamFormat, input: &[Vec<f32>], buffers: &mut [&mut [u8]]) -> Result<usize, Error> {
+    try!(Encoder::new(format, Dither::None)).encode(input, buffers)
+}
+
+/// Every whole frame in `buffers`, a `Vec` per channel.
+pub fn to_float(format: &StreamFormat, buffers: &[&[u8]]) -> Result<Vec<Vec<f32>>, Error> {
+    let frames = try!(frames(format, buffers));
+    let mut output = vec![vec![0.0; frames]; format.channels() as usize];
+    try!(decode(format, buffers, &mut output, 0));
+    Ok(output)
+}
+
+/// `input` in `format`, in a `Vec` per buffer, as long as its shortest channel.
+pub fn from_float(format: &StreamFormat, input: &[Vec<f32>]) -> Result<Vec<Vec<u8>>, Error> {
+    let frames = input.iter().map(|channel| channel.len()).min().unwrap_or(0);
+    let mut buffers = vec![vec![0; frames * format.bytes_per_frame() as usize]; format.buffers() as usize];
+    {
+        let mut slices: Vec<&mut [u8]> = buffers.iter_mut().map(|buffer| &mut buffer[..]).collect();
+        try!(encode(format, input, &mut slices));
+    }
+    Ok(buffers)
+}
+
+/// Converts floats to a format buffer after buffer, carrying the dither on from one to the next
+/// as if they were one long stream.
+pub struct Encoder {
+    format: StreamFormat,
+    dither: Dither,
+    quantizers: Vec<Quantizer>,
+}
+
+impl Encoder {
+
+    /// An encoder to `format`, requantizing integers with `dither`, or
+    /// `AudioConverterError::FormatNotSupported` if `can_convert` says no. Floats aren't dithered.
+    pub fn new(format: &StreamFormat, dither: Dither) -> Result<Encoder, Error> {
+        if !can_convert(format) {
+            return Err(Error::AudioConverter(AudioConverterError::FormatNotSupported));
+        }
+        Ok(Encoder { format: *format, dither, quantizers: Encoder::quantizers(format, dither) })
+    }
+
+    pub fn format(&self) -> &StreamFormat {
+        &self.format
+    }
+
+    pub fn dither(&self) -> Dither {
+        self.dither
+    }
+
+    /// Starts the dither again from its seed.
+    pub fn reset(&mut self) {
+        self.quantizers = Encoder::quantizers(&self.format, self.dither);
+    }
+
+    /// As `encode`, carrying on from the last call.
+    pub fn encode(&mut self, input: &[Vec<f32>], buffers: &mut [&mut [u8]]) -> Result<usize, Error> {
+        let format = self.format;
+        let dither = self.dither;
+        try!(check(&format, buffers.len()));
+        let frame_bytes = format.bytes_per_frame() as usize;
+        let room = buffers.iter().map(|buffer| buffer.len() / frame_bytes).min().unwrap_or(0);
+        let frames = input.iter().map(|channel| channel.len()).min().unwrap_or(0).min(room);
+        if frames == 0 {
+            return Ok(0);
+        }
+        let sample_bytes = format.bytes_per_sample() as usize;
+        for (channel, quantizer) in self.quantizers.iter_mut().enumerate() {
+            let (buffer, start, stride) = locate(&format, channel);
+            let words = buffers[buffer][start..].chunks_mut(stride).take(frames);
+            let samples = input.get(channel);
+            for (frame, word) in words.enumerate() {
+                let sample = samples.map_or(0.0, |samples| samples[frame]);
+                encode_sample(&format, dither, sample, quantizer, &mut word[..sample_bytes]);
+            }
+        }
+        Ok(frames)
+    }
+
+    fn quantizers(format: &StreamFormat, dither: Dither) -> Vec<Quantizer> {
+        let seed = match dither {
+            Dither::None => 0,
+            Dither::Triangular { seed } | Dither::NoiseShaped { seed } => seed,
+        };
+        (0..format.channels()).map(|channel| Quantizer::new(seed, channel)).collect()
+    }
+}
+
+/// One channel's dither: its noise generator, and the errors the noise shaping feeds back.
+struct Quantizer {
+    random: u32,
+    errors: [f64; 5],
+}
+
+impl Quantizer {
+
+    fn new(seed: u32, channel: u32) -> Quantizer {
+        // different noise in each channel, so that it doesn't image in the middle
+        Quantizer { random: seed ^ channel.wrapping_mul(0x9e37_79b9), errors: [0.0; 5] }
+    }
+
+    /// uniform in [0, 1)
+    fn uniform(&mut self) -> f64 {
+        self.random = self.random.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (self.random >> 8) as f64 / (1 << 24) as f64
+    }
+
+    /// Rounds `value`, in steps, to a whole number of steps.
+    fn quantize(&mut self, dither: Dither, value: f64) -> f64 {
+        match dither {
+            Dither::None => value.round(),
+            Dither::Triangular { .. } => (value + self.uniform() - self.uniform()).round(),
+            Dither::NoiseShaped { .. } => {
+                let shaped = value - SHAPING.iter().zip(self.errors.iter()).map(|(tap, error)| tap * error).sum::<f64>();
+                let quantized = (shaped + self.uniform() - self.uniform()).round();
+                for n in (1..self.errors.len()).rev() {
+                    self.errors[n] = self.errors[n - 1];
+                }
+                self.errors[0] = quantized - shaped;
+                quantized
+            },
+        }
+    }
+}
+
+fn check(format: &StreamFormat, buffers: usize) -> Result<(), Error> {
+    if !can_convert(format) {
+        return Err(Error::AudioConverter(AudioConverterError::FormatNotSupported));
+    }
+    if buffers != format.buffers() as usize {
+        return Err(Error::Audio(AudioError::Param));
+    }
+    Ok(())
+}
+
+/// Which buffer `channel` is in, the byte its first sample starts at, and the bytes from one of
+/// its samples to the next.
+fn locate(format: &StreamFormat, channel: usize) -> (usize, usize, usize) {
+    let stride = format.bytes_per_frame() as usize;
+    if format.is_interleaved() {
+        (0, channel * format.bytes_per_sample() as usize, stride)
+    }
+    else {
+        (channel, 0, stride)
+    }
+}
+
+/// Where the significant bits of a sample start, counting from the least significant bit of the
+/// bytes holding it.
+fn shift(format: &StreamFormat) -> u32 {
+    match format.alignment() {
+        Alignment::High => format.bytes_per_sample() * 8 - format.bits_per_sample(),
+        Alignment::Packed | Alignment::Low => 0,
+    }
+}
+
+fn decode_sample(format: &StreamFormat, word: &[u8]) -> f32 {
+    let mut bits: u64 = 0;
+    for i in 0..word.len() {
+        let byte = match format.endianness() {
+            Endianness::Big    => word[i],
+            Endianness::Little => word[word.len() - 1 - i],
+        };
+        bits = (bits << 8) | byte as u64;
+    }
+    let width = format.bits_per_sample();
+    let value = match format.sample_format() {
+        SampleFormat::Float => return if width == 32 { f32::from_bits(bits as u32) } else { f64::from_bits(bits) as f32 },
+        // sign extend from the top of the sample
+        SampleFormat::SignedInteger   => ((bits >> shift(format)) << (64 - width)) as i64 >> (64 - width),
+        SampleFormat::UnsignedInteger => ((bits >> shift(format)) & mask(width)) as i64 - (1i64 << (width - 1)),
+    };
+    (value as f64 / (1u64 << (width - 1)) as f64) as f32
+}
+
+fn encode_sample(format: &StreamFormat, dither: Dither, sample: f32, quantizer: &mut Quantizer, word: &mut [u8]) {
+    let width = format.bits_per_sample();
+    let bits = match format.sample_format() {
+        SampleFormat::Float => if width == 32 { sample.to_bits() as u64 } else { (sample as f64).to_bits() },
+        SampleFormat::SignedInteger | SampleFormat::UnsignedInteger => {
+            let scale = (1u64 << (width - 1)) as f64;
+            // kept finite, so the noise shaping's errors stay small even when the sample clips
+            let value = if sample.is_nan() { 0.0 } else { (sample as f64 * scale).max(-2.0 * scale).min(2.0 * scale) };
+            let value = quantizer.quantize(dither, value).max(-scale).min(scale - 1.0);
+            let value = if format.sample_format() == SampleFormat::UnsignedInteger { value + scale } else { value };
+            ((value as i64 as u64) & mask(width)) << shift(format)
+        },
+    };
+    let length = word.len();
+    for i in 0..length {
+        let byte = (bits >> (8 * (length - 1 - i))) as u8;
+        match format.endianness() {
+            Endianness::Big    => word[i] = byte,
+            Endianness::Little => word[length - 1 - i] = byte,
+        }
+    }
+}
+
+/// The low `width` bits.
+fn mask(width: u32) -> u64 {
+    if width >= 64 { !0 } else { (1u64 << width) - 1 }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{decode, encode, from_float, to_float, Dither, Encoder};
+    use error::{AudioConverterError, AudioError, Error};
+    use stream_format::{Alignment, Endianness, SampleFormat, StreamFormat};
+
+    fn format(sample_format: SampleFormat, bits: u32) -> StreamFormat {
+        StreamFormat::new(sample_format, bits, 2, 44100.0).unwrap().with_endianness(Endianness::Little)
+    }
+
+    #[test]
+    fn reads_integers_as_fractions_of_full_scale() {
+        let cases: Vec<(StreamFormat, Vec<u8>)> = vec![
+            (format(SampleFormat::SignedInteger, 8), vec![0x80, 0x40]),
+            (format(SampleFormat::UnsignedInteger, 8), vec![0x00, 0xc0]),
+            (format(SampleFormat::SignedInteger, 16), vec![0x00, 0x80, 0x00, 0x40]),
+            (format(SampleFormat::UnsignedInteger, 16), vec![0x00, 0x00, 0x00, 0xc0]),
+            (format(SampleFormat::SignedInteger, 24), vec![0x00, 0x00, 0x80, 0x00, 0x00, 0x40]),
+            (format(SampleFormat::SignedInteger, 32), vec![0, 0, 0, 0x80, 0, 0, 0, 0x40]),
+            (format(SampleFormat::SignedInteger, 16).with_endianness(Endianness::Big), vec![0x80, 0x00, 0x40, 0x00]),
+            // 24 bits in the high or the low bytes of 4
+            (format(SampleFormat::SignedInteger, 24).with_container(4, Alignment::High).unwrap(),
+             vec![0xff, 0x00, 0x00, 0x80, 0xff, 0x00, 0x00, 0x40]),
+            (format(SampleFormat::SignedInteger, 24).with_container(4, Alignment::Low).unwrap(),
+             vec![0x00, 0x00, 0x80, 0xff, 0x00, 0x00, 0x40, 0x00]),
+            (format(SampleFormat::Float, 32), [(-1.0f32).to_bits().to_le_bytes(), 0.5f32.to_bits().to_le_bytes()].concat()),
+            (format(SampleFormat::Float, 64), [(-1.0f64).to_bits().to_le_bytes(), 0.5f64.to_bits().to_le_bytes()].concat()),
+        ];
+        for (format, bytes) in cases {
+            assert_eq!(to_float(&format, &[&bytes[..]]).unwrap(), vec![vec![-1.0], vec![0.5]], "{}", format);
+        }
+    }
+
+    #[test]
+    fn round_trips_every_layout() {
+        let samples = vec![vec![-1.0, -0.5, 0.0, 0.25], vec![0.5, 0.125, -0.25, -0.75]];
+        let mut formats = Vec::new();
+        for &sample_format in &[SampleFormat::SignedInteger, SampleFormat::UnsignedInteger] {
+            for &bits in &[8, 12, 16, 20, 24, 32] {
+                formats.push(format(sample_format, bits));
+            }
+            formats.push(format(sample_format, 24).with_container(4, Alignment::Low).unwrap());
+            formats.push(format(sample_format, 20).with_container(4, Alignment::High).unwrap());
+        }
+        formats.push(format(SampleFormat::Float, 32));
+        formats.push(format(SampleFormat::Float, 64));
+        for format in formats {
+            for &endianness in &[Endianness::Little, Endianness::Big] {
+                for &interleaved in &[true, false] {
+                    let format = format.with_endianness(endianness).interleaved(interleaved);
+                    let buffers = from_float(&format, &samples).unwrap();
+                    assert_eq!(buffers.len(), if interleaved { 1 } else { 2 });
+                    assert_eq!(buffers[0].len(), 4 * format.bytes_per_frame() as usize);
+                    let slices: Vec<&[u8]> = buffers.iter().map(|buffer| &buffer[..]).collect();
+                    assert_eq!(to_float(&format, &slices).unwrap(), samples, "{}", format);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rounds_and_clips_integers() {
+        let signed = format(SampleFormat::SignedInteger, 16);
+        let bytes = from_float(&signed, &[vec![1.0, 2.0, -2.0, 1.6 / 32768.0], vec![0.0; 4]]).unwrap();
+        let words: Vec<i16> = bytes[0].chunks(4).map(|frame| i16::from_le_bytes([frame[0], frame[1]])).collect();
+        assert_eq!(words, vec![32767, 32767, -32768, 2]);
+
+        let unsigned = format(SampleFormat::UnsignedInteger, 8);
+        assert_eq!(from_float(&unsigned, &[vec![1.0, -1.0, 0.0], vec![0.0; 3]]).unwrap()[0], vec![255, 128, 0, 128, 128, 128]);
+    }
+
+    #[test]
+    fn decodes_into_part_of_a_buffer() {
+        let format = format(SampleFormat::SignedInteger, 16);
+        let bytes = from_float(&format, &[vec![0.5, 0.25], vec![-0.5, -0.25]]).unwrap();
+        // a mono output only takes the first channel, and stops when it's full
+        let mut output = vec![vec![1.0; 3]];
+        assert_eq!(decode(&format, &[&bytes[0][..]], &mut output, 2).unwrap(), 1);
+        assert_eq!(output, vec![vec![1.0, 1.0, 0.5]]);
+
+        // missing channels are silent
+        let mut buffer = vec![0xffu8; 8];
+        assert_eq!(encode(&format, &[vec![0.5, 0.5]], &mut [&mut buffer[..]]).unwrap(), 2);
+        assert_eq!(buffer, vec![0x00, 0x40, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn rejects_the_wrong_buffers_and_wide_integers() {
+        let format = format(SampleFormat::SignedInteger, 16).interleaved(false);
+        match to_float(&format, &[&[0u8, 0][..]]) {
+            Err(Error::Audio(AudioError::Param)) => (),
+            other => panic!("{:?}", other),
+        }
+        let wide = StreamFormat::new(SampleFormat::SignedInteger, 48, 1, 44100.0).unwrap();
+        match to_float(&wide, &[&[0u8; 6][..]]) {
+            Err(Error::AudioConverter(AudioConverterError::FormatNotSupported)) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    /// mono 8 bit, where a step is big enough to see what the dither does
+    fn mono_8_bit() -> StreamFormat {
+        StreamFormat::new(SampleFormat::SignedInteger, 8, 1, 44100.0).unwrap()
+    }
+
+    /// `input` in mono 8 bit, converted by a new encoder with `dither`
+    fn dithered(dither: Dither, input: &[f32]) -> Vec<u8> {
+        let mut bytes = vec![0u8; input.len()];
+        Encoder::new(&mono_8_bit(), dither).unwrap().encode(&[input.to_vec()], &mut [&mut bytes[..]]).unwrap();
+        bytes
+    }
+
+    /// the requantization error of `input` in mono 8 bit with `dither`, in steps
+    fn errors(dither: Dither, input: &[f32]) -> Vec<f64> {
+        let bytes = dithered(dither, input);
+        let output = to_float(&mono_8_bit(), &[&bytes[..]]).unwrap();
+        output[0].iter().zip(input.iter()).map(|(&output, &input)| (output - input) as f64 * 128.0).collect()
+    }
+
+    #[test]
+    fn triangular_dither_keeps_levels_between_steps() {
+        // rounding 0.3 of a step always gives 0, dithering it gives 0.3 on average
+        let input = vec![0.3 / 128.0; 10000];
+        assert!(errors(Dither::None, &input).iter().all(|&error| (error + 0.3).abs() < 1e-6));
+        let errors = errors(Dither::Triangular { seed: 1 }, &input);
+        let mean = errors.iter().sum::<f64>() / errors.len() as f64;
+        assert!(mean.abs() < 0.02, "{}", mean);
+        assert!(errors.iter().all(|&error| error.abs() < 1.5));
+        // the noise's power is a quarter of a step squared, rounding's own twelfth plus the
+        // triangle's sixth
+        let power = errors.iter().map(|error| error * error).sum::<f64>() / errors.len() as f64;
+        assert!((power - 0.25).abs() < 0.03, "{}", power);
+    }
+
+    #[test]
+    fn noise_shaping_moves_the_noise_up() {
+        let input: Vec<f32> = (0..20000).map(|n| 0.5 * (n as f32 * 0.01).sin()).collect();
+        // the power of the error below about 1kHz, by averaging over 32 samples three times over
+        // so that the high frequencies don't leak through
+        let low_power = |mut errors: Vec<f64>| {
+            for _ in 0..3 {
+                errors = errors.windows(32).map(|window| window.iter().sum::<f64>() / 32.0).collect();
+            }
+            errors.iter().map(|error| error * error).sum::<f64>() / errors.len() as f64
+        };
+        let triangular = low_power(errors(Dither::Triangular { seed: 7 }, &input));
+        let shaped = low_power(errors(Dither::NoiseShaped { seed: 7 }, &input));
+        assert!(shaped * 10.0 < triangular, "{} {}", shaped, triangular);
+    }
+
+    #[test]
+    fn dither_is_reproducible_from_its_seed() {
+        let input = vec![(0..1000).map(|n| (n as f32 * 0.05).sin() * 0.3).collect::<Vec<f32>>()];
+        for &dither in &[Dither::Triangular { seed: 42 }, Dither::NoiseShaped { seed: 42 }] {
+            let whole = dithered(dither, &input[0]);
+            assert_eq!(dithered(dither, &input[0]), whole);
+            let other = match dither {
+                Dither::Triangular { .. } => Dither::Triangular { seed: 43 },
+                _ => Dither::NoiseShaped { seed: 43 },
+            };
+            assert!(dithered(other, &input[0]) != whole);
+
+            // an encoder carries on from one buffer to the next, and starts again when reset
+            let mut encoder = Encoder::new(&mono_8_bit(), dither).unwrap();
+            let mut pieces = vec![0u8; 1000];
+            {
+                let (first, second) = pieces.split_at_mut(400);
+                assert_eq!(encoder.encode(&[input[0][..400].to_vec()], &mut [first]).unwrap(), 400);
+                assert_eq!(encoder.encode(&[input[0][400..].to_vec()], &mut [second]).unwrap(), 600);
+            }
+            assert_eq!(pieces, whole);
+            encoder.reset();
+            encoder.encode(&input, &mut [&mut pieces[..]]).unwrap();
+            assert_eq!(pieces, whole);
+        }
+    }
+
+    #[test]
+    fn floats_are_not_dithered() {
+        let format = StreamFormat::new(SampleFormat::Float, 32, 1, 44100.0).unwrap();
+        let input = vec![vec![0.1, -0.2, 0.3]];
+        let mut bytes = [0u8; 12];
+        Encoder::new(&format, Dither::Triangular { seed: 1 }).unwrap().encode(&input, &mut [&mut bytes[..]]).unwrap();
+        assert_eq!(to_float(&format, &[&bytes[..]]).unwrap(), input);
+    }
+}
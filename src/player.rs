@@ -0,0 +1,551 @@
+//! Plays a file through a file player and an output, the way the PlayFile sample does, with
+//! transport controls and notification of when it has finished instead of leaving the caller to
+//! sleep for the file's duration.
+
+use audio_file::{duration_to_frames, frames_to_duration, AudioFile};
+use audio_unit::AudioUnit;
+use region::Region;
+use backend::{AudioBackend, DefaultBackend, FileRegion};
+use core_audio;
+use error::Error;
+use property;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+use transport::Transport;
+
+/// A file player feeding an output, playing a region of `file`, by default all of it.
+///
+/// Pausing, stopping and seeking all stop the graph and reset the file player, throwing away the
+/// scheduled regions; playing again schedules whatever of the region is left to play.
+pub struct Player<'a, B: AudioBackend = DefaultBackend> {
+    file: &'a AudioFile<B>,
+    sample_rate: f64,
+    transport: Transport<'a, B>,
+    /// the region to play, resolved against the file
+    region: FileRegion,
+    /// how far through the region, counting every loop, playing started from while playing,
+    /// otherwise where it will start from
+    offset: u64,
+    /// how long the file player waits before playing from `offset`
+    delay: f64,
+    paused: bool,
+    /// where the scheduled regions end, in the file player's play time
+    end_time: Option<f64>,
+}
+
+impl<'a, B: AudioBackend> Player<'a, B> {
+
+    /// A player for `file` that plays through the default output.
+    pub fn new(file: &'a AudioFile<B>) -> Result<Player<'a, B>, Error> {
+        Player::with_output(file, core_audio::kAudioUnitSubType_DefaultOutput)
+    }
+
+    /// A player for `file` that plays through the kAudioUnitType_Output unit with the given sub
+    /// type, e.g. the portable backend's null output.
+    pub fn with_output(file: &'a AudioFile<B>, output_sub_type: u32) -> Result<Player<'a, B>, Error> {
+        let file_format = try!(file.data_format());
+        let region = try!(Region::new().file_region(file));
+        let transport = try!(Transport::new(output_sub_type, file_format.mSampleRate, file_format.mChannelsPerFrame));
+
+        try!(transport.set_scheduled_file_ids(&[file]));
+
+        // apparently, workaround a race condition in the file player AU
+        thread::sleep(Duration::from_millis(10));
+
+        // as in the apple c++ sample, tell the output which speaker each channel of a surround
+        // file is for, so that it can put them in the right places
+        if file_format.mChannelsPerFrame > 2 {
+            if let Some(layout) = try!(file.channel_layout()) {
+                try!(transport.output().set::<property::AudioChannelLayout>(core_audio::kAudioUnitScope_Input, 0,
+                                                                            &layout));
+            }
+        }
+
+        Ok(Player {
+            file,
+            sample_rate: file_format.mSampleRate,
+            transport,
+            region,
+            offset: 0,
+            delay: 0.0,
+            paused: false,
+            end_time: None,
+        })
+    }
+
+    /// Plays `region` of the file from now on. Stops the player if it's playing.
+    pub fn set_region(&mut self, region: &Region) -> Result<(), Error> {
+        let region = try!(region.file_region(self.file));
+        try!(self.stop());
+        self.region = region;
+        Ok(())
+    }
+
+    /// Calls `callback` each time the file finishes playing, with the error if it couldn't be
+    /// played. It's called on the audio system's thread, so it mustn't block or use the player.
+    pub fn on_completion<F>(&mut self, callback: F) -> Result<(), Error>
+        where F: FnMut(Result<(), Error>) + Send + 'static {
+        let mut callback = callback;
+        self.transport.on_finished(Box::new(move |_, result| callback(result)))
+    }
+
+    /// A channel that receives the result each time the file finishes playing.
+    pub fn completion_channel(&mut self) -> Result<Receiver<Result<(), Error>>, Error> {
+        let (sender, receiver) = mpsc::channel();
+        try!(self.on_completion(move |result| {
+            // nobody listening any more is fine
+            let _ = sender.send(result);
+        }));
+        Ok(receiver)
+    }
+
+    /// Starts playing from the current position, which is the start of the region unless it has
+    /// been paused or sought. Does nothing if it's already playing; once the region has finished,
+    /// plays it again from the start.
+    pub fn play(&mut self) -> Result<(), Error> {
+        if self.is_playing() {
+            if try!(self.transport.finished()) == 0 {
+                return Ok(());
+            }
+            try!(self.halt());
+            self.offset = 0;
+        }
+        let offset = self.offset;
+        self.start_from(offset)
+    }
+
+    /// Stops playing, keeping the position so that `resume` carries on from there.
+    pub fn pause(&mut self) -> Result<(), Error> {
+        if !self.is_playing() {
+            return Ok(());
+        }
+        let offset = try!(self.played());
+        try!(self.halt());
+        self.offset = offset;
+        self.paused = true;
+        Ok(())
+    }
+
+    /// Carries on from where `pause` left off. Does nothing unless paused.
+    pub fn resume(&mut self) -> Result<(), Error> {
+        if !self.paused {
+            return Ok(());
+        }
+        let offset = self.offset;
+        self.start_from(offset)
+    }
+
+    /// Stops playing and goes back to the start of the region.
+    pub fn stop(&mut self) -> Result<(), Error> {
+        try!(self.halt());
+        self.offset = 0;
+        self.paused = false;
+        Ok(())
+    }
+
+    /// Moves to `position` into the file, kept within the region. Playing carries on from there
+    /// straight away, otherwise it's where playing will start. Seeking a looping region starts
+    /// its loops over.
+    pub fn seek(&mut self, position: Duration) -> Result<(), Error> {
+        let start = self.region.start_frame as u64;
+        let length = self.region.frames_to_play as u64;
+        let frame = duration_to_frames(position, self.sample_rate);
+        let offset = frame.max(start).min(start + length) - start;
+        if self.is_playing() {
+            try!(self.halt());
+            self.start_from(offset)
+        }
+        else {
+            self.offset = offset;
+            Ok(())
+        }
+    }
+
+    /// Where in the file playing has got.
+    pub fn position(&self) -> Result<Duration, Error> {
+        let start = self.region.start_frame as u64;
+        let length = self.region.frames_to_play as u64;
+        let played = try!(self.played());
+        let frame = match self.total() {
+            Some(total) if played >= total => start + length,
+            _ if length == 0 => start,
+            _ => start + played % length,
+        };
+        Ok(frames_to_duration(frame, self.sample_rate))
+    }
+
+    /// Whether the graph is running, which it carries on doing after the region has finished
+    /// until the player is paused or stopped.
+    pub fn is_playing(&self) -> bool {
+        self.transport.is_playing()
+    }
+
+    /// Blocks until the region has finished playing, returning straight away if it isn't
+    /// playing. A region looping forever never finishes, so this doesn't return for one.
+    pub fn wait(&self) -> Result<(), Error> {
+        self.transport.wait(1, self.end_time)
+    }
+
+    /// The file player unit.
+    pub fn file_player<'p>(&'p self) -> AudioUnit<'p, B> {
+        self.transport.file_player()
+    }
+
+    /// The output unit.
+    pub fn output<'p>(&'p self) -> AudioUnit<'p, B> {
+        self.transport.output()
+    }
+
+    /// How many frames of the region, counting every loop, a region that loops a finite number
+    /// of times plays.
+    fn total(&self) -> Option<u64> {
+        if self.region.loop_count == u32::MAX {
+            None
+        }
+        else {
+            Some(self.region.frames_to_play as u64 * (self.region.loop_count as u64 + 1))
+        }
+    }
+
+    /// How far through the region playing has got, counting every loop, from
+    /// kAudioUnitProperty_CurrentPlayTime.
+    fn played(&self) -> Result<u64, Error> {
+        if !self.is_playing() {
+            return Ok(self.offset);
+        }
+        // -1 until the first render cycle
+        let play_time = try!(self.transport.play_time());
+        let played = self.offset + (play_time - self.delay).max(0.0) as u64;
+        Ok(self.total().map_or(played, |total| played.min(total)))
+    }
+
+    /// Schedules what's left of the region from `offset` on and starts the graph. Part way
+    /// through a loop, that's the rest of the loop followed by the loops still to come.
+    fn start_from(&mut self, offset: u64) -> Result<(), Error> {
+        let region = self.region;
+        let length = region.frames_to_play as u64;
+        let total = self.total();
+        let offset = total.map_or(offset, |total| offset.min(total));
+        // the region's own start time only applies when it's played from the start
+        let delay = if offset == 0 { region.sample_time } else { 0.0 };
+
+        let mut regions = Vec::new();
+        if length == 0 || Some(offset) == total {
+            // nothing left to play, but the completion still wants calling
+            regions.push(FileRegion { sample_time: delay, start_frame: region.start_frame + length as i64,
+                                      frames_to_play: 0, loop_count: 0 });
+        }
+        else {
+            let (loops_done, into_loop) = (offset / length, offset % length);
+            let loops_left = |loops_done: u64| if total.is_none() {
+                u32::MAX
+            }
+            else {
+                (region.loop_count as u64 - loops_done) as u32
+            };
+            if into_loop == 0 {
+                regions.push(FileRegion { sample_time: delay, loop_count: loops_left(loops_done), ..region });
+            }
+            else {
+                regions.push(FileRegion { sample_time: 0.0, start_frame: region.start_frame + into_loop as i64,
+                                          frames_to_play: (length - into_loop) as u32, loop_count: 0 });
+                if total.is_none_or(|total| (loops_done + 1) * length < total) {
+                    regions.push(FileRegion { sample_time: (length - into_loop) as f64,
+                                              loop_count: loops_left(loops_done + 1), ..region });
+                }
+            }
+        }
+
+        try!(self.transport.reset_completions());
+        let last = regions.len() - 1;
+        for (i, file_region) in regions.iter().enumerate() {
+            // only the region that ends last says when playing has finished
+            let completion = if i == last { Some(try!(self.transport.completion(0))) } else { None };
+            try!(self.transport.set_scheduled_file_region(self.file, file_region, completion));
+        }
+        try!(self.transport.start());
+        self.offset = offset;
+        self.delay = delay;
+        self.paused = false;
+        self.end_time = total.map(|total| delay + (total - offset) as f64);
+        Ok(())
+    }
+
+    /// Stops the graph if it's running and throws away whatever is scheduled on the file player.
+    fn halt(&mut self) -> Result<(), Error> {
+        self.end_time = None;
+        self.transport.halt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::Player;
+    use audio_file::{frames_to_duration, AudioFile};
+    use region::Region;
+    use backend::PortableBackend;
+    use backend::portable::NULL_OUTPUT_SUB_TYPE;
+    use backend::portable::test_files;
+    use channel_matrix::ChannelMatrix;
+    use core_audio;
+    use property;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    /// a mono 16 bit 44.1kHz WAVE file whose frame `n` is `sample(n)`
+    fn open<F: Fn(u32) -> i16>(name: &str, frames: u32, sample: F) -> AudioFile<PortableBackend> {
+        test_files::open(name, &test_files::wav(44100, 1, None, frames, |n, _| sample(n)))
+    }
+
+    fn half_scale(_: u32) -> i16 {
+        0x4000
+    }
+
+    fn ramp(n: u32) -> i16 {
+        n as i16
+    }
+
+    #[test]
+    fn wait_returns_once_the_file_has_played() {
+        let file = open("wait.wav", 3000, half_scale);
+        let mut player = Player::with_output(&file, NULL_OUTPUT_SUB_TYPE).unwrap();
+        player.play().unwrap();
+        player.wait().unwrap();
+        let rendered = player.output().rendered_samples().unwrap();
+        assert!(rendered.len() >= 3000);
+        assert!(rendered[..3000].iter().all(|&sample| sample == 0.5));
+        assert_eq!(player.position().unwrap(), file.duration().unwrap());
+    }
+
+    #[test]
+    fn completion_fires_callbacks_and_channels() {
+        let file = open("completion.wav", 1000, half_scale);
+        let mut player = Player::with_output(&file, NULL_OUTPUT_SUB_TYPE).unwrap();
+        let calls = Arc::new(Mutex::new(0));
+        let counted = calls.clone();
+        player.on_completion(move |result| {
+            assert!(result.is_ok());
+            *counted.lock().unwrap() += 1;
+        }).unwrap();
+        let receiver = player.completion_channel().unwrap();
+
+        player.play().unwrap();
+        assert!(receiver.recv().unwrap().is_ok());
+        player.wait().unwrap();
+        assert_eq!(*calls.lock().unwrap(), 1);
+
+        // playing a finished file plays it again
+        player.play().unwrap();
+        assert!(receiver.recv().unwrap().is_ok());
+        player.wait().unwrap();
+        assert_eq!(*calls.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn wait_without_playing_returns_straight_away() {
+        let file = open("unplayed.wav", 10, half_scale);
+        let player = Player::with_output(&file, NULL_OUTPUT_SUB_TYPE).unwrap();
+        player.wait().unwrap();
+    }
+
+    #[test]
+    fn seeking_before_playing_starts_from_there() {
+        let file = open("seek.wav", 44100, ramp);
+        let mut player = Player::with_output(&file, NULL_OUTPUT_SUB_TYPE).unwrap();
+        player.seek(Duration::from_millis(500)).unwrap();
+        assert_eq!(player.position().unwrap(), Duration::from_millis(500));
+        player.play().unwrap();
+        player.wait().unwrap();
+        let rendered = player.output().rendered_samples().unwrap();
+        assert_eq!(rendered[0], ramp(22050) as f32 / 32768.0);
+        assert_eq!(rendered[22049], ramp(44099) as f32 / 32768.0);
+
+        // past the end is the end
+        player.stop().unwrap();
+        player.seek(Duration::from_secs(5)).unwrap();
+        assert_eq!(player.position().unwrap(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn pause_resume_and_stop_move_the_position() {
+        // the null output renders as fast as it can, so only the order of things is certain, not
+        // how far it gets in between
+        let file = open("transport.wav", 44100, half_scale);
+        let mut player = Player::with_output(&file, NULL_OUTPUT_SUB_TYPE).unwrap();
+        player.play().unwrap();
+        player.pause().unwrap();
+        assert!(!player.is_playing());
+        let paused_at = player.position().unwrap();
+        assert!(paused_at <= Duration::from_secs(1));
+        assert_eq!(player.position().unwrap(), paused_at);
+
+        player.resume().unwrap();
+        assert!(player.is_playing());
+        assert!(player.position().unwrap() >= paused_at);
+        player.wait().unwrap();
+        assert_eq!(player.position().unwrap(), Duration::from_secs(1));
+
+        // sought while paused, it carries on from there
+        player.pause().unwrap();
+        player.seek(Duration::from_millis(900)).unwrap();
+        assert_eq!(player.position().unwrap(), Duration::from_millis(900));
+        player.resume().unwrap();
+        assert!(player.position().unwrap() >= Duration::from_millis(900));
+        player.wait().unwrap();
+        assert_eq!(player.position().unwrap(), Duration::from_secs(1));
+
+        player.stop().unwrap();
+        assert!(!player.is_playing());
+        assert_eq!(player.position().unwrap(), Duration::from_millis(0));
+        // nothing to wait for once stopped
+        player.wait().unwrap();
+    }
+
+    fn frame_time(frame: u64) -> Duration {
+        frames_to_duration(frame, 44100.0)
+    }
+
+    /// a 16 bit 44.1kHz WAVE_FORMAT_EXTENSIBLE file with `channels` channels for the
+    /// speakers in `mask`, channel n holding n + 1 sixteenths of full scale
+    fn open_surround(name: &str, mask: u32, channels: u16, frames: u32) -> AudioFile<PortableBackend> {
+        test_files::open(name, &test_files::wav(44100, channels, Some(mask), frames,
+                                                |_, channel| ((channel + 1) * 0x800) as i16))
+    }
+
+    fn ramp_values(frames: &[u32]) -> Vec<f32> {
+        frames.iter().map(|&n| ramp(n) as f32 / 32768.0).collect()
+    }
+
+    #[test]
+    fn plays_a_looping_excerpt() {
+        let file = open("loop.wav", 1000, ramp);
+        let mut player = Player::with_output(&file, NULL_OUTPUT_SUB_TYPE).unwrap();
+        player.set_region(&Region::new().start_frame(100).frames(10).loop_count(2).at_frame(5)).unwrap();
+        player.play().unwrap();
+        player.wait().unwrap();
+
+        let rendered = player.output().rendered_samples().unwrap();
+        let mut expected = vec![0.0; 5];
+        for _ in 0..3 {
+            expected.extend(ramp_values(&(100..110).collect::<Vec<_>>()));
+        }
+        assert_eq!(&rendered[..35], &expected[..]);
+        assert!(rendered[35..].iter().all(|&sample| sample == 0.0));
+        assert_eq!(player.position().unwrap(), frame_time(110));
+    }
+
+    #[test]
+    fn seeking_into_a_loop_plays_the_rest_then_the_loops() {
+        let file = open("seek-loop.wav", 1000, ramp);
+        let mut player = Player::with_output(&file, NULL_OUTPUT_SUB_TYPE).unwrap();
+        player.set_region(&Region::new().start_frame(100).frames(10).loop_count(1)).unwrap();
+        // frame 105
+        player.seek(frame_time(105)).unwrap();
+        player.play().unwrap();
+        player.wait().unwrap();
+
+        let rendered = player.output().rendered_samples().unwrap();
+        let mut expected = ramp_values(&(105..110).collect::<Vec<_>>());
+        expected.extend(ramp_values(&(100..110).collect::<Vec<_>>()));
+        assert_eq!(&rendered[..15], &expected[..]);
+        assert!(rendered[15..].iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn a_region_looping_forever_keeps_playing() {
+        let file = open("forever.wav", 1000, ramp);
+        let mut player = Player::new(&file).unwrap();
+        player.set_region(&Region::new().start_frame(500).frames(100).loop_forever()).unwrap();
+        player.play().unwrap();
+        thread::sleep(Duration::from_millis(50));
+        // 50ms is over 20 loops
+        let position = player.position().unwrap();
+        assert!(position >= frame_time(500));
+        assert!(position < frame_time(600));
+        assert!(player.is_playing());
+        player.stop().unwrap();
+    }
+
+    #[test]
+    fn plays_surround_channels_through_the_matching_speakers() {
+        // 7.1 as WAVE orders it, L R C LFE BL BR SL SR
+        let frames = 100u32;
+        let file = open_surround("surround.wav", 0x63f, 8, frames);
+
+        let mut player = Player::with_output(&file, NULL_OUTPUT_SUB_TYPE).unwrap();
+        let layout = player.output().get::<property::AudioChannelLayout>(core_audio::kAudioUnitScope_Input, 0);
+        assert_eq!(layout.unwrap(), file.channel_layout().unwrap().unwrap());
+        player.play().unwrap();
+        player.wait().unwrap();
+
+        // the device is in the usual 7.1 order, L R C LFE Ls Rs Rls Rrs, so the side pair the
+        // file has last comes before its back pair
+        let rendered = player.output().rendered_samples().unwrap();
+        let sixteenths = [1.0, 2.0, 3.0, 4.0, 7.0, 8.0, 5.0, 6.0];
+        for frame in rendered[..frames as usize * 8].chunks(8) {
+            let expected: Vec<f32> = sixteenths.iter().map(|sixteenth| sixteenth / 16.0).collect();
+            assert_eq!(frame, &expected[..]);
+        }
+    }
+
+    #[test]
+    fn downmixes_surround_for_a_stereo_device() {
+        // L R C LFE Ls Rs
+        let frames = 100u32;
+        let file = open_surround("downmix.wav", 0x3f, 6, frames);
+        let mut player = Player::with_output(&file, NULL_OUTPUT_SUB_TYPE).unwrap();
+        player.output().set_number_of_channels(core_audio::kAudioUnitScope_Output, 0, 2).unwrap();
+        player.play().unwrap();
+        player.wait().unwrap();
+
+        // the centre and surrounds go in at -3dB and the LFE is left out
+        let half = ::std::f32::consts::FRAC_1_SQRT_2;
+        let left = (1.0 + half * 3.0 + half * 5.0) / 16.0;
+        let right = (2.0 + half * 3.0 + half * 6.0) / 16.0;
+        let rendered = player.output().rendered_samples().unwrap();
+        for frame in rendered[..frames as usize * 2].chunks(2) {
+            assert!((frame[0] - left).abs() < 1e-6 && (frame[1] - right).abs() < 1e-6, "{:?}", frame);
+        }
+    }
+
+    #[test]
+    fn resamples_for_a_device_at_another_rate() {
+        let sine = |n: u32| (0.5 * 32768.0 * (2.0 * ::std::f64::consts::PI * 1000.0 * n as f64 / 44100.0).sin()) as i16;
+        let file = open("resample.wav", 4410, sine);
+        let mut player = Player::with_output(&file, NULL_OUTPUT_SUB_TYPE).unwrap();
+        player.output().set_sample_rate(core_audio::kAudioUnitScope_Output, 0, 48000.0).unwrap();
+        player.play().unwrap();
+        player.wait().unwrap();
+        assert_eq!(player.position().unwrap(), file.duration().unwrap());
+
+        // the same tenth of a second of 1kHz, at 48kHz
+        let rendered = player.output().rendered_samples().unwrap();
+        assert!(rendered.len() >= 4800);
+        for (n, &sample) in rendered.iter().enumerate().take(4700).skip(100) {
+            let expected = 0.5 * (2.0 * ::std::f64::consts::PI * 1000.0 * n as f64 / 48000.0).sin();
+            assert!((sample as f64 - expected).abs() < 1e-3, "{} at {}, not {}", sample, n, expected);
+        }
+    }
+
+    #[test]
+    fn mixes_with_a_matrix_given_by_hand() {
+        let frames = 100u32;
+        let file = open_surround("matrix.wav", 0x3, 2, frames);
+        let mut player = Player::with_output(&file, NULL_OUTPUT_SUB_TYPE).unwrap();
+        // swapped, and the left at half
+        let matrix = ChannelMatrix::from_gains(vec![vec![0.0, 1.0], vec![0.5, 0.0]]).unwrap();
+        player.output().set_channel_matrix(Some(matrix)).unwrap();
+        let wrong_size = ChannelMatrix::identity(3);
+        assert!(player.output().set_channel_matrix(Some(wrong_size)).is_err());
+        player.play().unwrap();
+        player.wait().unwrap();
+
+        let rendered = player.output().rendered_samples().unwrap();
+        for frame in rendered[..frames as usize * 2].chunks(2) {
+            assert_eq!(frame, &[2.0 / 16.0, 0.5 / 16.0]);
+        }
+    }
+}
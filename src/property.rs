@@ -0,0 +1,198 @@
+//! Typed audio unit properties.
+//!
+//! AudioUnitGetProperty and AudioUnitSetProperty deal in untyped buffers and byte counts. A
+//! `Property` pairs a property ID with the type of its value, so that `AudioUnit::get` and
+//! `AudioUnit::set` can do the conversion and check the size. Adding a property is a unit struct
+//! and an unsafe impl naming the two.
+
+use channel_layout::ChannelLayout;
+use core_audio;
+use error::{AudioUnitError, Error};
+use std::mem;
+use std::ptr;
+
+/// An audio unit property, `ID` being its kAudioUnitProperty_ constant.
+///
+/// # Safety
+///
+/// A unit trusts whatever it's handed for `ID`, so any `Value` has to be safe to set. Properties
+/// whose values carry pointers or handles, e.g. kAudioUnitProperty_ScheduledFileIDs and
+/// kAudioUnitProperty_ScheduledFileRegion with their file IDs and completion procs, can't be
+/// `Property`s; `Graph::set_scheduled_file_ids` and `Graph::set_scheduled_file_region` set those.
+pub unsafe trait Property {
+    const ID: core_audio::AudioUnitPropertyID;
+    type Value: PropertyValue;
+}
+
+/// A type properties can have, and how it is laid out in the buffer the property calls take.
+pub trait PropertyValue: Sized {
+
+    /// Reads a value out of what a unit handed back, failing if it's the wrong size.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Error>;
+
+    /// The buffer to hand a unit.
+    fn to_bytes(&self) -> Vec<u8>;
+}
+
+/// Types that are their own buffer.
+///
+/// # Safety
+///
+/// Implementors have to be `repr(C)` (or primitives), not zero sized, and fine to copy out of
+/// whatever bytes a unit hands back for them.
+pub unsafe trait Plain: Copy {}
+
+unsafe impl Plain for u32 {}
+unsafe impl Plain for i32 {}
+unsafe impl Plain for f32 {}
+unsafe impl Plain for f64 {}
+unsafe impl Plain for core_audio::AudioStreamBasicDescription {}
+unsafe impl Plain for core_audio::AudioTimeStamp {}
+#[cfg(target_os = "macos")]
+unsafe impl Plain for core_audio::AudioFileID {}
+#[cfg(target_os = "macos")]
+unsafe impl Plain for core_audio::ScheduledAudioFileRegion {}
+
+impl<T: Plain> PropertyValue for T {
+
+    fn from_bytes(bytes: &[u8]) -> Result<T, Error> {
+        if bytes.len() != mem::size_of::<T>() {
+            return Err(Error::AudioUnit(AudioUnitError::InvalidPropertyValue));
+        }
+        Ok(unsafe { ptr::read_unaligned(bytes.as_ptr() as *const T) })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; mem::size_of::<T>()];
+        unsafe { ptr::copy_nonoverlapping(self as *const T as *const u8, bytes.as_mut_ptr(), bytes.len()); }
+        bytes
+    }
+}
+
+/// Variable size properties: as many values as the buffer holds.
+impl<T: Plain> PropertyValue for Vec<T> {
+
+    fn from_bytes(bytes: &[u8]) -> Result<Vec<T>, Error> {
+        let size = mem::size_of::<T>();
+        if !bytes.len().is_multiple_of(size) {
+            return Err(Error::AudioUnit(AudioUnitError::InvalidPropertyValue));
+        }
+        bytes.chunks(size).map(T::from_bytes).collect()
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.iter().flat_map(|value| value.to_bytes()).collect()
+    }
+}
+
+/// kAudioUnitProperty_StreamFormat, the format of a bus. `AudioBackend::get_stream_format` checks it.
+pub struct StreamFormat;
+
+unsafe impl Property for StreamFormat {
+    const ID: core_audio::AudioUnitPropertyID = core_audio::kAudioUnitProperty_StreamFormat;
+    type Value = core_audio::AudioStreamBasicDescription;
+}
+
+/// kAudioUnitProperty_ElementCount, how many busses a scope has.
+pub struct ElementCount;
+
+unsafe impl Property for ElementCount {
+    const ID: core_audio::AudioUnitPropertyID = core_audio::kAudioUnitProperty_ElementCount;
+    type Value = u32;
+}
+
+/// kAudioUnitProperty_Latency, in seconds, global scope.
+pub struct Latency;
+
+unsafe impl Property for Latency {
+    const ID: core_audio::AudioUnitPropertyID = core_audio::kAudioUnitProperty_Latency;
+    type Value = f64;
+}
+
+/// kAudioUnitProperty_MaximumFramesPerSlice, global scope.
+pub struct MaximumFramesPerSlice;
+
+unsafe impl Property for MaximumFramesPerSlice {
+    const ID: core_audio::AudioUnitPropertyID = core_audio::kAudioUnitProperty_MaximumFramesPerSlice;
+    type Value = u32;
+}
+
+/// kAudioUnitProperty_RenderQuality, global scope: how much work a unit that resamples puts in,
+/// from 0 (kRenderQuality_Min) to 0x7F (kRenderQuality_Max).
+pub struct RenderQuality;
+
+unsafe impl Property for RenderQuality {
+    const ID: core_audio::AudioUnitPropertyID = core_audio::kAudioUnitProperty_RenderQuality;
+    type Value = u32;
+}
+
+/// kAudioUnitProperty_AudioChannelLayout, which speaker each channel of a bus is for. Setting it
+/// on an output unit's input has it route the channels to the matching speakers.
+pub struct AudioChannelLayout;
+
+unsafe impl Property for AudioChannelLayout {
+    const ID: core_audio::AudioUnitPropertyID = core_audio::kAudioUnitProperty_AudioChannelLayout;
+    type Value = ChannelLayout;
+}
+
+/// kAudioUnitProperty_ScheduleStartTimeStamp, when a file player starts, in its render time stamps.
+pub struct ScheduleStartTimeStamp;
+
+unsafe impl Property for ScheduleStartTimeStamp {
+    const ID: core_audio::AudioUnitPropertyID = core_audio::kAudioUnitProperty_ScheduleStartTimeStamp;
+    type Value = core_audio::AudioTimeStamp;
+}
+
+/// kAudioUnitProperty_ScheduledFilePrime, how many frames to read ahead, 0 for the default.
+pub struct ScheduledFilePrime;
+
+unsafe impl Property for ScheduledFilePrime {
+    const ID: core_audio::AudioUnitPropertyID = core_audio::kAudioUnitProperty_ScheduledFilePrime;
+    type Value = u32;
+}
+
+/// kAudioUnitProperty_CurrentPlayTime, how far a file player has got since its start time stamp.
+pub struct CurrentPlayTime;
+
+unsafe impl Property for CurrentPlayTime {
+    const ID: core_audio::AudioUnitPropertyID = core_audio::kAudioUnitProperty_CurrentPlayTime;
+    type Value = core_audio::AudioTimeStamp;
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::PropertyValue;
+    use core_audio;
+    use error::{AudioUnitError, Error};
+
+    #[test]
+    fn values_round_trip_through_bytes() {
+        let time_stamp = core_audio::AudioTimeStamp {
+            mSampleTime: 1234.0,
+            mFlags: core_audio::kAudioTimeStampSampleTimeValid,
+            ..Default::default()
+        };
+        let bytes = time_stamp.to_bytes();
+        assert_eq!(bytes.len(), ::std::mem::size_of::<core_audio::AudioTimeStamp>());
+        let read = core_audio::AudioTimeStamp::from_bytes(&bytes).unwrap();
+        assert_eq!((read.mSampleTime, read.mFlags), (1234.0, core_audio::kAudioTimeStampSampleTimeValid));
+
+        let values: Vec<u32> = vec![1, 2, 0xdeadbeef];
+        assert_eq!(values.to_bytes().len(), 12);
+        assert_eq!(Vec::<u32>::from_bytes(&values.to_bytes()).unwrap(), values);
+        assert_eq!(Vec::<u32>::from_bytes(&[]).unwrap(), Vec::<u32>::new());
+        // a buffer that isn't aligned for the type
+        let mut unaligned = vec![0u8];
+        unaligned.extend(1.5f64.to_bytes());
+        assert_eq!(f64::from_bytes(&unaligned[1..]).unwrap(), 1.5);
+    }
+
+    #[test]
+    fn sizes_have_to_match() {
+        let invalid = Error::AudioUnit(AudioUnitError::InvalidPropertyValue);
+        assert_eq!(u32::from_bytes(&[0, 0, 0]).unwrap_err(), invalid);
+        assert_eq!(f64::from_bytes(&[0; 4]).unwrap_err(), invalid);
+        assert_eq!(Vec::<u32>::from_bytes(&[0; 6]).unwrap_err(), invalid);
+    }
+}
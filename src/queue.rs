@@ -0,0 +1,262 @@
+//! Files played one after another with no gap between them, e.g. the tracks of an album.
+//!
+//! Every queued file is one of the file player's scheduled files, and each track's region is
+//! scheduled to start on the sample the one before it ends on. Regions only cover a file's real
+//! audio (see `AudioFile::frame_count`), so encoder priming and padding don't leave gaps.
+
+use audio_file::AudioFile;
+use audio_unit::AudioUnit;
+use backend::{AudioBackend, DefaultBackend, FileRegion};
+use core_audio;
+use error::{AudioUnitError, Error};
+use std::sync::mpsc::{self, Receiver};
+use transport::Transport;
+
+pub use transport::TrackCallback;
+
+/// A track's index in the queue and how it finished, as sent by `Queue::track_channel`.
+pub type TrackFinished = (usize, Result<(), Error>);
+
+/// A file player feeding an output, playing the files queued on it back to back.
+///
+/// The file player's output format is fixed when the queue is made, and the files have to be at
+/// its sample rate.
+pub struct Queue<'a, B: AudioBackend = DefaultBackend> {
+    transport: Transport<'a, B>,
+    sample_rate: f64,
+    files: Vec<&'a AudioFile<B>>,
+    /// where each track starts and ends on the file player's timeline
+    tracks: Vec<(f64, f64)>,
+}
+
+impl<'a, B: AudioBackend> Queue<'a, B> {
+
+    /// An empty queue that plays through the default output.
+    pub fn new(sample_rate: f64, channels: u32) -> Result<Queue<'a, B>, Error> {
+        Queue::with_output(core_audio::kAudioUnitSubType_DefaultOutput, sample_rate, channels)
+    }
+
+    /// An empty queue that plays through the kAudioUnitType_Output unit with the given sub type.
+    /// The portable backend's null output stops once it has played everything queued on it, so
+    /// with that everything has to be queued before playing.
+    pub fn with_output(output_sub_type: u32, sample_rate: f64, channels: u32) -> Result<Queue<'a, B>, Error> {
+        Ok(Queue {
+            transport: try!(Transport::new(output_sub_type, sample_rate, channels)),
+            sample_rate,
+            files: Vec::new(),
+            tracks: Vec::new(),
+        })
+    }
+
+    /// Queues `file` to play straight after the last track, returning its index. While playing,
+    /// a track queued after the last one has already finished starts straight away.
+    pub fn push(&mut self, file: &'a AudioFile<B>) -> Result<usize, Error> {
+        let file_format = try!(file.data_format());
+        if file_format.mSampleRate != self.sample_rate {
+            // there's nothing to convert the sample rate with
+            return Err(Error::AudioUnit(AudioUnitError::FormatNotSupported));
+        }
+        let frame_count = try!(file.frame_count());
+        if frame_count > u32::MAX as u64 {
+            return Err(Error::AudioUnit(AudioUnitError::InvalidPropertyValue));
+        }
+
+        let mut start = self.tracks.last().map_or(0.0, |&(_, end)| end);
+        if self.is_playing() {
+            start = start.max(try!(self.transport.play_time()));
+        }
+        let region = FileRegion {
+            sample_time: start,
+            start_frame: 0,
+            frames_to_play: frame_count as u32,
+            loop_count: 0,
+        };
+
+        let track = self.files.len();
+        let completion = try!(self.transport.completion(track));
+
+        self.files.push(file);
+        let scheduled = self.transport.set_scheduled_file_ids(&self.files)
+            .and_then(|_| self.transport.set_scheduled_file_region(file, &region, Some(completion)));
+        if let Err(err) = scheduled {
+            self.files.pop();
+            return Err(err);
+        }
+        self.tracks.push((start, start + frame_count as f64));
+        Ok(track)
+    }
+
+    /// Starts playing the queue. Does nothing if it's already playing.
+    pub fn play(&mut self) -> Result<(), Error> {
+        self.transport.start()
+    }
+
+    /// Stops playing and empties the queue.
+    pub fn stop(&mut self) -> Result<(), Error> {
+        try!(self.transport.halt());
+        self.files.clear();
+        self.tracks.clear();
+        Ok(())
+    }
+
+    /// Whether the graph is running, which it carries on doing after the last track has finished
+    /// so that more can be queued.
+    pub fn is_playing(&self) -> bool {
+        self.transport.is_playing()
+    }
+
+    /// How many tracks have been queued.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// The index of the track playing now, if any is.
+    pub fn current_track(&self) -> Result<Option<usize>, Error> {
+        if !self.is_playing() {
+            return Ok(None);
+        }
+        // until the first render cycle the play time is -1, but the first track is as good as
+        // playing
+        let play_time = try!(self.transport.play_time()).max(0.0);
+        Ok(self.tracks.iter().position(|&(start, end)| start <= play_time && play_time < end))
+    }
+
+    /// Calls `callback` with a track's index each time one finishes playing. It's called on the
+    /// audio system's thread, so it mustn't block or use the queue.
+    pub fn on_track_finished<F>(&mut self, callback: F) -> Result<(), Error>
+        where F: FnMut(usize, Result<(), Error>) + Send + 'static {
+        self.transport.on_finished(Box::new(callback))
+    }
+
+    /// A channel that receives a track's index each time one finishes playing.
+    pub fn track_channel(&mut self) -> Result<Receiver<TrackFinished>, Error> {
+        let (sender, receiver) = mpsc::channel();
+        try!(self.on_track_finished(move |track, result| {
+            // nobody listening any more is fine
+            let _ = sender.send((track, result));
+        }));
+        Ok(receiver)
+    }
+
+    /// Blocks until every track queued so far has finished playing, returning straight away if
+    /// the queue isn't playing.
+    pub fn wait(&self) -> Result<(), Error> {
+        let end_time = self.tracks.last().map_or(0.0, |&(_, end)| end);
+        self.transport.wait(self.files.len(), Some(end_time))
+    }
+
+    /// The file player unit.
+    pub fn file_player<'q>(&'q self) -> AudioUnit<'q, B> {
+        self.transport.file_player()
+    }
+
+    /// The output unit.
+    pub fn output<'q>(&'q self) -> AudioUnit<'q, B> {
+        self.transport.output()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::Queue;
+    use audio_file::AudioFile;
+    use backend::PortableBackend;
+    use backend::portable::NULL_OUTPUT_SUB_TYPE;
+    use backend::portable::test_files;
+
+    /// a mono 16 bit 44.1kHz WAVE file holding `samples`
+    fn wav(name: &str, samples: &[i16]) -> AudioFile<PortableBackend> {
+        test_files::open(name, &test_files::mono_wav(44100, samples))
+    }
+
+    /// a mono big endian 16 bit 44.1kHz CAF file holding `samples`, the first `priming` and last
+    /// `remainder` of which its packet table says aren't audio
+    fn caf(name: &str, samples: &[i16], priming: u32, remainder: u32) -> AudioFile<PortableBackend> {
+        test_files::open(name, &test_files::caf(44100.0, samples, priming, remainder))
+    }
+
+    fn values(samples: &[i16]) -> Vec<f32> {
+        samples.iter().map(|&sample| sample as f32 / 32768.0).collect()
+    }
+
+    #[test]
+    fn plays_tracks_back_to_back() {
+        let first: Vec<i16> = (1..701).collect();
+        let second: Vec<i16> = (1..301).map(|n| -n).collect();
+        let first_file = wav("first.wav", &first);
+        let second_file = wav("second.wav", &second);
+
+        let mut queue = Queue::with_output(NULL_OUTPUT_SUB_TYPE, 44100.0, 1).unwrap();
+        let receiver = queue.track_channel().unwrap();
+        assert_eq!(queue.push(&first_file).unwrap(), 0);
+        assert_eq!(queue.push(&second_file).unwrap(), 1);
+        assert_eq!(queue.len(), 2);
+        queue.play().unwrap();
+        queue.wait().unwrap();
+
+        let rendered = queue.output().rendered_samples().unwrap();
+        let mut expected = values(&first);
+        expected.extend(values(&second));
+        assert_eq!(&rendered[..1000], &expected[..]);
+        assert!(rendered[1000..].iter().all(|&sample| sample == 0.0));
+        assert_eq!(receiver.recv().unwrap().0, 0);
+        assert_eq!(receiver.recv().unwrap().0, 1);
+    }
+
+    #[test]
+    fn trims_priming_and_remainder_frames() {
+        let samples: Vec<i16> = (1..101).collect();
+        let file = caf("primed.caf", &samples, 10, 5);
+        assert_eq!(file.frame_count().unwrap(), 85);
+        let tail = wav("tail.wav", &[-1, -2]);
+
+        let mut queue = Queue::with_output(NULL_OUTPUT_SUB_TYPE, 44100.0, 1).unwrap();
+        queue.push(&file).unwrap();
+        queue.push(&tail).unwrap();
+        queue.play().unwrap();
+        queue.wait().unwrap();
+
+        let rendered = queue.output().rendered_samples().unwrap();
+        let mut expected = values(&samples[10..95]);
+        expected.extend(values(&[-1, -2]));
+        assert_eq!(&rendered[..87], &expected[..]);
+    }
+
+    #[test]
+    fn takes_tracks_while_playing() {
+        let first_file = wav("playing-first.wav", &[100; 4410]);
+        let second_file = wav("playing-second.wav", &[200; 100]);
+
+        // the default output plays at the real time rate, and the second track plays after the
+        // first whether or not the first is still playing when it's queued
+        let mut queue = Queue::new(44100.0, 1).unwrap();
+        let finished = queue.track_channel().unwrap();
+        queue.push(&first_file).unwrap();
+        queue.play().unwrap();
+        queue.push(&second_file).unwrap();
+        queue.wait().unwrap();
+        assert_eq!(queue.current_track().unwrap(), None);
+        let order: Vec<usize> = (0..2).map(|_| {
+            let (track, result) = finished.recv().unwrap();
+            result.unwrap();
+            track
+        }).collect();
+        assert_eq!(order, vec![0, 1]);
+
+        queue.stop().unwrap();
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn rejects_files_at_another_sample_rate() {
+        let file = wav("rate.wav", &[0; 10]);
+        let mut queue = Queue::<PortableBackend>::with_output(NULL_OUTPUT_SUB_TYPE, 48000.0, 1).unwrap();
+        assert!(queue.push(&file).is_err());
+        assert!(queue.is_empty());
+    }
+}
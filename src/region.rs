@@ -0,0 +1,186 @@
+//! Which part of a file to play, how many times, and when to start.
+
+use audio_file::{duration_to_frames, AudioFile};
+use backend::{AudioBackend, FileRegion};
+use error::{AudioFileError, AudioUnitError, Error};
+use std::time::Duration;
+
+/// A place in a file, or a length of it, either in frames or as a time at the file's sample rate.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Span {
+    Frames(u64),
+    Time(Duration),
+}
+
+impl Span {
+
+    fn frames(&self, sample_rate: f64) -> u64 {
+        match *self {
+            Span::Frames(frames) => frames,
+            Span::Time(duration) => duration_to_frames(duration, sample_rate),
+        }
+    }
+}
+
+/// Builds the `FileRegion` to schedule on a file player. By default it's the whole file, played
+/// once, starting as soon as the player does:
+///
+/// ```
+/// use playfile::Region;
+/// use std::time::Duration;
+///
+/// // a ten second excerpt from a minute in
+/// let preview = Region::new().start(Duration::from_secs(60)).length(Duration::from_secs(10));
+/// // a bar at 120bpm and 44.1kHz, over and over
+/// let bar = Region::new().start_frame(88200).frames(88200).loop_forever();
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Region {
+    start: Span,
+    length: Option<Span>,
+    loop_count: u32,
+    at: Span,
+}
+
+impl Default for Region {
+    fn default() -> Region {
+        Region::new()
+    }
+}
+
+impl Region {
+
+    /// The whole file, played once as soon as the player starts.
+    pub fn new() -> Region {
+        Region {
+            start: Span::Frames(0),
+            length: None,
+            loop_count: 0,
+            at: Span::Frames(0),
+        }
+    }
+
+    /// Starts `offset` into the file.
+    pub fn start(mut self, offset: Duration) -> Region {
+        self.start = Span::Time(offset);
+        self
+    }
+
+    /// Starts at frame `frame` of the file.
+    pub fn start_frame(mut self, frame: u64) -> Region {
+        self.start = Span::Frames(frame);
+        self
+    }
+
+    /// Plays for `length`, rather than to the end of the file.
+    pub fn length(mut self, length: Duration) -> Region {
+        self.length = Some(Span::Time(length));
+        self
+    }
+
+    /// Plays `frames` frames, rather than to the end of the file.
+    pub fn frames(mut self, frames: u64) -> Region {
+        self.length = Some(Span::Frames(frames));
+        self
+    }
+
+    /// Plays the region `loop_count` more times once it has played through, so 1 plays it twice.
+    pub fn loop_count(mut self, loop_count: u32) -> Region {
+        self.loop_count = loop_count;
+        self
+    }
+
+    /// Plays the region over and over until the player is stopped.
+    pub fn loop_forever(mut self) -> Region {
+        self.loop_count = u32::MAX;
+        self
+    }
+
+    /// Waits `delay` after the player starts before playing the region.
+    pub fn at(mut self, delay: Duration) -> Region {
+        self.at = Span::Time(delay);
+        self
+    }
+
+    /// Waits `frames` frames after the player starts before playing the region.
+    pub fn at_frame(mut self, frames: u64) -> Region {
+        self.at = Span::Frames(frames);
+        self
+    }
+
+    /// Whether the region loops until it's stopped.
+    pub fn loops_forever(&self) -> bool {
+        self.loop_count == u32::MAX
+    }
+
+    /// The region of `file` to schedule. A start past the end of the file or a length running
+    /// off it are cut short at the end of the file.
+    pub fn file_region<B: AudioBackend>(&self, file: &AudioFile<B>) -> Result<FileRegion, Error> {
+        let file_format = try!(file.data_format());
+        let sample_rate = file_format.mSampleRate;
+        if sample_rate.is_nan() || sample_rate <= 0.0 {
+            return Err(Error::AudioFile(AudioFileError::InvalidFile));
+        }
+        let frame_count = try!(file.frame_count());
+        let start = self.start.frames(sample_rate).min(frame_count);
+        let rest = frame_count - start;
+        let length = self.length.map_or(rest, |length| length.frames(sample_rate).min(rest));
+        if length > u32::MAX as u64 {
+            // mFramesToPlay is only 32 bits
+            return Err(Error::AudioUnit(AudioUnitError::InvalidPropertyValue));
+        }
+        Ok(FileRegion {
+            sample_time: self.at.frames(sample_rate) as f64,
+            start_frame: start as i64,
+            frames_to_play: length as u32,
+            loop_count: self.loop_count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::Region;
+    use audio_file::AudioFile;
+    use backend::{FileRegion, PortableBackend};
+    use backend::portable::test_files;
+    use std::time::Duration;
+
+    /// a silent mono 16 bit 1kHz WAVE file of `frames` frames
+    fn open(name: &str, frames: u32) -> AudioFile<PortableBackend> {
+        test_files::open(name, &test_files::wav(1000, 1, None, frames, |_, _| 0))
+    }
+
+    #[test]
+    fn defaults_to_the_whole_file_once() {
+        let file = open("whole.wav", 5000);
+        assert_eq!(Region::new().file_region(&file).unwrap(),
+                   FileRegion { sample_time: 0.0, start_frame: 0, frames_to_play: 5000, loop_count: 0 });
+    }
+
+    #[test]
+    fn resolves_times_against_the_file_sample_rate() {
+        let file = open("times.wav", 5000);
+        let region = Region::new().start(Duration::from_millis(1500))
+                                  .length(Duration::from_secs(2))
+                                  .loop_count(3)
+                                  .at(Duration::from_millis(250));
+        assert_eq!(region.file_region(&file).unwrap(),
+                   FileRegion { sample_time: 250.0, start_frame: 1500, frames_to_play: 2000, loop_count: 3 });
+
+        let region = Region::new().start_frame(100).frames(10).loop_forever().at_frame(7);
+        assert!(region.loops_forever());
+        assert_eq!(region.file_region(&file).unwrap(),
+                   FileRegion { sample_time: 7.0, start_frame: 100, frames_to_play: 10, loop_count: u32::MAX });
+    }
+
+    #[test]
+    fn stops_at_the_end_of_the_file() {
+        let file = open("end.wav", 5000);
+        let region = Region::new().start_frame(4000).frames(2000).file_region(&file).unwrap();
+        assert_eq!((region.start_frame, region.frames_to_play), (4000, 1000));
+        let region = Region::new().start(Duration::from_secs(10)).file_region(&file).unwrap();
+        assert_eq!((region.start_frame, region.frames_to_play), (5000, 0));
+    }
+}
@@ -0,0 +1,491 @@
+//! Band-limited sample rate conversion.
+//!
+//! A `Resampler` works out each output frame from the input frames around the instant it falls
+//! on. Apart from the cheap `Quality::Linear`, which draws a straight line between the two
+//! nearest input frames, that's done by convolving with a sinc cut off below the lower of the two
+//! Nyquist frequencies, so that nothing above it aliases when going down or images when going up.
+//! The sinc is tapered by a Kaiser window, and the better the quality the more zero crossings it
+//! keeps and the sharper the cut off.
+//!
+//! Output frame `n` falls on input frame `n * from / to`, so there's no delay, but the resampler
+//! needs input from a little way past that instant before it can work the frame out. `flush` makes
+//! up the last of it with silence.
+
+use error::{AudioConverterError, AudioError, Error};
+use std::f64::consts::PI;
+
+/// How many points per zero crossing the sinc table has. Interpolating between them is good to
+/// about -100dB.
+const TABLE_RESOLUTION: usize = 512;
+
+/// How hard a `Resampler` works to keep the signal clean.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Quality {
+    /// straight lines between input frames: cheap, but it aliases and dulls the top end
+    Linear,
+    /// a short sinc, with at least 50dB of rejection and the passband up to 30% of the lower
+    /// sample rate
+    Low,
+    /// at least 70dB of rejection, and the passband up to 36%
+    Medium,
+    /// at least 90dB of rejection, and the passband up to 45%, i.e. 20kHz at 44.1kHz
+    High,
+}
+
+impl Quality {
+
+    /// The quality for a kAudioUnitProperty_RenderQuality value, from kRenderQuality_Min (0) to
+    /// kRenderQuality_Max (0x7f).
+    pub fn from_render_quality(render_quality: u32) -> Quality {
+        if render_quality < 0x20 {
+            Quality::Linear
+        }
+        else if render_quality < 0x40 {
+            Quality::Low
+        }
+        else if render_quality < 0x60 {
+            Quality::Medium
+        }
+        else {
+            Quality::High
+        }
+    }
+
+    /// The kAudioUnitProperty_RenderQuality value for the quality: kRenderQuality_Min, _Low,
+    /// _Medium or _High.
+    pub fn render_quality(&self) -> u32 {
+        match *self {
+            Quality::Linear => 0x00,
+            Quality::Low    => 0x20,
+            Quality::Medium => 0x40,
+            Quality::High   => 0x60,
+        }
+    }
+
+    /// zero crossings either side of the middle of the sinc, and the Kaiser window's beta
+    fn kernel(&self) -> Option<(usize, f64)> {
+        match *self {
+            Quality::Linear => None,
+            Quality::Low    => Some((8, 5.0)),
+            Quality::Medium => Some((16, 7.0)),
+            Quality::High   => Some((64, 9.5)),
+        }
+    }
+}
+
+/// A windowed sinc, tabulated from the middle out.
+struct Kernel {
+    /// the sinc's frequency as a fraction of the input's Nyquist frequency
+    cutoff: f64,
+    zero_crossings: usize,
+    table: Vec<f32>,
+}
+
+impl Kernel {
+
+    fn new(zero_crossings: usize, beta: f64, ratio: f64) -> Kernel {
+        // Kaiser's estimate of the transition band the window leaves, as a fraction of the sample
+        // rate; the cut off goes half of that below Nyquist so that the stop band starts there
+        let attenuation = beta / 0.1102 + 8.7;
+        let transition = (attenuation - 7.95) / (14.36 * 2.0 * zero_crossings as f64);
+        let cutoff = ratio.min(1.0) * (1.0 - transition);
+        let table = (0..zero_crossings * TABLE_RESOLUTION + 2).map(|n| {
+            let x = n as f64 / TABLE_RESOLUTION as f64;
+            let r = x / zero_crossings as f64;
+            if r >= 1.0 {
+                return 0.0;
+            }
+            let sinc = if n == 0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+            (cutoff * sinc * bessel_i0(beta * (1.0 - r * r).sqrt()) / bessel_i0(beta)) as f32
+        }).collect();
+        Kernel { cutoff, zero_crossings, table }
+    }
+
+    /// input frames either side of the output frame that contribute to it
+    fn half_width(&self) -> usize {
+        (self.zero_crossings as f64 / self.cutoff).ceil() as usize
+    }
+
+    /// the weight of an input frame `x` input frames away from the output frame
+    fn weight(&self, x: f64) -> f32 {
+        let position = (x * self.cutoff).abs() * TABLE_RESOLUTION as f64;
+        let index = position as usize;
+        if index + 1 >= self.table.len() {
+            return 0.0;
+        }
+        let fraction = (position - index as f64) as f32;
+        self.table[index] + (self.table[index + 1] - self.table[index]) * fraction
+    }
+}
+
+/// The zeroth order modified Bessel function of the first kind, which the Kaiser window is made
+/// from.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1e-12 {
+        term *= (x / (2.0 * k)) * (x / (2.0 * k));
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+/// The two rates as whole numbers in their lowest terms, scaled up together first if either has
+/// a fraction.
+fn whole_rates(from: f64, to: f64) -> (u64, u64) {
+    let scale = if from.fract() == 0.0 && to.fract() == 0.0 { 1.0 } else { 1000.0 };
+    let (from, to) = ((from * scale).round() as u64, (to * scale).round() as u64);
+    let divisor = gcd(from, to);
+    (from / divisor, to / divisor)
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Converts a stream of audio from one sample rate to another, a buffer at a time.
+pub struct Resampler {
+    channels: usize,
+    from: f64,
+    to: f64,
+    quality: Quality,
+    kernel: Option<Kernel>,
+    /// input frames either side of an output frame that it's worked out from
+    half_width: usize,
+    /// input frames per output frame, as `step / denominator`
+    step: u64,
+    denominator: u64,
+    /// buffered input, from `half_width` frames before the next output frame's
+    input: Vec<Vec<f32>>,
+    /// where the next output frame falls in `input`: frame `index`, plus `phase / denominator`
+    index: usize,
+    phase: u64,
+    /// all the input frames written, and output frames read, since the start
+    written: u64,
+    read: u64,
+}
+
+impl Resampler {
+
+    /// A resampler for `channels` channels from `from` Hz to `to` Hz.
+    pub fn new(channels: usize, from: f64, to: f64, quality: Quality) -> Result<Resampler, Error> {
+        if channels == 0 {
+            return Err(Error::Audio(AudioError::Param));
+        }
+        if !(from > 0.0 && from.is_finite()) {
+            return Err(Error::AudioConverter(AudioConverterError::InputSampleRateOutOfRange));
+        }
+        if !(to > 0.0 && to.is_finite()) {
+            return Err(Error::AudioConverter(AudioConverterError::OutputSampleRateOutOfRange));
+        }
+        let kernel = quality.kernel().map(|(zero_crossings, beta)| Kernel::new(zero_crossings, beta, to / from));
+        let half_width = kernel.as_ref().map_or(1, |kernel| kernel.half_width());
+        let (step, denominator) = whole_rates(from, to);
+        let mut resampler = Resampler {
+            channels,
+            from,
+            to,
+            quality,
+            kernel,
+            half_width,
+            step,
+            denominator,
+            input: Vec::new(),
+            index: 0,
+            phase: 0,
+            written: 0,
+            read: 0,
+        };
+        resampler.reset();
+        Ok(resampler)
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn from_rate(&self) -> f64 {
+        self.from
+    }
+
+    pub fn to_rate(&self) -> f64 {
+        self.to
+    }
+
+    pub fn quality(&self) -> Quality {
+        self.quality
+    }
+
+    /// Forgets everything written, to start on a new stream.
+    pub fn reset(&mut self) {
+        // silence before the start, for the first output frames to be worked out from
+        self.input = vec![vec![0.0; self.half_width]; self.channels];
+        self.index = self.half_width;
+        self.phase = 0;
+        self.written = 0;
+        self.read = 0;
+    }
+
+    /// How many more input frames have to be written before `frames` output frames can be read.
+    pub fn input_frames_needed(&self, frames: usize) -> usize {
+        if frames == 0 {
+            return 0;
+        }
+        let last = self.index + ((self.phase + (frames as u64 - 1) * self.step) / self.denominator) as usize;
+        (last + self.half_width + 1).saturating_sub(self.input[0].len())
+    }
+
+    /// How many output frames can be read.
+    pub fn available(&self) -> usize {
+        let end = self.input[0].len();
+        if self.index + self.half_width >= end {
+            return 0;
+        }
+        // the frames that fall before `end - half_width`
+        let room = (end - self.half_width - self.index) as u64 * self.denominator - self.phase;
+        room.div_ceil(self.step) as usize
+    }
+
+    /// Adds `input`, a `Vec` per channel, to the end of the stream.
+    pub fn write(&mut self, input: &[Vec<f32>]) -> Result<(), Error> {
+        if input.len() != self.channels || input.iter().any(|channel| channel.len() != input[0].len()) {
+            return Err(Error::Audio(AudioError::Param));
+        }
+        for (buffer, channel) in self.input.iter_mut().zip(input.iter()) {
+            buffer.extend_from_slice(channel);
+        }
+        self.written += input[0].len() as u64;
+        Ok(())
+    }
+
+    /// Reads as many output frames as are available into `output`, starting at
+    /// `output[channel][offset]`, up to the end of its shortest channel, and returns how many.
+    pub fn read(&mut self, output: &mut [Vec<f32>], offset: usize) -> usize {
+        let room = output.iter().map(|channel| channel.len().saturating_sub(offset)).min().unwrap_or(0);
+        let frames = self.available().min(room);
+        let mut weights = vec![0.0; 2 * self.half_width];
+        for frame in 0..frames {
+            let fraction = self.phase as f64 / self.denominator as f64;
+            // the input frames from `first` to `first + weights.len()` make up this output frame
+            let first = self.index + 1 - self.half_width;
+            match self.kernel {
+                Some(ref kernel) => for (n, weight) in weights.iter_mut().enumerate() {
+                    *weight = kernel.weight(fraction + (self.half_width - 1) as f64 - n as f64);
+                },
+                None => {
+                    weights[0] = 1.0 - fraction as f32;
+                    weights[1] = fraction as f32;
+                },
+            }
+            for (samples, input) in output.iter_mut().zip(self.input.iter()) {
+                let window = &input[first..first + weights.len()];
+                samples[offset + frame] = window.iter().zip(weights.iter()).map(|(&sample, &weight)| sample * weight).sum();
+            }
+            self.phase += self.step;
+            self.index += (self.phase / self.denominator) as usize;
+            self.phase %= self.denominator;
+        }
+        self.read += frames as u64;
+
+        // drop the input no output frame needs any more
+        let consumed = self.index - self.half_width;
+        if consumed > 0 {
+            for channel in self.input.iter_mut() {
+                channel.drain(..consumed);
+            }
+            self.index -= consumed;
+        }
+        frames
+    }
+
+    /// Writes `input` and reads everything that's then available.
+    pub fn process(&mut self, input: &[Vec<f32>]) -> Result<Vec<Vec<f32>>, Error> {
+        try!(self.write(input));
+        let mut output = vec![vec![0.0; self.available()]; self.channels];
+        self.read(&mut output, 0);
+        Ok(output)
+    }
+
+    /// Ends the stream, returning the output frames that were waiting on input past its end. The
+    /// output then lasts as long as the input did.
+    pub fn flush(&mut self) -> Vec<Vec<f32>> {
+        // output frames up to the one falling on or after the end of the input
+        let total = (self.written * self.denominator).div_ceil(self.step);
+        let frames = total.saturating_sub(self.read) as usize;
+        let silence = self.input_frames_needed(frames);
+        for channel in self.input.iter_mut() {
+            let len = channel.len() + silence;
+            channel.resize(len, 0.0);
+        }
+        let mut output = vec![vec![0.0; frames]; self.channels];
+        self.read(&mut output, 0);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Quality, Resampler};
+    use error::{AudioConverterError, Error};
+    use std::f64::consts::PI;
+
+    /// `seconds` of a full scale sine at `frequency` Hz
+    fn sine(frequency: f64, sample_rate: f64, seconds: f64) -> Vec<f32> {
+        (0..(sample_rate * seconds) as usize).map(|n| (2.0 * PI * frequency * n as f64 / sample_rate).sin() as f32).collect()
+    }
+
+    fn resample(input: &[f32], from: f64, to: f64, quality: Quality) -> Vec<f32> {
+        let mut resampler = Resampler::new(1, from, to, quality).unwrap();
+        let mut output = resampler.process(&[input.to_vec()]).unwrap().remove(0);
+        output.extend(resampler.flush().remove(0));
+        output
+    }
+
+    /// The level, in dB relative to full scale, of the sine at `frequency` Hz in the middle of
+    /// `samples`, by least squares so that it needn't be a whole number of cycles.
+    fn level(samples: &[f32], frequency: f64, sample_rate: f64) -> f64 {
+        let middle = &samples[samples.len() / 10..samples.len() * 9 / 10];
+        let (mut ss, mut sc, mut cc, mut ys, mut yc) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for (n, &y) in middle.iter().enumerate() {
+            let angle = 2.0 * PI * frequency * n as f64 / sample_rate;
+            let (s, c) = (angle.sin(), angle.cos());
+            ss += s * s;
+            sc += s * c;
+            cc += c * c;
+            ys += y as f64 * s;
+            yc += y as f64 * c;
+        }
+        let determinant = ss * cc - sc * sc;
+        let a = (ys * cc - yc * sc) / determinant;
+        let b = (yc * ss - ys * sc) / determinant;
+        20.0 * (a * a + b * b).sqrt().log10()
+    }
+
+    /// the qualities with a sinc, how much each rejects, and how far up its passband goes as a
+    /// fraction of the lower sample rate
+    const SINCS: [(Quality, f64, f64); 3] = [(Quality::Low, 50.0, 0.3), (Quality::Medium, 70.0, 0.36),
+                                             (Quality::High, 90.0, 0.45)];
+
+    #[test]
+    fn keeps_the_passband_flat() {
+        for &(quality, rejection, passband) in SINCS.iter() {
+            for &(from, to) in &[(44100.0, 48000.0), (48000.0, 44100.0)] {
+                let lower: f64 = if from < to { from } else { to };
+                let levels: Vec<f64> = (1..11).map(|n| {
+                    let frequency = lower * passband * n as f64 / 10.0;
+                    level(&resample(&sine(frequency, from, 0.2), from, to, quality), frequency, to)
+                }).collect();
+                // the ripple is as big as what gets through the stop band
+                let ripple = 20.0 * (1.0 + 10f64.powf(-rejection / 20.0)).log10();
+                for (n, &level) in levels.iter().enumerate() {
+                    assert!(level.abs() < ripple, "{:?} {} to {}, {}0% of the passband: {}dB", quality, from, to, n + 1, level);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_what_would_alias_going_down() {
+        // 23kHz is above 44.1kHz's Nyquist frequency, and would fold down to 21.1kHz
+        let input = sine(23000.0, 48000.0, 0.2);
+        for &(quality, rejection, _) in SINCS.iter() {
+            let output = resample(&input, 48000.0, 44100.0, quality);
+            assert!(level(&output, 21100.0, 44100.0) < -rejection, "{:?}", quality);
+            // and there's nothing else either
+            let middle = &output[output.len() / 10..output.len() * 9 / 10];
+            let power = middle.iter().map(|&sample| sample as f64 * sample as f64).sum::<f64>() / middle.len() as f64;
+            assert!(10.0 * (2.0 * power).log10() < -rejection, "{:?}", quality);
+        }
+        // which straight lines don't manage
+        assert!(level(&resample(&input, 48000.0, 44100.0, Quality::Linear), 21100.0, 44100.0) > -20.0);
+    }
+
+    #[test]
+    fn rejects_images_going_up() {
+        // 10kHz at 44.1kHz has an image at 34.1kHz, which 96kHz can carry
+        let input = sine(10000.0, 44100.0, 0.2);
+        for &(quality, rejection, _) in SINCS.iter() {
+            let output = resample(&input, 44100.0, 96000.0, quality);
+            assert!(level(&output, 10000.0, 96000.0).abs() < 0.01, "{:?}", quality);
+            assert!(level(&output, 34100.0, 96000.0) < -rejection, "{:?}", quality);
+        }
+    }
+
+    #[test]
+    fn streams_in_pieces_the_same_as_in_one_go() {
+        let input = sine(1000.0, 44100.0, 0.1);
+        for &quality in &[Quality::Linear, Quality::Medium, Quality::High] {
+            let whole = resample(&input, 44100.0, 48000.0, quality);
+            // as long as the input, to the frame that falls on or after its end
+            assert_eq!(whole.len(), (input.len() * 48000).div_ceil(44100));
+
+            let mut resampler = Resampler::new(1, 44100.0, 48000.0, quality).unwrap();
+            let mut pieces = Vec::new();
+            let mut written = 0;
+            for size in [1, 7, 100, 3, 512, 0, 1000].iter().cycle() {
+                let end = (written + size).min(input.len());
+                pieces.extend(resampler.process(&[input[written..end].to_vec()]).unwrap().remove(0));
+                written = end;
+                if written == input.len() {
+                    break;
+                }
+            }
+            pieces.extend(resampler.flush().remove(0));
+            assert_eq!(pieces, whole);
+        }
+    }
+
+    #[test]
+    fn says_how_much_input_it_needs() {
+        let input = vec![sine(1000.0, 48000.0, 0.1); 2];
+        let mut resampler = Resampler::new(2, 48000.0, 44100.0, Quality::High).unwrap();
+        let mut output = vec![vec![0.0; 256]; 2];
+        let mut written = 0;
+        for _ in 0..10 {
+            // exactly enough for a buffer, with nothing left over
+            let needed = resampler.input_frames_needed(256);
+            let piece: Vec<Vec<f32>> = input.iter().map(|channel| channel[written..written + needed].to_vec()).collect();
+            resampler.write(&piece).unwrap();
+            written += needed;
+            assert_eq!(resampler.available(), 256);
+            assert_eq!(resampler.read(&mut output, 0), 256);
+            assert_eq!(resampler.input_frames_needed(0), 0);
+        }
+        assert!(written > 2560 * 48000 / 44100);
+    }
+
+    #[test]
+    fn draws_straight_lines() {
+        let mut resampler = Resampler::new(1, 1.0, 2.0, Quality::Linear).unwrap();
+        let mut output = resampler.process(&[vec![0.0, 1.0, 0.0, -1.0]]).unwrap().remove(0);
+        output.extend(resampler.flush().remove(0));
+        assert_eq!(output, vec![0.0, 0.5, 1.0, 0.5, 0.0, -0.5, -1.0, -0.5]);
+    }
+
+    #[test]
+    fn maps_render_quality() {
+        for &quality in &[Quality::Linear, Quality::Low, Quality::Medium, Quality::High] {
+            assert_eq!(Quality::from_render_quality(quality.render_quality()), quality);
+        }
+        assert_eq!(Quality::from_render_quality(0x7f), Quality::High);
+        assert_eq!(Quality::from_render_quality(0x30), Quality::Low);
+    }
+
+    #[test]
+    fn rejects_bad_rates() {
+        match Resampler::new(1, 0.0, 48000.0, Quality::High) {
+            Err(Error::AudioConverter(AudioConverterError::InputSampleRateOutOfRange)) => (),
+            _ => panic!("a zero input rate was accepted"),
+        }
+        match Resampler::new(1, 44100.0, f64::NAN, Quality::High) {
+            Err(Error::AudioConverter(AudioConverterError::OutputSampleRateOutOfRange)) => (),
+            _ => panic!("a NaN output rate was accepted"),
+        }
+        // fractional rates are fine
+        let output = resample(&sine(100.0, 44100.0, 0.1), 44100.0, 44099.5, Quality::Medium);
+        assert_eq!(output.len(), (4410.0f64 * 44099.5 / 44100.0).ceil() as usize);
+    }
+}
@@ -0,0 +1,525 @@
+//! A checked, Rust shaped linear PCM `AudioStreamBasicDescription`.
+
+use core_audio;
+use error::{AudioFormatError, Error};
+
+/// The flags a `StreamFormat` knows what to do with. Anything else, like the fixed point
+/// fraction bits, isn't supported.
+const KNOWN_FLAGS: i32 = core_audio::kAudioFormatFlagIsFloat | core_audio::kAudioFormatFlagIsBigEndian |
+                         core_audio::kAudioFormatFlagIsSignedInteger | core_audio::kAudioFormatFlagIsPacked |
+                         core_audio::kAudioFormatFlagIsAlignedHigh | core_audio::kAudioFormatFlagIsNonInterleaved |
+                         core_audio::kAudioFormatFlagIsNonMixable;
+
+/// How each sample is represented.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    SignedInteger,
+    UnsignedInteger,
+    Float,
+}
+
+/// The byte order of samples wider than a byte.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl Endianness {
+
+    /// The byte order of the machine we're running on.
+    pub fn native() -> Endianness {
+        if cfg!(target_endian = "big") { Endianness::Big } else { Endianness::Little }
+    }
+}
+
+/// Where a sample's bits sit in the bytes that hold it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Alignment {
+    /// the sample fills its bytes, kAudioFormatFlagIsPacked
+    Packed,
+    /// in the high bits, kAudioFormatFlagIsAlignedHigh
+    High,
+    /// in the low bits, i.e. neither flag
+    Low,
+}
+
+/// A linear PCM stream format. Unlike a bare `AudioStreamBasicDescription`, it can only be made
+/// with fields that agree with each other, and converting one to a description and back gives
+/// the same format.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StreamFormat {
+    sample_rate: f64,
+    channels: u32,
+    sample_format: SampleFormat,
+    bits_per_sample: u32,
+    bytes_per_sample: u32,
+    alignment: Alignment,
+    endianness: Endianness,
+    interleaved: bool,
+    mixable: bool,
+}
+
+impl StreamFormat {
+
+    /// Native endian, interleaved samples of `bits_per_sample` bits, packed if that's a whole
+    /// number of bytes and aligned high in the bytes holding them otherwise.
+    pub fn new(sample_format: SampleFormat, bits_per_sample: u32, channels: u32,
+               sample_rate: f64) -> Result<StreamFormat, Error> {
+        let bytes_per_sample = bits_per_sample.div_ceil(8);
+        let format = StreamFormat {
+            sample_rate,
+            channels,
+            sample_format,
+            bits_per_sample,
+            bytes_per_sample,
+            alignment: if bits_per_sample.is_multiple_of(8) { Alignment::Packed } else { Alignment::High },
+            endianness: Endianness::native(),
+            interleaved: true,
+            mixable: true,
+        };
+        try!(format.check());
+        Ok(format)
+    }
+
+    /// The format audio units use between themselves: native endian, packed, non-interleaved
+    /// 32 bit float.
+    pub fn canonical(sample_rate: f64, channels: u32) -> Result<StreamFormat, Error> {
+        StreamFormat::new(SampleFormat::Float, 32, channels, sample_rate).map(|format| format.interleaved(false))
+    }
+
+    /// The format `description` describes, or `Error::InvalidStreamFormat` if its fields disagree
+    /// with each other. Formats other than linear PCM aren't supported.
+    pub fn from_description(description: &core_audio::AudioStreamBasicDescription) -> Result<StreamFormat, Error> {
+        if description.mFormatID != core_audio::kAudioFormatLinearPCM {
+            return Err(Error::AudioFormat(AudioFormatError::UnsupportedDataFormat));
+        }
+        let flags = description.mFormatFlags as i32;
+        let flag = |mask: i32| flags & mask != 0;
+        if flags & !KNOWN_FLAGS != 0 ||
+           flag(core_audio::kAudioFormatFlagIsFloat) && flag(core_audio::kAudioFormatFlagIsSignedInteger) ||
+           flag(core_audio::kAudioFormatFlagIsPacked) && flag(core_audio::kAudioFormatFlagIsAlignedHigh) ||
+           description.mFramesPerPacket != 1 ||
+           description.mBytesPerPacket != description.mBytesPerFrame ||
+           description.mChannelsPerFrame == 0 ||
+           description.mReserved != 0 {
+            return Err(Error::InvalidStreamFormat);
+        }
+
+        let interleaved = !flag(core_audio::kAudioFormatFlagIsNonInterleaved);
+        let interleaved_channels = if interleaved { description.mChannelsPerFrame } else { 1 };
+        if !description.mBytesPerFrame.is_multiple_of(interleaved_channels) {
+            return Err(Error::InvalidStreamFormat);
+        }
+
+        let format = StreamFormat {
+            sample_rate: description.mSampleRate,
+            channels: description.mChannelsPerFrame,
+            sample_format: if flag(core_audio::kAudioFormatFlagIsFloat) {
+                SampleFormat::Float
+            }
+            else if flag(core_audio::kAudioFormatFlagIsSignedInteger) {
+                SampleFormat::SignedInteger
+            }
+            else {
+                SampleFormat::UnsignedInteger
+            },
+            bits_per_sample: description.mBitsPerChannel,
+            bytes_per_sample: description.mBytesPerFrame / interleaved_channels,
+            alignment: if flag(core_audio::kAudioFormatFlagIsPacked) {
+                Alignment::Packed
+            }
+            else if flag(core_audio::kAudioFormatFlagIsAlignedHigh) {
+                Alignment::High
+            }
+            else {
+                Alignment::Low
+            },
+            endianness: if flag(core_audio::kAudioFormatFlagIsBigEndian) { Endianness::Big } else { Endianness::Little },
+            interleaved,
+            mixable: !flag(core_audio::kAudioFormatFlagIsNonMixable),
+        };
+        try!(format.check());
+        Ok(format)
+    }
+
+    /// The `AudioStreamBasicDescription` to hand to Core Audio.
+    pub fn to_description(&self) -> core_audio::AudioStreamBasicDescription {
+        let mut flags = 0;
+        match self.sample_format {
+            SampleFormat::SignedInteger   => flags |= core_audio::kAudioFormatFlagIsSignedInteger,
+            SampleFormat::UnsignedInteger => (),
+            SampleFormat::Float           => flags |= core_audio::kAudioFormatFlagIsFloat,
+        }
+        match self.alignment {
+            Alignment::Packed => flags |= core_audio::kAudioFormatFlagIsPacked,
+            Alignment::High   => flags |= core_audio::kAudioFormatFlagIsAlignedHigh,
+            Alignment::Low    => (),
+        }
+        if self.endianness == Endianness::Big {
+            flags |= core_audio::kAudioFormatFlagIsBigEndian;
+        }
+        if !self.interleaved {
+            flags |= core_audio::kAudioFormatFlagIsNonInterleaved;
+        }
+        if !self.mixable {
+            flags |= core_audio::kAudioFormatFlagIsNonMixable;
+        }
+        core_audio::AudioStreamBasicDescription {
+            mSampleRate: self.sample_rate,
+            mFormatID: core_audio::kAudioFormatLinearPCM,
+            mFormatFlags: flags as u32,
+            mBytesPerPacket: self.bytes_per_frame(),
+            mFramesPerPacket: 1,
+            mBytesPerFrame: self.bytes_per_frame(),
+            mChannelsPerFrame: self.channels,
+            mBitsPerChannel: self.bits_per_sample,
+            mReserved: 0,
+        }
+    }
+
+    /// The same format with `channels` channels.
+    pub fn with_channels(mut self, channels: u32) -> Result<StreamFormat, Error> {
+        self.channels = channels;
+        try!(self.check());
+        Ok(self)
+    }
+
+    /// The same format at `sample_rate`.
+    pub fn with_sample_rate(mut self, sample_rate: f64) -> Result<StreamFormat, Error> {
+        self.sample_rate = sample_rate;
+        try!(self.check());
+        Ok(self)
+    }
+
+    /// The same format with each sample in `bytes_per_sample` bytes, aligned as given.
+    pub fn with_container(mut self, bytes_per_sample: u32, alignment: Alignment) -> Result<StreamFormat, Error> {
+        self.bytes_per_sample = bytes_per_sample;
+        self.alignment = alignment;
+        try!(self.check());
+        Ok(self)
+    }
+
+    /// The same format with its samples interleaved into frames, or in a buffer per channel.
+    pub fn interleaved(mut self, interleaved: bool) -> StreamFormat {
+        self.interleaved = interleaved;
+        self
+    }
+
+    /// The same format in the given byte order.
+    pub fn with_endianness(mut self, endianness: Endianness) -> StreamFormat {
+        self.endianness = endianness;
+        self
+    }
+
+    /// The same format, with or without kAudioFormatFlagIsNonMixable.
+    pub fn mixable(mut self, mixable: bool) -> StreamFormat {
+        self.mixable = mixable;
+        self
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u32 {
+        self.channels
+    }
+
+    pub fn sample_format(&self) -> SampleFormat {
+        self.sample_format
+    }
+
+    /// How many of the bits holding a sample are significant.
+    pub fn bits_per_sample(&self) -> u32 {
+        self.bits_per_sample
+    }
+
+    /// How many bytes hold a sample.
+    pub fn bytes_per_sample(&self) -> u32 {
+        self.bytes_per_sample
+    }
+
+    pub fn alignment(&self) -> Alignment {
+        self.alignment
+    }
+
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    pub fn is_interleaved(&self) -> bool {
+        self.interleaved
+    }
+
+    pub fn is_mixable(&self) -> bool {
+        self.mixable
+    }
+
+    /// How many channels share a buffer: all of them when interleaved, otherwise one.
+    pub fn interleaved_channels(&self) -> u32 {
+        if self.interleaved { self.channels } else { 1 }
+    }
+
+    /// How many buffers the samples are spread over: one when interleaved, otherwise one per
+    /// channel.
+    pub fn buffers(&self) -> u32 {
+        self.channels / self.interleaved_channels()
+    }
+
+    /// The bytes a frame takes up in each buffer.
+    pub fn bytes_per_frame(&self) -> u32 {
+        self.bytes_per_sample * self.interleaved_channels()
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        let container_bits = self.bytes_per_sample * 8;
+        let consistent = self.sample_rate > 0.0 && self.sample_rate.is_finite() &&
+            self.channels > 0 &&
+            self.bytes_per_sample > 0 &&
+            self.bits_per_sample > 0 &&
+            self.bits_per_sample <= container_bits &&
+            // mBytesPerFrame is only 32 bits
+            self.bytes_per_sample.checked_mul(self.interleaved_channels()).is_some() &&
+            (self.alignment != Alignment::Packed || self.bits_per_sample == container_bits) &&
+            (self.sample_format != SampleFormat::Float ||
+             (self.bits_per_sample == 32 || self.bits_per_sample == 64) && self.bits_per_sample == container_bits);
+        if consistent { Ok(()) } else { Err(Error::InvalidStreamFormat) }
+    }
+}
+
+/// Prints the format in Core Audio's shorthand, which `from_str` reads back: byte order, sample
+/// format and bits, then the sample rate and channel count, e.g. `LEI16@44100,2`. Samples that
+/// don't fill their bytes give the bytes and the alignment after the bits, as in `LEI24/4H` for
+/// 24 bits in the high bytes of 4. `,D` on the end means deinterleaved and `,N` non-mixable.
+impl ::std::fmt::Display for StreamFormat {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        let endianness = match self.endianness {
+            Endianness::Little => "LE",
+            Endianness::Big    => "BE",
+        };
+        let sample_format = match self.sample_format {
+            SampleFormat::SignedInteger   => "I",
+            SampleFormat::UnsignedInteger => "UI",
+            SampleFormat::Float           => "F",
+        };
+        try!(write!(f, "{}{}{}", endianness, sample_format, self.bits_per_sample));
+        match self.alignment {
+            Alignment::Packed => (),
+            Alignment::High   => try!(write!(f, "/{}H", self.bytes_per_sample)),
+            Alignment::Low    => try!(write!(f, "/{}L", self.bytes_per_sample)),
+        }
+        try!(write!(f, "@{},{}", self.sample_rate, self.channels));
+        if !self.interleaved {
+            try!(write!(f, ",D"));
+        }
+        if !self.mixable {
+            try!(write!(f, ",N"));
+        }
+        Ok(())
+    }
+}
+
+/// Reads the shorthand `Display` prints. The byte order can be left off for native endian.
+impl ::std::str::FromStr for StreamFormat {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<StreamFormat, Error> {
+        let mut fields = text.split(',');
+        let mut sample = fields.next().unwrap_or("").splitn(2, '@');
+        let mut sample_type = sample.next().unwrap_or("");
+        let sample_rate = try!(sample.next().and_then(|rate| rate.parse::<f64>().ok()).ok_or(Error::InvalidStreamFormat));
+
+        let mut endianness = Endianness::native();
+        if let Some(rest) = sample_type.strip_prefix("LE") {
+            endianness = Endianness::Little;
+            sample_type = rest;
+        }
+        else if let Some(rest) = sample_type.strip_prefix("BE") {
+            endianness = Endianness::Big;
+            sample_type = rest;
+        }
+
+        let (sample_format, size) = if let Some(size) = sample_type.strip_prefix("UI") {
+            (SampleFormat::UnsignedInteger, size)
+        }
+        else if let Some(size) = sample_type.strip_prefix('I') {
+            (SampleFormat::SignedInteger, size)
+        }
+        else if let Some(size) = sample_type.strip_prefix('F') {
+            (SampleFormat::Float, size)
+        }
+        else {
+            return Err(Error::InvalidStreamFormat);
+        };
+
+        let mut size = size.splitn(2, '/');
+        let bits = try!(size.next().unwrap_or("").parse::<u32>().map_err(|_| Error::InvalidStreamFormat));
+        let (bytes, alignment) = match size.next() {
+            None => (bits / 8, Alignment::Packed),
+            Some(container) => {
+                let alignment = if container.ends_with('H') {
+                    Alignment::High
+                }
+                else if container.ends_with('L') {
+                    Alignment::Low
+                }
+                else {
+                    return Err(Error::InvalidStreamFormat);
+                };
+                let bytes = &container[..container.len() - 1];
+                (try!(bytes.parse::<u32>().map_err(|_| Error::InvalidStreamFormat)), alignment)
+            },
+        };
+
+        let channels = try!(fields.next().and_then(|channels| channels.parse::<u32>().ok()).ok_or(Error::InvalidStreamFormat));
+
+        let mut format = try!(StreamFormat::new(sample_format, bits, channels, sample_rate));
+        format = try!(format.with_container(bytes, alignment)).with_endianness(endianness);
+        let (mut interleaved, mut mixable) = (true, true);
+        for field in fields {
+            match field {
+                "D" if interleaved => interleaved = false,
+                "N" if mixable     => mixable = false,
+                _ => return Err(Error::InvalidStreamFormat),
+            }
+        }
+        Ok(format.interleaved(interleaved).mixable(mixable))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Alignment, Endianness, SampleFormat, StreamFormat};
+    use core_audio;
+    use std::str::FromStr;
+    use error::Error;
+
+    fn description(flags: i32, bytes_per_frame: u32, channels: u32, bits: u32) -> core_audio::AudioStreamBasicDescription {
+        core_audio::AudioStreamBasicDescription {
+            mSampleRate: 44100.0,
+            mFormatID: core_audio::kAudioFormatLinearPCM,
+            mFormatFlags: flags as u32,
+            mBytesPerPacket: bytes_per_frame,
+            mFramesPerPacket: 1,
+            mBytesPerFrame: bytes_per_frame,
+            mChannelsPerFrame: channels,
+            mBitsPerChannel: bits,
+            mReserved: 0,
+        }
+    }
+
+    /// the fields of `description`, which bindgen's version can't compare itself
+    fn fields(description: &core_audio::AudioStreamBasicDescription) -> (f64, u32, u32, u32, u32, u32, u32, u32, u32) {
+        (description.mSampleRate, description.mFormatID, description.mFormatFlags, description.mBytesPerPacket,
+         description.mFramesPerPacket, description.mBytesPerFrame, description.mChannelsPerFrame,
+         description.mBitsPerChannel, description.mReserved)
+    }
+
+    #[test]
+    fn round_trips_descriptions() {
+        let descriptions = [
+            description(core_audio::kAudioFormatFlagIsFloat | core_audio::kAudioFormatFlagIsPacked |
+                        core_audio::kAudioFormatFlagIsNonInterleaved, 4, 2, 32),
+            description(core_audio::kAudioFormatFlagIsSignedInteger | core_audio::kAudioFormatFlagIsBigEndian |
+                        core_audio::kAudioFormatFlagIsPacked, 4, 2, 16),
+            description(core_audio::kAudioFormatFlagIsSignedInteger | core_audio::kAudioFormatFlagIsAlignedHigh, 8, 2, 24),
+            description(core_audio::kAudioFormatFlagIsPacked | core_audio::kAudioFormatFlagIsNonMixable, 1, 1, 8),
+            description(core_audio::kAudioFormatFlagIsSignedInteger, 6, 2, 24),
+        ];
+        for description in descriptions.iter() {
+            let format = StreamFormat::from_description(description).unwrap();
+            assert_eq!(fields(&format.to_description()), fields(description));
+            assert_eq!(StreamFormat::from_description(&format.to_description()).unwrap(), format);
+        }
+
+        let format = StreamFormat::from_description(&descriptions[2]).unwrap();
+        assert_eq!(format.sample_format(), SampleFormat::SignedInteger);
+        assert_eq!((format.bits_per_sample(), format.bytes_per_sample()), (24, 4));
+        assert_eq!(format.alignment(), Alignment::High);
+        assert_eq!(format.endianness(), Endianness::Little);
+    }
+
+    #[test]
+    fn rejects_inconsistent_descriptions() {
+        let invalid = |description: core_audio::AudioStreamBasicDescription| {
+            match StreamFormat::from_description(&description) {
+                Err(Error::InvalidStreamFormat) => (),
+                other => panic!("{:?} gave {:?}", description, other),
+            }
+        };
+        let signed_packed = core_audio::kAudioFormatFlagIsSignedInteger | core_audio::kAudioFormatFlagIsPacked;
+        // 3 bytes per frame can't be split between 2 channels
+        invalid(description(signed_packed, 3, 2, 16));
+        // packed 16 bit samples in 4 bytes
+        invalid(description(signed_packed, 8, 2, 16));
+        // more bits than bytes
+        invalid(description(signed_packed, 2, 2, 16));
+        invalid(description(core_audio::kAudioFormatFlagIsFloat, 3, 1, 24));
+        invalid(description(core_audio::kAudioFormatFlagIsFloat | core_audio::kAudioFormatFlagIsSignedInteger, 4, 1, 32));
+        invalid(description(signed_packed, 4, 0, 16));
+        let mut mismatched = description(signed_packed, 4, 2, 16);
+        mismatched.mBytesPerPacket = 8;
+        invalid(mismatched);
+        let mut rateless = description(signed_packed, 4, 2, 16);
+        rateless.mSampleRate = 0.0;
+        invalid(rateless);
+
+        let mut compressed = description(0, 0, 2, 0);
+        compressed.mFormatID = 0x61616320; // 'aac '
+        assert!(StreamFormat::from_description(&compressed).is_err());
+    }
+
+    #[test]
+    fn changing_channels_keeps_the_layout() {
+        let interleaved = StreamFormat::new(SampleFormat::SignedInteger, 16, 2, 48000.0).unwrap();
+        assert_eq!(interleaved.alignment(), Alignment::Packed);
+        assert_eq!(interleaved.endianness(), Endianness::native());
+        let surround = interleaved.with_channels(6).unwrap();
+        assert_eq!((surround.bytes_per_frame(), surround.buffers()), (12, 1));
+
+        let canonical = StreamFormat::canonical(48000.0, 2).unwrap().with_channels(6).unwrap();
+        assert_eq!((canonical.bytes_per_frame(), canonical.buffers()), (4, 6));
+        assert!(!canonical.is_interleaved());
+
+        assert!(interleaved.with_channels(0).is_err());
+        assert!(interleaved.with_sample_rate(-1.0).is_err());
+        assert_eq!(StreamFormat::new(SampleFormat::SignedInteger, 20, 1, 48000.0).unwrap().alignment(), Alignment::High);
+        assert!(interleaved.with_container(1, Alignment::Low).is_err());
+    }
+
+    #[test]
+    fn prints_and_parses_shorthand() {
+        let format: StreamFormat = "LEI16@44100,2".parse().unwrap();
+        assert_eq!(format.sample_format(), SampleFormat::SignedInteger);
+        assert_eq!(format.endianness(), Endianness::Little);
+        assert_eq!((format.bits_per_sample(), format.sample_rate(), format.channels()), (16, 44100.0, 2));
+        assert_eq!(format.to_string(), "LEI16@44100,2");
+
+        let format = StreamFormat::from_str("BEF32@48000,6,D").unwrap();
+        assert_eq!((format.sample_format(), format.endianness()), (SampleFormat::Float, Endianness::Big));
+        assert!(!format.is_interleaved());
+        assert_eq!(format.to_string(), "BEF32@48000,6,D");
+
+        let native = StreamFormat::from_str("UI8@8000,1").unwrap();
+        assert_eq!(native.endianness(), Endianness::native());
+
+        for text in ["LEI24/4H@96000,2", "BEI24/3L@44100.5,1,N", "LEF64@192000,8,D,N", "BEUI8@22050,1"].iter() {
+            let format = StreamFormat::from_str(text).unwrap();
+            assert_eq!(format.to_string(), *text);
+            assert_eq!(StreamFormat::from_str(&format.to_string()).unwrap(), format);
+        }
+        assert_eq!(StreamFormat::canonical(44100.0, 2).unwrap().to_string(),
+                   if cfg!(target_endian = "big") { "BEF32@44100,2,D" } else { "LEF32@44100,2,D" });
+    }
+
+    #[test]
+    fn rejects_malformed_shorthand() {
+        for text in ["", "LEI16", "LEI16@44100", "LEX16@44100,2", "LEI@44100,2", "LEI16@fast,2", "LEI16@44100,two",
+                     "LEI16@44100,2,D,D", "LEI16@44100,2,Q", "LEI20@44100,2", "LEI24/4@44100,2", "LEF24@44100,2",
+                     "LEI16@0,2"].iter() {
+            assert!(StreamFormat::from_str(text).is_err(), "{} parsed", text);
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! What `Player` and `Queue` have in common: a file player feeding an output in a graph of their
+//! own, started and stopped as a whole, and the completions of the regions scheduled on it.
+
+use audio_file::AudioFile;
+use audio_unit::AudioUnit;
+use backend::{AudioBackend, CompletionProc, FileRegion};
+use core_audio;
+use error::{AudioUnitError, Error};
+use graph::{Created, Graph, Initialized, Running};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+/// How long `wait` sleeps between looking at the play time.
+const POLL_INTERVAL_MS: u64 = 5;
+
+/// Called with a track's index in the queue once it has finished playing. A `Player`'s file is
+/// always track 0.
+pub type TrackCallback = Box<dyn FnMut(usize, Result<(), Error>) + Send>;
+
+/// The graph, stopped or playing.
+enum State<'a, B: AudioBackend> {
+    Stopped(Graph<'a, Initialized, B>),
+    Playing(Graph<'a, Running, B>),
+}
+
+/// How many of the regions scheduled since the last reset have finished.
+struct Finished {
+    /// counts resets, so completions of regions thrown away by one can be ignored
+    generation: u64,
+    count: usize,
+    /// the first error a region finished with
+    error: Option<Error>,
+}
+
+/// Shared between the transport and the completion procs of its regions.
+struct Completions {
+    finished: Mutex<Finished>,
+    condvar: Condvar,
+    callbacks: Mutex<Vec<TrackCallback>>,
+}
+
+impl Completions {
+
+    fn lock(&self) -> Result<MutexGuard<'_, Finished>, Error> {
+        self.finished.lock().map_err(|_| Error::Unspecified)
+    }
+
+    fn complete(&self, generation: u64, index: usize, result: Result<(), Error>) {
+        match self.finished.lock() {
+            Ok(mut finished) => {
+                if finished.generation != generation {
+                    return;
+                }
+                finished.count += 1;
+                if let Err(ref err) = result {
+                    if finished.error.is_none() {
+                        finished.error = Some(err.clone());
+                    }
+                }
+                self.condvar.notify_all();
+            },
+            Err(_) => return,
+        }
+        if let Ok(mut callbacks) = self.callbacks.lock() {
+            for callback in callbacks.iter_mut() {
+                callback(index, result.clone());
+            }
+        }
+    }
+}
+
+/// A file player feeding an output.
+pub struct Transport<'a, B: AudioBackend> {
+    state: Option<State<'a, B>>,
+    file_node: core_audio::AUNode,
+    file_player: B::Unit,
+    output: B::Unit,
+    completions: Arc<Completions>,
+}
+
+impl<'a, B: AudioBackend> Transport<'a, B> {
+
+    /// A file player putting out `channels` channels at `sample_rate` into the
+    /// kAudioUnitType_Output unit with the given sub type, initialized and stopped.
+    pub fn new(output_sub_type: u32, sample_rate: f64, channels: u32) -> Result<Transport<'a, B>, Error> {
+        let mut graph = try!(Graph::<Created, B>::new());
+
+        let output_node = try!(graph.add_node(core_audio::kAudioUnitType_Output,
+                                              output_sub_type,
+                                              core_audio::kAudioUnitManufacturer_Apple));
+
+        let file_node = try!(graph.add_node(core_audio::kAudioUnitType_Generator,
+                                            core_audio::kAudioUnitSubType_AudioFilePlayer,
+                                            core_audio::kAudioUnitManufacturer_Apple));
+
+        let mut graph = try!(graph.open());
+
+        let file_player = try!(graph.unit(file_node));
+        let output = try!(graph.unit(output_node));
+
+        try!(B::set_number_of_channels(&file_player, core_audio::kAudioUnitScope_Output, 0, channels));
+
+        try!(B::set_sample_rate(&file_player, core_audio::kAudioUnitScope_Output, 0, sample_rate));
+
+        try!(graph.connect_node_input(file_node, 0, output_node, 0));
+
+        let graph = try!(graph.initialize());
+
+        Ok(Transport {
+            state: Some(State::Stopped(graph)),
+            file_node,
+            file_player,
+            output,
+            completions: Arc::new(Completions {
+                finished: Mutex::new(Finished { generation: 0, count: 0, error: None }),
+                condvar: Condvar::new(),
+                callbacks: Mutex::new(Vec::new()),
+            }),
+        })
+    }
+
+    /// Whether the graph is running.
+    pub fn is_playing(&self) -> bool {
+        matches!(self.state, Some(State::Playing(_)))
+    }
+
+    /// Makes `files` the file player's scheduled files, see `Graph::set_scheduled_file_ids`.
+    pub fn set_scheduled_file_ids(&self, files: &[&'a AudioFile<B>]) -> Result<(), Error> {
+        match self.state {
+            Some(State::Stopped(ref graph)) =>
+                graph.set_scheduled_file_ids(self.file_node, core_audio::kAudioUnitScope_Global, 0, files),
+            Some(State::Playing(ref graph)) =>
+                graph.set_scheduled_file_ids(self.file_node, core_audio::kAudioUnitScope_Global, 0, files),
+            None => Err(Error::AudioUnit(AudioUnitError::Uninitialized)),
+        }
+    }
+
+    /// Schedules `region` of `file` on the file player, see `Graph::set_scheduled_file_region`.
+    pub fn set_scheduled_file_region(&self, file: &'a AudioFile<B>, region: &FileRegion,
+                                     completion: Option<CompletionProc>) -> Result<(), Error> {
+        match self.state {
+            Some(State::Stopped(ref graph)) => graph.set_scheduled_file_region(self.file_node, file, region, completion),
+            Some(State::Playing(ref graph)) => graph.set_scheduled_file_region(self.file_node, file, region, completion),
+            None => Err(Error::AudioUnit(AudioUnitError::Uninitialized)),
+        }
+    }
+
+    /// A completion proc counting region `index` as finished, unless the completions have been
+    /// reset by then.
+    pub fn completion(&self, index: usize) -> Result<CompletionProc, Error> {
+        let generation = try!(self.completions.lock()).generation;
+        let completions = self.completions.clone();
+        Ok(Box::new(move |result| completions.complete(generation, index, result)))
+    }
+
+    /// How many regions have finished since the completions were last reset.
+    pub fn finished(&self) -> Result<usize, Error> {
+        Ok(try!(self.completions.lock()).count)
+    }
+
+    /// Forgets the regions finished so far, and ignores the completions of any still scheduled.
+    pub fn reset_completions(&self) -> Result<(), Error> {
+        let mut finished = try!(self.completions.lock());
+        finished.generation += 1;
+        finished.count = 0;
+        finished.error = None;
+        Ok(())
+    }
+
+    /// Calls `callback` with a region's index each time one finishes. It's called on the audio
+    /// system's thread.
+    pub fn on_finished(&self, callback: TrackCallback) -> Result<(), Error> {
+        let mut callbacks = try!(self.completions.callbacks.lock().map_err(|_| Error::Unspecified));
+        callbacks.push(callback);
+        Ok(())
+    }
+
+    /// Starts the file player on the next render cycle and the graph with it. Does nothing if
+    /// it's already playing.
+    pub fn start(&mut self) -> Result<(), Error> {
+        match self.state.take() {
+            Some(State::Stopped(graph)) => {
+                try!(B::audio_unit_set_scheduled_file_prime(&self.file_player, 0));
+                try!(B::audio_unit_set_schedule_start_time_stamp(&self.file_player, -1.0));
+                self.state = Some(State::Playing(try!(graph.start())));
+                Ok(())
+            },
+            state => {
+                self.state = state;
+                Ok(())
+            },
+        }
+    }
+
+    /// Stops the graph if it's running and throws away whatever is scheduled on the file player,
+    /// along with the completions still to come.
+    pub fn halt(&mut self) -> Result<(), Error> {
+        let graph = match self.state.take() {
+            Some(State::Playing(graph)) => try!(graph.stop()),
+            Some(State::Stopped(graph)) => graph,
+            None => return Err(Error::AudioUnit(AudioUnitError::Uninitialized)),
+        };
+        self.state = Some(State::Stopped(graph));
+        try!(self.reset_completions());
+        B::audio_unit_reset(&self.file_player, core_audio::kAudioUnitScope_Global, 0)
+    }
+
+    /// The file player's kAudioUnitProperty_CurrentPlayTime, -1 until the first render cycle.
+    pub fn play_time(&self) -> Result<f64, Error> {
+        B::audio_unit_get_current_play_time(&self.file_player)
+    }
+
+    /// Blocks until `count` regions have finished, or one has failed, and then until the play
+    /// time has got to `end_time`. Returns straight away if it isn't playing.
+    pub fn wait(&self, count: usize, end_time: Option<f64>) -> Result<(), Error> {
+        if !self.is_playing() {
+            return Ok(());
+        }
+
+        {
+            let mut finished = try!(self.completions.lock());
+            while finished.count < count && finished.error.is_none() {
+                finished = try!(self.completions.condvar.wait(finished).map_err(|_| Error::Unspecified));
+            }
+            if let Some(ref err) = finished.error {
+                return Err(err.clone());
+            }
+        }
+
+        // Core Audio is done with a region once it has read all of it, which is a little before
+        // the last of it has been heard
+        if let Some(end_time) = end_time {
+            while try!(self.play_time()) < end_time {
+                thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+            }
+        }
+        Ok(())
+    }
+
+    /// The file player unit.
+    pub fn file_player<'t>(&'t self) -> AudioUnit<'t, B> {
+        AudioUnit::new(self.file_player.clone())
+    }
+
+    /// The output unit.
+    pub fn output<'t>(&'t self) -> AudioUnit<'t, B> {
+        AudioUnit::new(self.output.clone())
+    }
+}